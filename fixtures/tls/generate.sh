@@ -0,0 +1,17 @@
+#!/bin/sh
+# Generates the certificates of the TLS tests: a CA, a certificate for localhost and a client certificate,
+# both signed by the CA. The key of the CA isn't kept.
+set -e
+cd "$(dirname "$0")"
+days=3650
+openssl req -x509 -newkey rsa:2048 -nodes -keyout ca-key.pem -out ca.pem -days $days -subj "/CN=rusty-advisor test CA" \
+  -addext "basicConstraints=critical,CA:TRUE" -addext "keyUsage=critical,keyCertSign,cRLSign"
+for name in localhost client; do
+  usage=$([ $name = localhost ] && echo serverAuth || echo clientAuth)
+  openssl req -newkey rsa:2048 -nodes -keyout $name-key.pem -out $name.csr -subj "/CN=$name"
+  printf "basicConstraints=CA:FALSE\nkeyUsage=critical,digitalSignature,keyEncipherment\nextendedKeyUsage=%s\nsubjectAltName=DNS:%s\n" \
+    $usage $name > $name.ext
+  openssl x509 -req -in $name.csr -CA ca.pem -CAkey ca-key.pem -CAcreateserial -out $name.pem -days $days -extfile $name.ext
+  rm $name.csr $name.ext
+done
+rm ca-key.pem ca.srl
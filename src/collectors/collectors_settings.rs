@@ -0,0 +1,96 @@
+use std::collections::HashSet;
+
+use crate::collectors::cpufreq_collector::cpufreq_settings::CpufreqSettings;
+use crate::collectors::exec_collector::exec_settings::ExecSettings;
+use crate::collectors::hwmon_collector::hwmon_settings::HwmonSettings;
+use crate::collectors::interrupts_collector::interrupts_settings::InterruptsSettings;
+use crate::collectors::log_tail_collector::log_tail_settings::LogTailSettings;
+use crate::collectors::numa_collector::numa_settings::NumaSettings;
+use crate::collectors::probe_collector::probe_settings::{DnsProbeSettings, HttpProbeSettings, TcpProbeSettings};
+use crate::collectors::schedstat_collector::schedstat_settings::SchedstatSettings;
+use crate::collectors::sockets_collector::sockets_settings::SocketsSettings;
+use crate::collectors::textfile_collector::textfile_settings::TextfileSettings;
+use crate::collectors::timex_collector::timex_settings::TimexSettings;
+use crate::errors::{Error::Msg, Result};
+
+/// Settings of the collectors which read the kernel stats from `procfs` and `sysfs`.
+///
+/// The mount points can be changed to read the stats of the host from a container,
+/// e.g. mounting the `/proc` of the host on `/host/proc`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct CollectorsSettings {
+    pub procfs_path: String,
+    pub sysfs_path: String,
+    pub schedstat: SchedstatSettings,
+    pub sockets: SocketsSettings,
+    pub interrupts: InterruptsSettings,
+    pub numa: NumaSettings,
+    pub hwmon: HwmonSettings,
+    pub cpufreq: CpufreqSettings,
+    pub timex: TimexSettings,
+    pub textfile: TextfileSettings,
+    /// Commands run on their own schedule, from the `[[collectors.exec]]` entries.
+    pub exec: Vec<ExecSettings>,
+    /// Log files followed to update metrics from their lines, from the `[[collectors.log_tail]]` entries.
+    pub log_tail: Vec<LogTailSettings>,
+    /// HTTP targets probed from the host, from the `[[collectors.http_probe]]` entries.
+    pub http_probe: Vec<HttpProbeSettings>,
+    /// TCP targets probed from the host, from the `[[collectors.tcp_probe]]` entries.
+    pub tcp_probe: Vec<TcpProbeSettings>,
+    /// DNS queries probed from the host, from the `[[collectors.dns_probe]]` entries.
+    pub dns_probe: Vec<DnsProbeSettings>,
+}
+
+impl Default for CollectorsSettings {
+    fn default() -> Self {
+        CollectorsSettings {
+            procfs_path: "/proc".into(),
+            sysfs_path: "/sys".into(),
+            schedstat: SchedstatSettings::default(),
+            sockets: SocketsSettings::default(),
+            interrupts: InterruptsSettings::default(),
+            numa: NumaSettings::default(),
+            hwmon: HwmonSettings::default(),
+            cpufreq: CpufreqSettings::default(),
+            timex: TimexSettings::default(),
+            textfile: TextfileSettings::default(),
+            exec: vec![],
+            log_tail: vec![],
+            http_probe: vec![],
+            tcp_probe: vec![],
+            dns_probe: vec![],
+        }
+    }
+}
+
+impl CollectorsSettings {
+    /// The series of a command are tagged with its name, so two commands with the same name would share them.
+    pub fn validate(&self) -> Result<()> {
+        let mut names = HashSet::new();
+        for exec in self.exec.iter() {
+            if !names.insert(exec.name.as_str()) {
+                return Err(Msg(format!("Exec collector {} is configured more than once", exec.name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collectors::exec_collector::exec_settings::ExecOutputFormat;
+
+    use super::*;
+
+    fn exec(name: &str) -> ExecSettings {
+        ExecSettings { name: name.into(), command: "true".into(), args: vec![], interval_millis: 15_000, timeout_millis: 10_000, format: ExecOutputFormat::Prometheus }
+    }
+
+    #[test]
+    fn test_exec_names_are_unique() {
+        let mut settings = CollectorsSettings { exec: vec![exec("backup"), exec("cleanup")], ..CollectorsSettings::default() };
+        assert!(settings.validate().is_ok());
+        settings.exec.push(exec("backup"));
+        assert!(settings.validate().is_err());
+    }
+}
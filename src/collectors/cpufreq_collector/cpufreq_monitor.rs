@@ -0,0 +1,189 @@
+//! Collects the frequency scaling of every CPU from `/sys/devices/system/cpu/cpu*/cpufreq`,
+//! and the clocksource of the kernel from `/sys/devices/system/clocksource`.
+//!
+//! The governor is exported as an info series, tagged with the current governor, whatever it is.
+//! The clocksource is exported with a series per available value, which is 1 for the current one
+//! and 0 for the rest, so switching to a slow clocksource like `hpet` (a known source of timing
+//! hiccups) can be alerted on.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::fs;
+
+use crate::collectors::collectors_settings::CollectorsSettings;
+use crate::collectors::metric_recorders::{MetricRecorders, to_tag_value};
+use crate::collectors::sysfs;
+use crate::errors::Result;
+use crate::metrics::gauge::GaugeBuilder;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CpuFrequency {
+    pub cpu: String,
+    pub current_hertz: Option<f64>,
+    pub min_hertz: f64,
+    pub max_hertz: f64,
+    pub governor: String,
+    pub available_governors: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clocksource {
+    pub clocksource: String,
+    pub current: String,
+    pub available: Vec<String>,
+}
+
+pub struct CpufreqMonitor {
+    cpus_path: PathBuf,
+    clocksources_path: PathBuf,
+    interval: Duration,
+    recorders: MetricRecorders,
+    /// The governor exported for every CPU, so its series is removed when the governor changes.
+    governors: HashMap<String, String>,
+}
+
+impl CpufreqMonitor {
+    pub fn new(config: &CollectorsSettings) -> CpufreqMonitor {
+        CpufreqMonitor {
+            cpus_path: PathBuf::from(&config.sysfs_path).join("devices/system/cpu"),
+            clocksources_path: PathBuf::from(&config.sysfs_path).join("devices/system/clocksource"),
+            interval: Duration::from_millis(config.cpufreq.interval_millis),
+            recorders: MetricRecorders::new(),
+            governors: HashMap::new(),
+        }
+    }
+
+    pub async fn start(mut self) {
+        loop {
+            if let Err(error) = self.collect().await {
+                warn!("Cpufreq collector failed to collect. Reason: {}", error);
+            }
+            tokio::time::delay_for(self.interval).await;
+        }
+    }
+
+    async fn collect(&mut self) -> Result<()> {
+        for cpu in read_cpu_frequencies(&self.cpus_path).await? {
+            let frequencies = [
+                ("cpu_scaling_frequency_hertz", "Current frequency of the CPU.", cpu.current_hertz),
+                ("cpu_scaling_frequency_min_hertz", "Minimum frequency the CPU can be scaled to.", Some(cpu.min_hertz)),
+                ("cpu_scaling_frequency_max_hertz", "Maximum frequency the CPU can be scaled to.", Some(cpu.max_hertz)),
+            ];
+            for (name, description, hertz) in frequencies.iter() {
+                if let Some(hertz) = hertz {
+                    let builder = GaugeBuilder::new(name.to_string(), description.to_string())
+                        .with_tags("cpu".to_string(), cpu.cpu.clone());
+                    self.recorders.gauge(builder, *hertz).await?;
+                }
+            }
+            let governor = to_tag_value(&cpu.governor);
+            if let Some(previous) = self.governors.insert(cpu.cpu.clone(), governor.clone()).filter(|previous| *previous != governor) {
+                self.recorders.remove_gauge(governor_info(&cpu.cpu, previous)).await?;
+            }
+            self.recorders.gauge(governor_info(&cpu.cpu, governor), 1.0).await?;
+        }
+        for clocksource in read_clocksources(&self.clocksources_path).await? {
+            for available in with_current(&clocksource.available, &clocksource.current) {
+                let builder = GaugeBuilder::new("clocksource_current".into(), "Clocksource used by the kernel, 1 for the current one.".into())
+                    .with_tags("device".to_string(), clocksource.clocksource.clone())
+                    .with_tags("clocksource".to_string(), to_tag_value(&available));
+                self.recorders.gauge(builder, if available == clocksource.current { 1.0 } else { 0.0 }).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn governor_info(cpu: &str, governor: String) -> GaugeBuilder {
+    GaugeBuilder::new("cpu_scaling_governor_info".into(), "Frequency scaling governor of the CPU, always 1.".into())
+        .with_tags("cpu".to_string(), cpu.to_string())
+        .with_tags("governor".to_string(), governor)
+}
+
+/// The available values don't always include the current one, e.g. the governors of some drivers.
+fn with_current(available: &[String], current: &str) -> Vec<String> {
+    let mut values = available.to_vec();
+    if !values.iter().any(|value| value == current) {
+        values.push(current.to_string());
+    }
+    values
+}
+
+/// Only the CPUs with a `cpufreq` directory are returned, which depends on the driver and the virtualization.
+/// The frequencies are in kHz.
+pub async fn read_cpu_frequencies(cpus_path: &Path) -> Result<Vec<CpuFrequency>> {
+    let mut cpus = Vec::new();
+    for (name, path) in sysfs::list_dir(cpus_path, "cpu").await? {
+        let cpu = name.trim_start_matches("cpu");
+        let cpufreq_path = path.join("cpufreq");
+        if cpu.parse::<u32>().is_err() || fs::metadata(&cpufreq_path).await.is_err() {
+            continue;
+        }
+        let current_khz = sysfs::read_optional_value::<f64, _>(cpufreq_path.join("scaling_cur_freq")).await?;
+        let available_governors = sysfs::read_optional_value::<String, _>(cpufreq_path.join("scaling_available_governors")).await?;
+        cpus.push(CpuFrequency {
+            cpu: cpu.to_string(),
+            current_hertz: current_khz.map(|khz| khz * 1_000.0),
+            min_hertz: sysfs::read_value::<f64, _>(cpufreq_path.join("scaling_min_freq")).await? * 1_000.0,
+            max_hertz: sysfs::read_value::<f64, _>(cpufreq_path.join("scaling_max_freq")).await? * 1_000.0,
+            governor: sysfs::read_string(cpufreq_path.join("scaling_governor")).await?,
+            available_governors: split_values(available_governors),
+        });
+    }
+    Ok(cpus)
+}
+
+pub async fn read_clocksources(clocksources_path: &Path) -> Result<Vec<Clocksource>> {
+    let mut clocksources = Vec::new();
+    for (name, path) in sysfs::list_dir(clocksources_path, "clocksource").await? {
+        let available = sysfs::read_optional_value::<String, _>(path.join("available_clocksource")).await?;
+        clocksources.push(Clocksource {
+            clocksource: name.trim_start_matches("clocksource").to_string(),
+            current: sysfs::read_string(path.join("current_clocksource")).await?,
+            available: split_values(available),
+        });
+    }
+    Ok(clocksources)
+}
+
+fn split_values(values: Option<String>) -> Vec<String> {
+    values.map(|values| values.split_whitespace().map(|value| value.to_string()).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::tests::sysfs_fixture;
+
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    #[test]
+    fn test_read_cpu_frequencies() {
+        let cpus = aw!(read_cpu_frequencies(&sysfs_fixture("devices/system/cpu"))).unwrap();
+        let governors = vec!["performance".to_string(), "powersave".to_string()];
+        assert_eq!(cpus, vec![
+            CpuFrequency { cpu: "0".into(), current_hertz: Some(3_400_123_000.0), min_hertz: 800_000_000.0, max_hertz: 4_200_000_000.0,
+                governor: "performance".into(), available_governors: governors.clone() },
+            CpuFrequency { cpu: "1".into(), current_hertz: Some(1_200_000_000.0), min_hertz: 800_000_000.0, max_hertz: 4_200_000_000.0,
+                governor: "powersave".into(), available_governors: governors },
+        ]);
+    }
+
+    #[test]
+    fn test_read_clocksources() {
+        let clocksources = aw!(read_clocksources(&sysfs_fixture("devices/system/clocksource"))).unwrap();
+        assert_eq!(clocksources, vec![
+            Clocksource { clocksource: "0".into(), current: "hpet".into(), available: vec!["tsc".into(), "hpet".into(), "acpi_pm".into()] },
+        ]);
+        assert_eq!(with_current(&clocksources[0].available, "kvm-clock").last().unwrap(), "kvm-clock");
+        assert_eq!(with_current(&clocksources[0].available, "tsc").len(), 3);
+    }
+}
@@ -0,0 +1,14 @@
+#[derive(Debug, Deserialize, Clone)]
+pub struct CpufreqSettings {
+    pub enabled: bool,
+    pub interval_millis: u64,
+}
+
+impl Default for CpufreqSettings {
+    fn default() -> Self {
+        CpufreqSettings {
+            enabled: true,
+            interval_millis: 15_000,
+        }
+    }
+}
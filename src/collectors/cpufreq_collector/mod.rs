@@ -0,0 +1,2 @@
+pub mod cpufreq_monitor;
+pub mod cpufreq_settings;
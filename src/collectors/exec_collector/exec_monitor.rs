@@ -0,0 +1,238 @@
+//! Runs a command configured on `[[collectors.exec]]` every interval, and publishes the metrics it writes
+//! to its standard output, in the Prometheus text format or the InfluxDB line protocol.
+//!
+//! The command is waited for on the blocking thread pool, so a slow command doesn't hold a thread of the
+//! runtime, and it's killed along with its children when it times out. Its exit code, duration, runs and
+//! timeouts are exported tagged with its `name`, and its output is only published when it succeeds.
+
+use std::io::Read;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::collectors::exec_collector::exec_settings::{ExecOutputFormat, ExecSettings};
+use crate::collectors::metric_recorders::MetricRecorders;
+use crate::collectors::parsers::{influx_line, prometheus_text};
+use crate::errors::{Error, Result};
+use crate::metrics::counter::CounterBuilder;
+use crate::metrics::gauge::GaugeBuilder;
+use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
+
+/// How often a running command is checked for its exit or its timeout.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How long the output is read for after the command exited, when it exited at its timeout.
+const READ_GRACE: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub struct CommandOutput {
+    pub stdout: String,
+    pub status: ExitStatus,
+    pub duration: Duration,
+    pub timed_out: bool,
+}
+
+impl CommandOutput {
+    /// The exit code, or 128 plus the signal when the command was killed, like the shells do.
+    pub fn exit_code(&self) -> i32 {
+        self.status.code()
+            .or_else(|| self.status.signal().map(|signal| 128 + signal))
+            .unwrap_or(-1)
+    }
+}
+
+/// Runs the command, without standard input, until it exits or it runs for longer than `timeout`.
+///
+/// The command runs on its own process group, so the children it started are killed with it on
+/// timeout and the standard output is closed. A child which left the group, e.g. with `setsid`, can keep
+/// the standard output open after the command exited, so the output is only read until the timeout too.
+/// It blocks the thread until the command is done.
+pub fn run_command(command: &str, args: &[String], timeout: Duration) -> Result<CommandOutput> {
+    let start = Instant::now();
+    let mut child = Command::new(command)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+    // the output is read on its own thread, so a command writing more than the pipe buffer isn't blocked
+    let mut stdout = child.stdout.take().ok_or_else(|| Error::Msg("Standard output of the command not captured".into()))?;
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = String::new();
+        // the command is done when the receiver is dropped
+        let _ = sender.send(stdout.read_to_string(&mut output).map(|_| output));
+    });
+    let mut timed_out = false;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() >= timeout {
+            timed_out = true;
+            unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+            break child.wait()?;
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    let stdout = match receiver.recv_timeout(timeout.saturating_sub(start.elapsed()).max(READ_GRACE)) {
+        Ok(stdout) => stdout?,
+        Err(RecvTimeoutError::Timeout) => {
+            timed_out = true;
+            String::new()
+        },
+        Err(RecvTimeoutError::Disconnected) => return Err(Error::Msg("Reader of the standard output of the command panicked".into())),
+    };
+    let duration = start.elapsed();
+    Ok(CommandOutput { stdout, status, duration, timed_out })
+}
+
+pub struct ExecMonitor {
+    settings: ExecSettings,
+    interval: Duration,
+    timeout: Duration,
+    recorders: MetricRecorders,
+    runs: u64,
+    timeouts: u64,
+}
+
+impl ExecMonitor {
+    pub fn new(config: &ExecSettings) -> ExecMonitor {
+        ExecMonitor {
+            settings: config.clone(),
+            interval: Duration::from_millis(config.interval_millis),
+            timeout: Duration::from_millis(config.timeout_millis),
+            recorders: MetricRecorders::new(),
+            runs: 0,
+            timeouts: 0,
+        }
+    }
+
+    pub async fn start(mut self) {
+        loop {
+            if let Err(error) = self.collect().await {
+                warn!("Exec collector failed to collect {}. Reason: {}", self.settings.name, error);
+            }
+            tokio::time::delay_for(self.interval).await;
+        }
+    }
+
+    async fn collect(&mut self) -> Result<()> {
+        let (command, args, timeout) = (self.settings.command.clone(), self.settings.args.clone(), self.timeout);
+        let output = tokio::task::spawn_blocking(move || run_command(&command, &args, timeout)).await
+            .map_err(|error| Error::Msg(format!("Command {} panicked. Reason: {}", self.settings.command, error)))??;
+        self.runs += 1;
+        if output.timed_out {
+            self.timeouts += 1;
+        }
+        self.record_command(&output).await?;
+        let parse_error = if output.timed_out || !output.status.success() {
+            warn!("Command {} failed with exit code {}, its output is discarded", self.settings.name, output.exit_code());
+            0.0
+        } else {
+            match self.record_output(&output.stdout).await {
+                Ok(_) => 0.0,
+                Err(error) => {
+                    warn!("Output of the command {} couldn't be published. Reason: {}", self.settings.name, error);
+                    1.0
+                },
+            }
+        };
+        let builder = GaugeBuilder::new("exec_parse_error".into(), "Whether the output of the command failed to be parsed.".into())
+            .with_tags("command".to_string(), self.settings.name.clone());
+        self.recorders.gauge(builder, parse_error).await
+    }
+
+    async fn record_command(&mut self, output: &CommandOutput) -> Result<()> {
+        let command = self.settings.name.clone();
+        let counters = [
+            ("exec_runs_total", "Times the command has been run.", self.runs),
+            ("exec_timeouts_total", "Times the command has been killed for running longer than its timeout.", self.timeouts),
+        ];
+        for (name, description, total) in counters.iter() {
+            let builder = CounterBuilder::new(name.to_string(), description.to_string())
+                .with_tags("command".to_string(), command.clone());
+            self.recorders.counter_total(builder, *total).await?;
+        }
+        let builder = GaugeBuilder::new("exec_exit_code".into(), "Exit code of the last run of the command.".into())
+            .with_tags("command".to_string(), command.clone());
+        self.recorders.gauge(builder, output.exit_code() as f64).await?;
+        let builder = GaugeBuilder::new("exec_duration_seconds".into(), "Duration of the last run of the command.".into())
+            .with_tags("command".to_string(), command)
+            .with_measurement_unit(&MEASUREMENT_UNITS.time.seconds);
+        self.recorders.gauge(builder, output.duration.as_secs_f64()).await
+    }
+
+    /// The whole output is parsed before recording anything, so a broken output doesn't publish half of its metrics.
+    async fn record_output(&mut self, stdout: &str) -> Result<()> {
+        let families = match self.settings.format {
+            ExecOutputFormat::Prometheus => prometheus_text::parse(stdout)?,
+            ExecOutputFormat::Influx => influx_line::parse(stdout)?,
+        };
+        self.recorders.record_families(&families).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    fn shell(script: &str) -> Vec<String> {
+        vec!["-c".to_string(), script.to_string()]
+    }
+
+    #[test]
+    fn test_run_command() {
+        let output = run_command("sh", &shell("echo 'backup_runs_total 3'; exit 3"), Duration::from_secs(10)).unwrap();
+        assert_eq!(output.stdout, "backup_runs_total 3\n");
+        assert_eq!(output.exit_code(), 3);
+        assert!(!output.timed_out);
+        assert!(run_command("/nonexistent/command", &[], Duration::from_secs(10)).is_err());
+    }
+
+    #[test]
+    fn test_kill_command_on_timeout() {
+        // the child started by the shell has to be killed too, or the output would never be closed
+        let output = run_command("sh", &shell("echo started; sleep 30; echo finished"), Duration::from_millis(100)).unwrap();
+        assert!(output.timed_out);
+        assert_eq!(output.stdout, "started\n");
+        assert_eq!(output.exit_code(), 128 + libc::SIGKILL);
+        assert!(output.duration < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_stop_reading_the_output_on_timeout() {
+        // the child left the process group of the command, so it isn't killed and keeps the output open
+        let output = run_command("sh", &shell("echo started; setsid sleep 5 &"), Duration::from_millis(200)).unwrap();
+        assert!(output.timed_out);
+        assert_eq!(output.stdout, "");
+        assert!(output.duration < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_collect_outputs() {
+        let settings = ExecSettings {
+            name: "exec_test".into(),
+            command: "sh".into(),
+            args: shell("echo 'exec_test_queue,queue=emails size=12i,oldest=3.5 1590000000000000000'"),
+            interval_millis: 15_000,
+            timeout_millis: 10_000,
+            format: ExecOutputFormat::Influx,
+        };
+        let mut monitor = ExecMonitor::new(&settings);
+        assert!(aw!(monitor.collect()).is_ok());
+        assert!(aw!(monitor.record_output("exec_test_jobs{state=\"done\"} 7")).is_err());
+        monitor.settings.format = ExecOutputFormat::Prometheus;
+        assert!(aw!(monitor.record_output("# TYPE exec_test_jobs gauge\nexec_test_jobs{state=\"done\"} 7")).is_ok());
+        assert_eq!(monitor.runs, 1);
+        assert_eq!(monitor.timeouts, 0);
+    }
+}
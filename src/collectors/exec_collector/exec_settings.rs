@@ -0,0 +1,34 @@
+/// A command run every `interval_millis`, whose output is parsed as `format` and published as metrics.
+/// It's killed, along with its children, when it runs for longer than `timeout_millis`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ExecSettings {
+    /// Name the metrics of the command itself are tagged with, e.g. `exec_exit_code{command="backup"}`.
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default = "default_interval_millis")]
+    pub interval_millis: u64,
+    #[serde(default = "default_timeout_millis")]
+    pub timeout_millis: u64,
+    #[serde(default)]
+    pub format: ExecOutputFormat,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecOutputFormat {
+    /// The Prometheus text exposition format, like the files of the textfile collector.
+    #[default]
+    Prometheus,
+    /// The InfluxDB line protocol, every field is published as the `<measurement>_<field>` gauge.
+    Influx,
+}
+
+fn default_interval_millis() -> u64 {
+    15_000
+}
+
+fn default_timeout_millis() -> u64 {
+    10_000
+}
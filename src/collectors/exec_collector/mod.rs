@@ -0,0 +1,2 @@
+pub mod exec_monitor;
+pub mod exec_settings;
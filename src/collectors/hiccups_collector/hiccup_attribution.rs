@@ -0,0 +1,257 @@
+//! Attributes the hiccups to the hypervisor or the scheduler by comparing the scheduling
+//! counters of the monitor thread, and the steal time of the host, before and after each sample.
+//!
+//! Reading `/proc` on every sample would add to the hiccups being measured, so only the involuntary
+//! context switches, which take a single syscall, are read on every sample. The run delay and the steal
+//! time are read after the samples above the threshold, and compared with a baseline refreshed at most
+//! every `BASELINE_REFRESH`.
+
+use std::fs;
+use std::time::{Duration, Instant};
+
+use crate::errors::{Error, Result};
+
+/// Cause attributed to a hiccup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, AsStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum HiccupCause {
+    /// The hypervisor stole CPU time from the host while the hiccup was happening.
+    Steal,
+    /// The monitor thread was preempted, or waited on a run queue, for a good part of the hiccup.
+    Preempted,
+    /// None of the above, e.g. page faults, SMIs or a stall of the whole host.
+    Unknown,
+}
+
+impl HiccupCause {
+    pub const ALL: [HiccupCause; 3] = [HiccupCause::Steal, HiccupCause::Preempted, HiccupCause::Unknown];
+}
+
+/// How often the baseline of the counters which take reading `/proc` is refreshed while there are no hiccups.
+const BASELINE_REFRESH: Duration = Duration::from_secs(1);
+
+/// Scheduling counters read around each sample.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SchedulingStats {
+    /// Time the monitor thread spent waiting on a run queue, in nanoseconds.
+    pub run_delay_nanos: u64,
+    /// Times the monitor thread was switched out involuntarily.
+    pub nonvoluntary_ctxt_switches: u64,
+    /// Time stolen by the hypervisor from all the CPUs of the host, in `USER_HZ` ticks.
+    pub steal_ticks: u64,
+}
+
+/// Reads the `SchedulingStats` of a thread.
+pub trait StatsReader {
+    /// The involuntary context switches of the thread, cheap enough to be read on every sample.
+    fn read_switches(&self) -> Result<u64>;
+
+    fn read(&self) -> Result<SchedulingStats>;
+}
+
+/// Reads the `SchedulingStats` of the thread which created it.
+pub struct SchedulingStatsReader {
+    schedstat_path: String,
+    stat_path: String,
+}
+
+impl SchedulingStatsReader {
+    /// It has to be created on the thread whose stats are going to be read.
+    pub fn new() -> SchedulingStatsReader {
+        let tid = unsafe { libc::syscall(libc::SYS_gettid) };
+        SchedulingStatsReader {
+            schedstat_path: format!("/proc/self/task/{}/schedstat", tid),
+            stat_path: "/proc/stat".to_string(),
+        }
+    }
+}
+
+impl StatsReader for SchedulingStatsReader {
+    /// The same count as the `nonvoluntary_ctxt_switches` of the status of the thread, without reading it.
+    fn read_switches(&self) -> Result<u64> {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        if unsafe { libc::getrusage(libc::RUSAGE_THREAD, &mut usage) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(usage.ru_nivcsw as u64)
+    }
+
+    fn read(&self) -> Result<SchedulingStats> {
+        Ok(SchedulingStats {
+            run_delay_nanos: parse_run_delay(&fs::read_to_string(&self.schedstat_path)?)?,
+            nonvoluntary_ctxt_switches: self.read_switches()?,
+            steal_ticks: parse_steal_ticks(&fs::read_to_string(&self.stat_path)?)?,
+        })
+    }
+}
+
+/// Keeps the baseline of the `SchedulingStats` to attribute the hiccups of the next samples.
+pub struct HiccupAttribution<R: StatsReader = SchedulingStatsReader> {
+    reader: R,
+    threshold_nanos: u64,
+    baseline: Option<(SchedulingStats, Instant)>,
+    last_switches: Option<u64>,
+}
+
+impl HiccupAttribution {
+    /// It has to be created on the monitor thread.
+    pub fn new(threshold_nanos: u64) -> HiccupAttribution {
+        HiccupAttribution::with_reader(SchedulingStatsReader::new(), threshold_nanos)
+    }
+}
+
+impl<R: StatsReader> HiccupAttribution<R> {
+    pub fn with_reader(reader: R, threshold_nanos: u64) -> HiccupAttribution<R> {
+        HiccupAttribution {
+            reader,
+            threshold_nanos,
+            baseline: None,
+            last_switches: None,
+        }
+    }
+
+    /// Reads the stats after a sample and attributes its hiccup to a cause, as long as
+    /// the hiccup is not shorter than the threshold.
+    pub fn attribute(&mut self, hiccup_nanos: u64) -> Option<HiccupCause> {
+        let switches = self.read(StatsReader::read_switches)?;
+        let previous_switches = self.last_switches.replace(switches);
+        let stale_baseline = self.baseline.is_none_or(|(_, read_at)| read_at.elapsed() >= BASELINE_REFRESH);
+        if hiccup_nanos < self.threshold_nanos || previous_switches.is_none() {
+            if stale_baseline {
+                let stats = self.read(StatsReader::read)?;
+                self.baseline = Some((stats, Instant::now()));
+            }
+            return None;
+        }
+        let stats = self.read(StatsReader::read)?;
+        let (baseline, _) = self.baseline.replace((stats, Instant::now()))?;
+        // the switches are the ones right before the sample, the rest since the baseline
+        let before = SchedulingStats { nonvoluntary_ctxt_switches: previous_switches?, ..baseline };
+        Some(attribute(&before, &SchedulingStats { nonvoluntary_ctxt_switches: switches, ..stats }, hiccup_nanos))
+    }
+
+    /// A failed read drops the baseline, so a hiccup isn't attributed from the stats before the failure.
+    fn read<T, F: Fn(&R) -> Result<T>>(&mut self, read: F) -> Option<T> {
+        match read(&self.reader) {
+            Ok(value) => Some(value),
+            Err(error) => {
+                debug!("Scheduling stats of the Hiccups-Monitor couldn't be read. Reason: {}", error);
+                self.baseline = None;
+                self.last_switches = None;
+                None
+            },
+        }
+    }
+}
+
+/// Steal takes precedence, since the scheduler of the guest also sees the stolen time as run delay.
+/// The thread is considered preempted when it was switched out involuntarily, or when it waited on
+/// a run queue for at least half of the hiccup.
+pub fn attribute(before: &SchedulingStats, after: &SchedulingStats, hiccup_nanos: u64) -> HiccupCause {
+    let run_delay_nanos = after.run_delay_nanos.saturating_sub(before.run_delay_nanos);
+    if after.steal_ticks > before.steal_ticks {
+        HiccupCause::Steal
+    } else if after.nonvoluntary_ctxt_switches > before.nonvoluntary_ctxt_switches || run_delay_nanos >= hiccup_nanos / 2 {
+        HiccupCause::Preempted
+    } else {
+        HiccupCause::Unknown
+    }
+}
+
+/// `schedstat` holds the time on the CPU, the time waiting on a run queue and the number of timeslices.
+fn parse_run_delay(schedstat: &str) -> Result<u64> {
+    schedstat.split_whitespace()
+        .nth(1)
+        .and_then(|run_delay| run_delay.parse::<u64>().ok())
+        .ok_or_else(|| Error::Msg(format!("Unexpected schedstat format: {}", schedstat)))
+}
+
+/// The aggregated `cpu` line of `/proc/stat` is `cpu user nice system idle iowait irq softirq steal ...`
+fn parse_steal_ticks(stat: &str) -> Result<u64> {
+    stat.lines()
+        .find(|line| line.starts_with("cpu "))
+        .and_then(|line| line.split_whitespace().nth(8))
+        .and_then(|steal| steal.parse::<u64>().ok())
+        .ok_or_else(|| Error::Msg("steal time not found on /proc/stat".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    const BEFORE: SchedulingStats = SchedulingStats { run_delay_nanos: 1_000_000, nonvoluntary_ctxt_switches: 10, steal_ticks: 100 };
+
+    #[test]
+    fn test_parse_scheduling_stats() {
+        assert_eq!(parse_run_delay("64015489 1523847 372\n").unwrap(), 1_523_847);
+        let stat = "cpu  74608 2520 24433 1117073 6176 4054 0 321 0 0\ncpu0 37784 1260 12215 558537 3088 2027 0 160 0 0\n";
+        assert_eq!(parse_steal_ticks(stat).unwrap(), 321);
+    }
+
+    #[test]
+    fn test_parse_unexpected_formats_fails() {
+        assert!(parse_run_delay("").is_err());
+        assert!(parse_steal_ticks("cpu  74608 2520 24433\n").is_err());
+    }
+
+    #[test]
+    fn test_steal_takes_precedence() {
+        let after = SchedulingStats { run_delay_nanos: 3_000_000, nonvoluntary_ctxt_switches: 11, steal_ticks: 101 };
+        assert_eq!(attribute(&BEFORE, &after, 2_000_000), HiccupCause::Steal);
+    }
+
+    #[test]
+    fn test_involuntary_switch_is_preemption() {
+        let after = SchedulingStats { nonvoluntary_ctxt_switches: 11, ..BEFORE };
+        assert_eq!(attribute(&BEFORE, &after, 2_000_000), HiccupCause::Preempted);
+    }
+
+    #[test]
+    fn test_long_run_delay_is_preemption() {
+        let after = SchedulingStats { run_delay_nanos: 2_000_000, ..BEFORE };
+        assert_eq!(attribute(&BEFORE, &after, 2_000_000), HiccupCause::Preempted);
+    }
+
+    #[test]
+    fn test_short_run_delay_is_unknown() {
+        let after = SchedulingStats { run_delay_nanos: 1_010_000, ..BEFORE };
+        assert_eq!(attribute(&BEFORE, &after, 2_000_000), HiccupCause::Unknown);
+        assert_eq!(attribute(&BEFORE, &BEFORE, 2_000_000), HiccupCause::Unknown);
+    }
+
+    /// Counts the reads of `/proc`, and preempts the thread once on every read of the switches.
+    #[derive(Default)]
+    struct FakeReader {
+        switches: Cell<u64>,
+        reads: Cell<u32>,
+    }
+
+    impl StatsReader for &FakeReader {
+        fn read_switches(&self) -> Result<u64> {
+            self.switches.set(self.switches.get() + 1);
+            Ok(self.switches.get())
+        }
+
+        fn read(&self) -> Result<SchedulingStats> {
+            self.reads.set(self.reads.get() + 1);
+            Ok(SchedulingStats { nonvoluntary_ctxt_switches: self.switches.get(), ..BEFORE })
+        }
+    }
+
+    #[test]
+    fn test_only_hiccups_above_the_threshold_are_attributed() {
+        let reader = FakeReader::default();
+        let mut attribution = HiccupAttribution::with_reader(&reader, 1_000);
+        // the first sample has nothing to be compared with, and reads the baseline
+        assert_eq!(attribution.attribute(5_000), None);
+        assert_eq!(reader.reads.get(), 1);
+        // the baseline is fresh, so the samples below the threshold don't read /proc
+        assert_eq!(attribution.attribute(999), None);
+        assert_eq!(attribution.attribute(10), None);
+        assert_eq!(reader.reads.get(), 1);
+        assert_eq!(attribution.attribute(5_000), Some(HiccupCause::Preempted));
+        assert_eq!(reader.reads.get(), 2);
+    }
+}
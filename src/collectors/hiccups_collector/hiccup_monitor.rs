@@ -1,29 +1,74 @@
 use std::{sync, thread};
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
 
-use crate::collectors::hiccups_collector::hiccup_settings::HiccupsMonitorSettings;
+use crate::collectors::hiccups_collector::hiccup_attribution::{HiccupAttribution, HiccupCause};
+use crate::collectors::hiccups_collector::hiccup_settings::{HiccupsCorrection, HiccupsMode, HiccupsMonitorSettings};
+use crate::collectors::hiccups_collector::hiccup_timer::HiccupTimer;
+use crate::metrics::counter::{CounterBuilder, CounterRecorder};
 use crate::metrics::histogram::{HistogramBuilder, HistogramRecorder, HistogramSettings};
+use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
+use crate::strum::AsStaticRef;
+use crate::utils::time;
+
+const CPU_USAGE_REPORT_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct HiccupMonitor {
     hiccup_nanos: u64,
+    mode: HiccupsMode,
+    correction: HiccupsCorrection,
     histogram: Arc<Mutex<HistogramRecorder>>,
+    attribution_threshold_nanos: u64,
+    attributed_histograms: Option<HashMap<HiccupCause, HistogramRecorder>>,
+    cpu_usage_counter: CounterRecorder,
     handle: Option<thread::JoinHandle<()>>,
     running: sync::Arc<AtomicBool>,
 }
 
 impl HiccupMonitor {
     pub fn new(config: &HiccupsMonitorSettings) -> HiccupMonitor {
-        info!("Starting Hiccups-Monitor [resolution = {} nanos]", config.resolution_nanos);
+        info!("Starting Hiccups-Monitor [resolution = {} nanos, mode = {:?}, correction = {:?}]", config.resolution_nanos, config.mode, config.correction);
+        let mut histogram_settings = HistogramSettings::from(config.histogram_settings.min, config.histogram_settings.max, config.histogram_settings.precision, config.histogram_settings.unit.to_measurement_units());
+        if config.correction == HiccupsCorrection::PostCorrect {
+            histogram_settings = histogram_settings.with_expected_interval(config.resolution_nanos);
+        }
         let histogram_publisher = HistogramBuilder::new(config.name.clone(), config.description.clone())
             .with_tags("component".to_string(), "rusty_advisor".to_string())
-            .with_settings(HistogramSettings::from(config.histogram_settings.min, config.histogram_settings.max, config.histogram_settings.precision, config.histogram_settings.unit.to_measurement_units()))
+            .with_settings(histogram_settings.clone())
+            .build_sync()
+            .unwrap();
+        let attributed_histograms = if config.attribution.enabled {
+            let mut attributed_histograms = HashMap::with_capacity(HiccupCause::ALL.len());
+            for cause in HiccupCause::ALL.iter() {
+                let histogram = HistogramBuilder::new(config.attribution.name.clone(), config.attribution.description.clone())
+                    .with_tags("component".to_string(), "rusty_advisor".to_string())
+                    .with_tags("cause".to_string(), cause.as_static().to_string())
+                    .with_settings(histogram_settings.clone())
+                    .build_sync()
+                    .unwrap();
+                attributed_histograms.insert(*cause, histogram);
+            }
+            Some(attributed_histograms)
+        } else {
+            None
+        };
+        let cpu_usage_counter = CounterBuilder::new("hiccups_monitor_cpu_seconds_total".into(),
+                                                    "CPU time consumed by the Hiccups-Monitor thread, which is the overhead of its measurement mode.".into())
+            .with_tags("component".to_string(), "rusty_advisor".to_string())
+            .with_tags("mode".to_string(), config.mode.as_static().to_string())
+            .with_measurement_unit(&MEASUREMENT_UNITS.time.nanos)
             .build_sync()
             .unwrap();
         HiccupMonitor {
             hiccup_nanos: config.resolution_nanos,
+            mode: config.mode,
+            correction: config.correction,
             histogram: Arc::new(Mutex::new(histogram_publisher)),
+            attribution_threshold_nanos: config.attribution.threshold_nanos,
+            attributed_histograms,
+            cpu_usage_counter,
             running: sync::Arc::new(AtomicBool::new(true)),
             handle: None,
         }
@@ -32,36 +77,43 @@ impl HiccupMonitor {
     pub fn run(&mut self) {
         info!("Hiccups Monitor running...");
 
-        let mut shortest_observed_delta = std::u64::MAX;
-        let resolution = self.hiccup_nanos.clone();
+        let mut shortest_observed_lateness = std::u64::MAX;
+        let resolution = self.hiccup_nanos;
+        let mode = self.mode;
+        let correction = self.correction;
         let is_running = self.running.clone();
         let histogram: Arc<Mutex<HistogramRecorder>> = self.histogram.clone();
+        let cpu_usage_counter = self.cpu_usage_counter.clone();
+        let attribution_threshold_nanos = self.attribution_threshold_nanos;
+        let attributed_histograms = self.attributed_histograms.take();
 
         self.handle = Some(thread::Builder::new().name("hiccup-monitor".into()).spawn(move || {
+            let mut timer = HiccupTimer::new(mode, resolution).unwrap_or_else(|error| {
+                error!("Hiccups mode {:?} couldn't be started, falling back to {:?}. Reason: {}", mode, HiccupsMode::Sleep, error);
+                HiccupTimer::new(HiccupsMode::Sleep, resolution).unwrap()
+            });
+            let mut cpu_usage = CpuUsageReporter::new(cpu_usage_counter);
+            let mut attribution = attributed_histograms
+                .map(|attributed_histograms| (HiccupAttribution::new(attribution_threshold_nanos), attributed_histograms));
             while is_running.load(Ordering::SeqCst) {
-                let hiccup_time = hicc(resolution, &mut shortest_observed_delta);
-                record(histogram.clone(), hiccup_time, resolution);
+                let hiccup_time = hicc(&mut timer, &mut shortest_observed_lateness);
+                record(&mut histogram.lock().unwrap(), hiccup_time, resolution, correction);
+                if let Some((attribution, attributed_histograms)) = attribution.as_mut() {
+                    if let Some(cause) = attribution.attribute(hiccup_time) {
+                        record(attributed_histograms.get_mut(&cause).unwrap(), hiccup_time, resolution, correction);
+                    }
+                    // the causes without hiccups are exported with empty histograms, instead of expiring
+                    attributed_histograms.values().for_each(HistogramRecorder::touch);
+                }
+                cpu_usage.report();
             }
+            cpu_usage.flush();
         }).unwrap());
 
-        fn hicc(resolution: u64, shortest_observed_delta: &mut u64) -> u64 {
-            let start = Instant::now();
-            thread::sleep(Duration::from_nanos(resolution));
-            let delta = start.elapsed().as_nanos() as u64;
-            if delta < *shortest_observed_delta { *shortest_observed_delta = delta }
-            delta - *shortest_observed_delta
-        }
-
-        /// We'll need fill in missing measurements as delayed
-        fn record(histogram: Arc<Mutex<HistogramRecorder>>, value: u64, expected_interval_between_value_samples: u64) {
-            histogram.lock().unwrap().record(value);
-            if expected_interval_between_value_samples > 0 {
-                let mut missing_value = if let Some(v) = value.checked_sub(expected_interval_between_value_samples) { v } else { 0 };
-                while missing_value >= expected_interval_between_value_samples {
-                    histogram.lock().unwrap().record(missing_value);
-                    missing_value -= expected_interval_between_value_samples
-                }
-            }
+        fn hicc(timer: &mut HiccupTimer, shortest_observed_lateness: &mut u64) -> u64 {
+            let lateness = timer.wait();
+            if lateness < *shortest_observed_lateness { *shortest_observed_lateness = lateness }
+            lateness - *shortest_observed_lateness
         }
     }
 
@@ -74,19 +126,91 @@ impl HiccupMonitor {
     }
 }
 
+/// Reports the CPU time consumed by the monitor thread. It's done once per `CPU_USAGE_REPORT_INTERVAL`
+/// so reporting doesn't add overhead to the measurement.
+struct CpuUsageReporter {
+    counter: CounterRecorder,
+    last_cpu_nanos: u64,
+    last_report: Instant,
+}
+
+impl CpuUsageReporter {
+    /// It has to be created on the thread whose CPU time is reported.
+    fn new(counter: CounterRecorder) -> CpuUsageReporter {
+        CpuUsageReporter {
+            counter,
+            last_cpu_nanos: time::thread_cpu_nanos(),
+            last_report: Instant::now(),
+        }
+    }
+
+    fn report(&mut self) {
+        if self.last_report.elapsed() >= CPU_USAGE_REPORT_INTERVAL {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        let cpu_nanos = time::thread_cpu_nanos();
+        self.counter.add(cpu_nanos.saturating_sub(self.last_cpu_nanos));
+        self.last_cpu_nanos = cpu_nanos;
+        self.last_report = Instant::now();
+    }
+}
+
+/// Records a hiccup according to the `correction` strategy.
+///
+/// With `RecordTime` we'll need fill in missing measurements as delayed, otherwise the
+/// hiccup is recorded as it was observed.
+fn record(recorder: &mut HistogramRecorder, value: u64, expected_interval_between_value_samples: u64, correction: HiccupsCorrection) {
+    let result = match correction {
+        HiccupsCorrection::RecordTime => recorder.record_correct(value, expected_interval_between_value_samples),
+        HiccupsCorrection::None | HiccupsCorrection::PostCorrect => recorder.record(value),
+    };
+    if let Err(error) = result {
+        debug!("Hiccup of {} nanos couldn't be recorded. Reason: {}", value, error);
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::collectors::hiccups_collector::hiccup_settings::{HiccupsHistogramSettings, HiccupsMonitorSettings};
+    use crate::collectors::hiccups_collector::hiccup_settings::{HiccupsAttributionSettings, HiccupsHistogramSettings, HiccupsMonitorSettings};
+    use crate::exporters::metrics_exporter::HistogramSample;
+    use crate::metrics::histogram::Histogram;
+    use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
+    use crate::metrics::metric::MetricDescription;
 
     use super::*;
 
+    const RESOLUTION: u64 = 1_000;
+
+    /// Records the synthetic `hiccups` on a fresh histogram and samples it like the exporter does.
+    fn record_and_sample(hiccups: &[u64], correction: HiccupsCorrection) -> HistogramSample {
+        let metric_description = MetricDescription::from("hiccups_test".into(), "some description".into(), hashmap! {}).unwrap();
+        let mut histogram_settings = HistogramSettings::from(1, 1_000_000, 2, &MEASUREMENT_UNITS.time.nanos);
+        if correction == HiccupsCorrection::PostCorrect {
+            histogram_settings = histogram_settings.with_expected_interval(RESOLUTION);
+        }
+        let mut histogram = Histogram::new(metric_description, histogram_settings).unwrap();
+        let mut recorder = histogram.new_recorder();
+        for hiccup in hiccups {
+            record(&mut recorder, *hiccup, RESOLUTION, correction);
+        }
+        // the recorder hands over its values to the histogram when it's dropped
+        drop(recorder);
+        histogram.sample(true)
+    }
+
     #[test]
     fn test_is_working() {
         let config = HiccupsMonitorSettings {
             name: "some_name".to_string(),
             description: "some description".to_string(),
             resolution_nanos: 1000,
+            mode: HiccupsMode::Sleep,
+            correction: HiccupsCorrection::RecordTime,
             histogram_settings: HiccupsHistogramSettings::default(),
+            attribution: HiccupsAttributionSettings::default(),
         };
         let mut monitor = HiccupMonitor::new(&config);
 
@@ -96,4 +220,68 @@ mod tests {
 
         monitor.stop()
     }
+
+    #[test]
+    fn test_no_correction_records_only_observed_hiccups() {
+        let sample = record_and_sample(&[0, 0, 5_000, 0], HiccupsCorrection::None);
+
+        let hdr_histogram = sample.hdr_histogram();
+        assert_eq!(hdr_histogram.len(), 4);
+        assert_eq!(hdr_histogram.count_at(5_000), 1);
+        assert_eq!(hdr_histogram.count_at(4_000), 0);
+    }
+
+    #[test]
+    fn test_record_time_correction_back_fills_missing_samples_once() {
+        let sample = record_and_sample(&[0, 0, 5_000, 0], HiccupsCorrection::RecordTime);
+
+        let hdr_histogram = sample.hdr_histogram();
+        // 5000 observed + 4000, 3000, 2000 and 1000 missed while the monitor was stalled
+        assert_eq!(hdr_histogram.len(), 8);
+        for value in &[5_000, 4_000, 3_000, 2_000, 1_000] {
+            assert_eq!(hdr_histogram.count_at(*value), 1, "value {} should have been recorded once", value);
+        }
+        assert_eq!(hdr_histogram.count_at(0), 3);
+    }
+
+    #[test]
+    fn test_post_correct_back_fills_missing_samples_when_sampling() {
+        let sample = record_and_sample(&[0, 0, 5_000, 0], HiccupsCorrection::PostCorrect);
+
+        let hdr_histogram = sample.hdr_histogram();
+        assert_eq!(hdr_histogram.len(), 8);
+        // the back-fill starts from the recorded value, so it's accurate up to the histogram precision
+        for value in &[5_000, 4_000, 3_000, 2_000, 1_000] {
+            assert_eq!(hdr_histogram.count_between(value - value / 50, value + value / 50), 1, "value {} should have been recorded once", value);
+        }
+        assert_eq!(hdr_histogram.count_at(0), 3);
+    }
+
+    #[test]
+    fn test_hiccups_shorter_than_resolution_are_never_corrected() {
+        for correction in &[HiccupsCorrection::None, HiccupsCorrection::RecordTime, HiccupsCorrection::PostCorrect] {
+            let sample = record_and_sample(&[999, 500, 1], *correction);
+
+            assert_eq!(sample.hdr_histogram().len(), 3, "correction {:?} shouldn't add samples", correction);
+        }
+    }
+
+    #[test]
+    fn test_record_time_and_post_correct_agree_on_high_percentiles() {
+        let mut hiccups = vec![0; 10_000];
+        hiccups[5_000] = 100_000;
+
+        let record_time = record_and_sample(&hiccups, HiccupsCorrection::RecordTime);
+        let post_correct = record_and_sample(&hiccups, HiccupsCorrection::PostCorrect);
+        let none = record_and_sample(&hiccups, HiccupsCorrection::None);
+
+        assert_eq!(record_time.hdr_histogram().len(), 10_099);
+        assert_eq!(record_time.hdr_histogram().len(), post_correct.hdr_histogram().len());
+        let record_time_p999 = record_time.hdr_histogram().value_at_quantile(0.999);
+        let post_correct_p999 = post_correct.hdr_histogram().value_at_quantile(0.999);
+        assert!(record_time_p999 > 0);
+        assert!((record_time_p999 as i64 - post_correct_p999 as i64).abs() <= (record_time_p999 / 50) as i64,
+                "p99.9 should be the same up to the histogram precision: {} vs {}", record_time_p999, post_correct_p999);
+        assert_eq!(none.hdr_histogram().value_at_quantile(0.999), 0);
+    }
 }
@@ -5,7 +5,20 @@ pub struct HiccupsMonitorSettings {
     pub name: String,
     pub description: String,
     pub resolution_nanos: u64,
+    pub mode: HiccupsMode,
+    pub correction: HiccupsCorrection,
     pub histogram_settings: HiccupsHistogramSettings,
+    pub attribution: HiccupsAttributionSettings,
+}
+
+/// Splits the hiccups longer than `threshold_nanos` by their cause (`steal`, `preempted` or `unknown`)
+/// on the histogram `name`, with the same settings as the hiccups histogram.
+#[derive(Debug, Deserialize, Clone)]
+pub struct HiccupsAttributionSettings {
+    pub enabled: bool,
+    pub name: String,
+    pub description: String,
+    pub threshold_nanos: u64,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -16,13 +29,68 @@ pub struct HiccupsHistogramSettings {
     pub unit: TimeUnitsSettings,
 }
 
+/// How the monitor waits between two samples.
+///
+/// The hiccup is how late the monitor wakes up compared with the shortest wake up it has ever observed,
+/// so the finer the wait, the smaller the hiccups it's able to detect.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, AsStaticStr)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum HiccupsMode {
+    /// Sleeps `resolution_nanos` with `thread::sleep`. It's the cheapest mode, but the timer slack
+    /// of the kernel hides the stalls shorter than ~50µs.
+    Sleep,
+    /// Spins on the monotonic clock for `resolution_nanos`, like `jHiccup` does, and takes the longest gap
+    /// between two consecutive reads of the clock. It detects stalls of a few microseconds at the cost
+    /// of keeping a CPU busy.
+    BusySpin,
+    /// Sleeps with `clock_nanosleep` until absolute deadlines every `resolution_nanos`, with the minimum
+    /// timer slack.
+    ClockNanosleep,
+    /// Waits for the expirations of a periodic `timerfd` armed every `resolution_nanos`, with the minimum
+    /// timer slack.
+    Timerfd,
+}
+
+/// Strategy used to compensate the coordinated omission of the hiccups measurements.
+///
+/// While the monitor thread is stalled it can't take the samples it was expected to take
+/// every `resolution_nanos`, so a single long hiccup hides all the samples that would have
+/// observed it too.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, AsStaticStr)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum HiccupsCorrection {
+    /// Records only the observed hiccups, without any correction.
+    None,
+    /// Back-fills the missing samples, at `resolution_nanos` steps, when a hiccup is recorded.
+    RecordTime,
+    /// Records only the observed hiccups, and back-fills the missing samples, at `resolution_nanos`
+    /// steps, when the histogram is sampled to be exported.
+    PostCorrect,
+}
+
 impl Default for HiccupsMonitorSettings {
     fn default() -> Self {
         HiccupsMonitorSettings {
             name: "hiccups_duration_seconds".into(),
             description: "Hiccups detected in the VM expressed in nanoseconds.".into(),
             resolution_nanos: 1_000_000,
+            mode: HiccupsMode::Sleep,
+            correction: HiccupsCorrection::RecordTime,
             histogram_settings: HiccupsHistogramSettings::default(),
+            attribution: HiccupsAttributionSettings::default(),
+        }
+    }
+}
+
+impl Default for HiccupsAttributionSettings {
+    fn default() -> Self {
+        HiccupsAttributionSettings {
+            enabled: true,
+            name: "hiccups_attributed_duration_seconds".into(),
+            description: "Hiccups detected in the VM split by their cause, expressed in nanoseconds.".into(),
+            threshold_nanos: 100_000,
         }
     }
 }
@@ -0,0 +1,180 @@
+use std::{io, mem, ptr, thread};
+use std::time::{Duration, Instant};
+
+use crate::collectors::hiccups_collector::hiccup_settings::HiccupsMode;
+use crate::errors::{Error, Result};
+use crate::utils::time;
+
+/// Waits between two samples of the hiccups monitor, according to its `HiccupsMode`.
+///
+/// Every wait returns how late the monitor woke up compared with what it was expecting.
+pub enum HiccupTimer {
+    Sleep { resolution: Duration },
+    BusySpin { resolution: Duration },
+    ClockNanosleep { resolution: u64, deadline: u64 },
+    Timerfd { resolution: u64, fd: libc::c_int, start: u64, expirations: u64 },
+}
+
+impl HiccupTimer {
+    /// Creates the timer for `mode`. It has to be created on the thread that is going to wait on it,
+    /// since the timer slack is set per thread.
+    pub fn new(mode: HiccupsMode, resolution_nanos: u64) -> Result<HiccupTimer> {
+        match mode {
+            HiccupsMode::Sleep => Ok(HiccupTimer::Sleep { resolution: Duration::from_nanos(resolution_nanos) }),
+            HiccupsMode::BusySpin => Ok(HiccupTimer::BusySpin { resolution: Duration::from_nanos(resolution_nanos) }),
+            HiccupsMode::ClockNanosleep => {
+                validate_resolution(mode, resolution_nanos)?;
+                set_minimum_timer_slack();
+                Ok(HiccupTimer::ClockNanosleep { resolution: resolution_nanos, deadline: time::monotonic_nanos() + resolution_nanos })
+            },
+            HiccupsMode::Timerfd => {
+                validate_resolution(mode, resolution_nanos)?;
+                set_minimum_timer_slack();
+                let fd = unsafe { libc::timerfd_create(libc::CLOCK_MONOTONIC, libc::TFD_CLOEXEC) };
+                if fd < 0 {
+                    return Err(io::Error::last_os_error().into());
+                }
+                // the timer is armed with absolute deadlines so we know exactly when each expiration was due
+                let start = time::monotonic_nanos();
+                let timer_spec = libc::itimerspec {
+                    it_interval: to_timespec(resolution_nanos),
+                    it_value: to_timespec(start + resolution_nanos),
+                };
+                if unsafe { libc::timerfd_settime(fd, libc::TFD_TIMER_ABSTIME, &timer_spec, ptr::null_mut()) } < 0 {
+                    let error = io::Error::last_os_error();
+                    unsafe { libc::close(fd) };
+                    return Err(error.into());
+                }
+                Ok(HiccupTimer::Timerfd { resolution: resolution_nanos, fd, start, expirations: 0 })
+            },
+        }
+    }
+
+    /// Blocks until the next sample and returns how late it woke up, in nanoseconds.
+    pub fn wait(&mut self) -> u64 {
+        match self {
+            HiccupTimer::Sleep { resolution } => {
+                let start = Instant::now();
+                thread::sleep(*resolution);
+                start.elapsed().checked_sub(*resolution).unwrap_or_default().as_nanos() as u64
+            },
+            HiccupTimer::BusySpin { resolution } => {
+                // any stall of the thread shows up as a gap between two consecutive reads of the clock
+                let start = Instant::now();
+                let mut last_read = start;
+                let mut longest_gap = Duration::default();
+                loop {
+                    let now = Instant::now();
+                    longest_gap = longest_gap.max(now - last_read);
+                    last_read = now;
+                    if now - start >= *resolution {
+                        break;
+                    }
+                    std::hint::spin_loop();
+                }
+                longest_gap.as_nanos() as u64
+            },
+            HiccupTimer::ClockNanosleep { resolution, deadline } => {
+                let deadline_spec = to_timespec(*deadline);
+                while unsafe { libc::clock_nanosleep(libc::CLOCK_MONOTONIC, libc::TIMER_ABSTIME, &deadline_spec, ptr::null_mut()) } == libc::EINTR {}
+                let now = time::monotonic_nanos();
+                let lateness = now.saturating_sub(*deadline);
+                *deadline += *resolution;
+                if *deadline <= now {
+                    // the missed deadlines are already accounted in the lateness, so it starts over from now
+                    *deadline = now + *resolution;
+                }
+                lateness
+            },
+            HiccupTimer::Timerfd { resolution, fd, start, expirations } => {
+                let mut new_expirations = 0u64;
+                loop {
+                    let read = unsafe { libc::read(*fd, &mut new_expirations as *mut u64 as *mut libc::c_void, mem::size_of::<u64>()) };
+                    if read >= 0 || io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+                        break;
+                    }
+                }
+                let now = time::monotonic_nanos();
+                // the lateness is measured from the first expiration we were waiting for, even when several expired
+                let deadline = *start + (*expirations + 1) * *resolution;
+                *expirations += new_expirations.max(1);
+                now.saturating_sub(deadline)
+            },
+        }
+    }
+}
+
+impl Drop for HiccupTimer {
+    fn drop(&mut self) {
+        if let HiccupTimer::Timerfd { fd, .. } = self {
+            unsafe { libc::close(*fd) };
+        }
+    }
+}
+
+fn validate_resolution(mode: HiccupsMode, resolution_nanos: u64) -> Result<()> {
+    if resolution_nanos == 0 {
+        return Err(Error::Msg(format!("Hiccups mode {:?} requires a resolution greater than 0 nanos", mode)));
+    }
+    Ok(())
+}
+
+/// Asks the kernel to not delay the wake ups of this thread to batch them with others,
+/// which is 50µs by default for non real time threads.
+fn set_minimum_timer_slack() {
+    if unsafe { libc::prctl(libc::PR_SET_TIMERSLACK, 1 as libc::c_ulong) } < 0 {
+        warn!("Timer slack of the hiccups monitor couldn't be reduced. Reason: {}", io::Error::last_os_error());
+    }
+}
+
+fn to_timespec(nanos: u64) -> libc::timespec {
+    libc::timespec {
+        tv_sec: (nanos / 1_000_000_000) as libc::time_t,
+        tv_nsec: (nanos % 1_000_000_000) as libc::c_long,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESOLUTION: u64 = 200_000;
+    const SAMPLES: u32 = 20;
+
+    #[test]
+    fn test_every_mode_waits_at_least_the_resolution_per_sample() {
+        for mode in &[HiccupsMode::Sleep, HiccupsMode::BusySpin, HiccupsMode::ClockNanosleep, HiccupsMode::Timerfd] {
+            let mut timer = HiccupTimer::new(*mode, RESOLUTION).unwrap();
+            let start = Instant::now();
+            for _ in 0..SAMPLES {
+                let lateness = timer.wait();
+                assert!(lateness < 1_000_000_000, "mode {:?} reported an unreasonable lateness of {} nanos", mode, lateness);
+            }
+            // the absolute deadlines start counting a bit before the first wait
+            let expected = Duration::from_nanos(RESOLUTION) * (SAMPLES - 1);
+            assert!(start.elapsed() >= expected, "mode {:?} waited {:?} for {} samples", mode, start.elapsed(), SAMPLES);
+        }
+    }
+
+    #[test]
+    fn test_absolute_deadline_modes_report_a_stall_as_lateness() {
+        for mode in &[HiccupsMode::ClockNanosleep, HiccupsMode::Timerfd] {
+            let mut timer = HiccupTimer::new(*mode, RESOLUTION).unwrap();
+            timer.wait();
+            // a synthetic stall of 50 samples
+            thread::sleep(Duration::from_nanos(RESOLUTION * 50));
+            let lateness = timer.wait();
+            assert!(lateness >= RESOLUTION * 49, "mode {:?} reported a lateness of {} nanos", mode, lateness);
+            // and then it goes back to the regular pace
+            timer.wait();
+            let lateness = timer.wait();
+            assert!(lateness < RESOLUTION * 49, "mode {:?} reported a lateness of {} nanos after recovering", mode, lateness);
+        }
+    }
+
+    #[test]
+    fn test_absolute_deadline_modes_require_a_resolution() {
+        assert!(HiccupTimer::new(HiccupsMode::ClockNanosleep, 0).is_err());
+        assert!(HiccupTimer::new(HiccupsMode::Timerfd, 0).is_err());
+    }
+}
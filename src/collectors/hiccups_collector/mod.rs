@@ -1,2 +1,4 @@
+pub mod hiccup_attribution;
 pub mod hiccup_monitor;
 pub mod hiccup_settings;
+pub mod hiccup_timer;
@@ -0,0 +1,283 @@
+//! Collects the hardware sensors of the host from `sysfs`:
+//!   - `/sys/class/hwmon/hwmon*`: the temperature, fan, voltage and power inputs of every chip.
+//!   - `/sys/class/thermal/thermal_zone*`: the temperature of every thermal zone.
+//!   - `/sys/devices/system/cpu/cpu*/thermal_throttle`: the times the cores and the packages were throttled.
+//!
+//! More details can be found at https://www.kernel.org/doc/html/latest/hwmon/sysfs-interface.html
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::collectors::collectors_settings::CollectorsSettings;
+use crate::collectors::metric_recorders::{MetricRecorders, to_tag_value};
+use crate::collectors::sysfs;
+use crate::errors::Result;
+use crate::metrics::counter::CounterBuilder;
+use crate::metrics::gauge::GaugeBuilder;
+
+/// Kind of input of a hwmon sensor, with the metric it's exported as and the scale of its `sysfs` value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SensorKind {
+    /// Millidegrees Celsius.
+    Temperature,
+    /// Revolutions per minute.
+    Fan,
+    /// Millivolts.
+    Voltage,
+    /// Microwatts.
+    Power,
+}
+
+impl SensorKind {
+    fn from_prefix(prefix: &str) -> Option<SensorKind> {
+        match prefix {
+            "temp" => Some(SensorKind::Temperature),
+            "fan" => Some(SensorKind::Fan),
+            "in" => Some(SensorKind::Voltage),
+            "power" => Some(SensorKind::Power),
+            _ => None,
+        }
+    }
+
+    fn metric(&self) -> (&'static str, &'static str) {
+        match self {
+            SensorKind::Temperature => ("hwmon_temperature_celsius", "Temperature of the hwmon sensor."),
+            SensorKind::Fan => ("hwmon_fan_rpm", "Speed of the hwmon fan."),
+            SensorKind::Voltage => ("hwmon_voltage_volts", "Voltage of the hwmon sensor."),
+            SensorKind::Power => ("hwmon_power_watts", "Power of the hwmon sensor."),
+        }
+    }
+
+    fn to_base_unit(self, value: f64) -> f64 {
+        match self {
+            SensorKind::Temperature | SensorKind::Voltage => value / 1_000.0,
+            SensorKind::Fan => value,
+            SensorKind::Power => value / 1_000_000.0,
+        }
+    }
+}
+
+/// Input of a hwmon chip, e.g. `temp1_input`, already converted to its base unit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sensor {
+    /// Directory of the chip, e.g. `hwmon0`.
+    pub chip: String,
+    /// Name of the chip driver, e.g. `coretemp`.
+    pub chip_name: String,
+    /// Name of the input, e.g. `temp1`.
+    pub sensor: String,
+    /// Label of the input, e.g. `Core 0`, or the name of the input when it doesn't have one.
+    pub label: String,
+    pub kind: SensorKind,
+    pub value: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThermalZone {
+    pub zone: String,
+    pub zone_type: String,
+    pub celsius: f64,
+}
+
+/// Throttle counts of a CPU. The package count is shared by all the CPUs of the package.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CpuThrottles {
+    pub cpu: String,
+    pub package: String,
+    pub core_throttles: Option<u64>,
+    pub package_throttles: Option<u64>,
+}
+
+pub struct HwmonMonitor {
+    hwmon_path: PathBuf,
+    thermal_path: PathBuf,
+    cpus_path: PathBuf,
+    interval: Duration,
+    recorders: MetricRecorders,
+}
+
+impl HwmonMonitor {
+    pub fn new(config: &CollectorsSettings) -> HwmonMonitor {
+        HwmonMonitor {
+            hwmon_path: PathBuf::from(&config.sysfs_path).join("class/hwmon"),
+            thermal_path: PathBuf::from(&config.sysfs_path).join("class/thermal"),
+            cpus_path: PathBuf::from(&config.sysfs_path).join("devices/system/cpu"),
+            interval: Duration::from_millis(config.hwmon.interval_millis),
+            recorders: MetricRecorders::new(),
+        }
+    }
+
+    pub async fn start(mut self) {
+        loop {
+            if let Err(error) = self.collect().await {
+                warn!("Hwmon collector failed to collect. Reason: {}", error);
+            }
+            tokio::time::delay_for(self.interval).await;
+        }
+    }
+
+    async fn collect(&mut self) -> Result<()> {
+        for sensor in read_sensors(&self.hwmon_path).await? {
+            let (name, description) = sensor.kind.metric();
+            let builder = GaugeBuilder::new(name.into(), description.into())
+                .with_tags("chip".to_string(), sensor.chip)
+                .with_tags("chip_name".to_string(), to_tag_value(&sensor.chip_name))
+                .with_tags("sensor".to_string(), sensor.sensor)
+                .with_tags("label".to_string(), to_tag_value(&sensor.label));
+            self.recorders.gauge(builder, sensor.value).await?;
+        }
+        for zone in read_thermal_zones(&self.thermal_path).await? {
+            let builder = GaugeBuilder::new("thermal_zone_temperature_celsius".into(), "Temperature of the thermal zone.".into())
+                .with_tags("zone".to_string(), zone.zone)
+                .with_tags("type".to_string(), to_tag_value(&zone.zone_type));
+            self.recorders.gauge(builder, zone.celsius).await?;
+        }
+        let mut packages = HashSet::new();
+        for throttles in read_cpu_throttles(&self.cpus_path).await? {
+            if let Some(core_throttles) = throttles.core_throttles {
+                let builder = CounterBuilder::new("cpu_core_throttles_total".into(), "Times the CPU core was throttled because of its temperature.".into())
+                    .with_tags("cpu".to_string(), throttles.cpu.clone());
+                self.recorders.counter_total(builder, core_throttles).await?;
+            }
+            match throttles.package_throttles {
+                Some(package_throttles) if packages.insert(throttles.package.clone()) => {
+                    let builder = CounterBuilder::new("cpu_package_throttles_total".into(), "Times the CPU package was throttled because of its temperature.".into())
+                        .with_tags("package".to_string(), throttles.package);
+                    self.recorders.counter_total(builder, package_throttles).await?;
+                },
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads every `<kind><N>_input` of every chip, along with its `<kind><N>_label`.
+pub async fn read_sensors(hwmon_path: &Path) -> Result<Vec<Sensor>> {
+    let mut sensors = Vec::new();
+    for (chip, chip_path) in sysfs::list_dir(hwmon_path, "hwmon").await? {
+        let chip_name = sysfs::read_optional_value::<String, _>(chip_path.join("name")).await?.unwrap_or_default();
+        for (file_name, path) in sysfs::list_dir(&chip_path, "").await? {
+            let sensor = match file_name.strip_suffix("_input") {
+                Some(sensor) => sensor,
+                None => continue,
+            };
+            let kind = match SensorKind::from_prefix(sensor.trim_end_matches(|c: char| c.is_ascii_digit())) {
+                Some(kind) => kind,
+                None => continue,
+            };
+            // some drivers fail to read the sensors which aren't connected
+            let value = match sysfs::read_value::<f64, _>(&path).await {
+                Ok(value) => value,
+                Err(error) => {
+                    debug!("Sensor {} couldn't be read. Reason: {}", path.display(), error);
+                    continue;
+                },
+            };
+            let label = sysfs::read_optional_value::<String, _>(chip_path.join(format!("{}_label", sensor))).await?;
+            sensors.push(Sensor {
+                chip: chip.clone(),
+                chip_name: chip_name.clone(),
+                sensor: sensor.to_string(),
+                label: label.unwrap_or_else(|| sensor.to_string()),
+                kind,
+                value: kind.to_base_unit(value),
+            });
+        }
+    }
+    Ok(sensors)
+}
+
+pub async fn read_thermal_zones(thermal_path: &Path) -> Result<Vec<ThermalZone>> {
+    let mut zones = Vec::new();
+    for (name, path) in sysfs::list_dir(thermal_path, "thermal_zone").await? {
+        let millicelsius = match sysfs::read_value::<f64, _>(path.join("temp")).await {
+            Ok(millicelsius) => millicelsius,
+            Err(error) => {
+                debug!("Thermal zone {} couldn't be read. Reason: {}", path.display(), error);
+                continue;
+            },
+        };
+        zones.push(ThermalZone {
+            zone: name.trim_start_matches("thermal_zone").to_string(),
+            zone_type: sysfs::read_string(path.join("type")).await?,
+            celsius: millicelsius / 1_000.0,
+        });
+    }
+    Ok(zones)
+}
+
+/// Only the CPUs with a `thermal_throttle` directory are returned, which depends on the CPU vendor.
+pub async fn read_cpu_throttles(cpus_path: &Path) -> Result<Vec<CpuThrottles>> {
+    let mut cpus = Vec::new();
+    for (name, path) in sysfs::list_dir(cpus_path, "cpu").await? {
+        let cpu = name.trim_start_matches("cpu");
+        if cpu.parse::<u32>().is_err() {
+            continue;
+        }
+        let throttle_path = path.join("thermal_throttle");
+        let core_throttles = sysfs::read_optional_value(throttle_path.join("core_throttle_count")).await?;
+        let package_throttles = sysfs::read_optional_value(throttle_path.join("package_throttle_count")).await?;
+        if core_throttles.is_none() && package_throttles.is_none() {
+            continue;
+        }
+        let package = sysfs::read_optional_value::<String, _>(path.join("topology/physical_package_id")).await?;
+        cpus.push(CpuThrottles {
+            cpu: cpu.to_string(),
+            package: package.unwrap_or_else(|| "0".to_string()),
+            core_throttles,
+            package_throttles,
+        });
+    }
+    Ok(cpus)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::tests::sysfs_fixture;
+
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    fn sensor(chip: &str, chip_name: &str, sensor: &str, label: &str, kind: SensorKind, value: f64) -> Sensor {
+        Sensor { chip: chip.into(), chip_name: chip_name.into(), sensor: sensor.into(), label: label.into(), kind, value }
+    }
+
+    #[test]
+    fn test_read_sensors() {
+        let sensors = aw!(read_sensors(&sysfs_fixture("class/hwmon"))).unwrap();
+        assert_eq!(sensors, vec![
+            sensor("hwmon0", "coretemp", "temp1", "Package id 0", SensorKind::Temperature, 54.0),
+            sensor("hwmon0", "coretemp", "temp2", "Core 0", SensorKind::Temperature, 51.0),
+            sensor("hwmon1", "nct6775", "fan1", "fan1", SensorKind::Fan, 1250.0),
+            sensor("hwmon1", "nct6775", "in0", "in0", SensorKind::Voltage, 1.104),
+            sensor("hwmon1", "nct6775", "power1", "power1", SensorKind::Power, 35.5),
+            sensor("hwmon1", "nct6775", "temp1", "temp1", SensorKind::Temperature, 38.5),
+        ]);
+    }
+
+    #[test]
+    fn test_read_thermal_zones() {
+        let zones = aw!(read_thermal_zones(&sysfs_fixture("class/thermal"))).unwrap();
+        assert_eq!(zones, vec![
+            ThermalZone { zone: "0".into(), zone_type: "x86_pkg_temp".into(), celsius: 55.0 },
+            ThermalZone { zone: "1".into(), zone_type: "acpitz".into(), celsius: 27.8 },
+        ]);
+    }
+
+    #[test]
+    fn test_read_cpu_throttles() {
+        let throttles = aw!(read_cpu_throttles(&sysfs_fixture("devices/system/cpu"))).unwrap();
+        assert_eq!(throttles, vec![
+            CpuThrottles { cpu: "0".into(), package: "0".into(), core_throttles: Some(3), package_throttles: Some(7) },
+            CpuThrottles { cpu: "1".into(), package: "0".into(), core_throttles: Some(0), package_throttles: Some(7) },
+        ]);
+        assert!(aw!(read_cpu_throttles(&sysfs_fixture("devices/system/missing"))).unwrap().is_empty());
+    }
+}
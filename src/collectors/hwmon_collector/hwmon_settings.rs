@@ -0,0 +1,14 @@
+#[derive(Debug, Deserialize, Clone)]
+pub struct HwmonSettings {
+    pub enabled: bool,
+    pub interval_millis: u64,
+}
+
+impl Default for HwmonSettings {
+    fn default() -> Self {
+        HwmonSettings {
+            enabled: true,
+            interval_millis: 15_000,
+        }
+    }
+}
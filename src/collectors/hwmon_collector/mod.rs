@@ -0,0 +1,2 @@
+pub mod hwmon_monitor;
+pub mod hwmon_settings;
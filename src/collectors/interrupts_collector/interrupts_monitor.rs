@@ -0,0 +1,196 @@
+//! Collects the hardware interrupts of `/proc/interrupts` and the software interrupts of `/proc/softirqs`,
+//! handled by every CPU. A noisy IRQ on the CPU the hiccups monitor runs on is a frequent cause of hiccups.
+
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::fs;
+
+use crate::collectors::collectors_settings::CollectorsSettings;
+use crate::collectors::metric_recorders::{MetricRecorders, to_tag_value};
+use crate::errors::{Error, Result};
+use crate::metrics::counter::CounterBuilder;
+
+/// Interrupts of an IRQ, or of a type of softirq, handled by every CPU.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Interrupts {
+    pub name: String,
+    /// Devices which raise the IRQ, or the description of the architecture specific interrupts (e.g. `LOC`).
+    pub device: String,
+    /// CPU number and interrupts handled by the CPU.
+    pub per_cpu: Vec<(String, u64)>,
+}
+
+pub struct InterruptsMonitor {
+    interrupts_path: PathBuf,
+    softirqs_path: PathBuf,
+    interval: Duration,
+    aggregate_cpus: bool,
+    recorders: MetricRecorders,
+}
+
+impl InterruptsMonitor {
+    pub fn new(config: &CollectorsSettings) -> InterruptsMonitor {
+        InterruptsMonitor {
+            interrupts_path: PathBuf::from(&config.procfs_path).join("interrupts"),
+            softirqs_path: PathBuf::from(&config.procfs_path).join("softirqs"),
+            interval: Duration::from_millis(config.interrupts.interval_millis),
+            aggregate_cpus: config.interrupts.aggregate_cpus,
+            recorders: MetricRecorders::new(),
+        }
+    }
+
+    pub async fn start(mut self) {
+        loop {
+            match self.collect().await {
+                Ok(_) => {},
+                Err(Error::Io(error)) if error.kind() == io::ErrorKind::NotFound => {
+                    warn!("Interrupts collector is stopped since {} or {} are not available. Reason: {}",
+                          self.interrupts_path.display(), self.softirqs_path.display(), error);
+                    return;
+                },
+                Err(error) => warn!("Interrupts collector failed to collect. Reason: {}", error),
+            }
+            tokio::time::delay_for(self.interval).await;
+        }
+    }
+
+    async fn collect(&mut self) -> Result<()> {
+        for irq in parse_interrupts(&fs::read_to_string(&self.interrupts_path).await?)? {
+            let builder = CounterBuilder::new("interrupts_total".into(), "Hardware interrupts handled by IRQ and device.".into())
+                .with_tags("irq".to_string(), to_tag_value(&irq.name))
+                .with_tags("device".to_string(), to_tag_value(&irq.device));
+            self.record(builder, &irq).await?;
+        }
+        for softirq in parse_softirqs(&fs::read_to_string(&self.softirqs_path).await?)? {
+            let builder = CounterBuilder::new("softirqs_total".into(), "Software interrupts handled by type.".into())
+                .with_tags("type".to_string(), to_tag_value(&softirq.name.to_lowercase()));
+            self.record(builder, &softirq).await?;
+        }
+        Ok(())
+    }
+
+    /// Records the interrupts by CPU, or their sum without the `cpu` tag when they are aggregated.
+    async fn record(&mut self, builder: CounterBuilder, interrupts: &Interrupts) -> Result<()> {
+        if self.aggregate_cpus {
+            let total = interrupts.per_cpu.iter().map(|(_, count)| count).sum();
+            self.recorders.counter_total(builder, total).await
+        } else {
+            for (cpu, count) in interrupts.per_cpu.iter() {
+                self.recorders.counter_total(builder.clone().with_tags("cpu".to_string(), cpu.clone()), *count).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The first line has the CPUs (e.g. `CPU0 CPU1`), and then every IRQ is `name: count_cpu0 count_cpu1 description`.
+///
+/// The description of a numbered IRQ is the chip, the hardware IRQ with its trigger type and the devices,
+/// e.g. `IO-APIC 16-fasteoi ehci_hcd:usb1, uhci_hcd:usb2`, and only the devices are kept.
+/// The architecture specific interrupts (e.g. `LOC`) only have a description, and some of them (e.g. `ERR`)
+/// have a single count for all the CPUs, so they are skipped.
+pub fn parse_interrupts(interrupts: &str) -> Result<Vec<Interrupts>> {
+    let mut lines = interrupts.lines();
+    let cpus = parse_cpus(lines.next())?;
+    let mut parsed = Vec::new();
+    for line in lines.filter(|line| !line.trim().is_empty()) {
+        let (name, fields) = split_name(line)?;
+        if fields.len() < cpus.len() {
+            continue;
+        }
+        let per_cpu = parse_counts(&cpus, &fields[..cpus.len()], line)?;
+        let description = &fields[cpus.len()..];
+        let device = if name.parse::<u32>().is_ok() {
+            let trigger = description.iter()
+                .rposition(|field| {
+                    let field = field.to_lowercase();
+                    field.ends_with("edge") || field.ends_with("level") || field.ends_with("fasteoi")
+                });
+            match trigger {
+                Some(trigger) => description[trigger + 1..].join(" "),
+                None => description.last().map(|device| device.to_string()).unwrap_or_default(),
+            }
+        } else {
+            description.join(" ")
+        };
+        parsed.push(Interrupts { name: name.to_string(), device, per_cpu });
+    }
+    Ok(parsed)
+}
+
+/// Same layout as `/proc/interrupts`, without descriptions.
+pub fn parse_softirqs(softirqs: &str) -> Result<Vec<Interrupts>> {
+    let mut lines = softirqs.lines();
+    let cpus = parse_cpus(lines.next())?;
+    let mut parsed = Vec::new();
+    for line in lines.filter(|line| !line.trim().is_empty()) {
+        let (name, fields) = split_name(line)?;
+        let per_cpu = parse_counts(&cpus, &fields, line)?;
+        parsed.push(Interrupts { name: name.to_string(), device: String::new(), per_cpu });
+    }
+    Ok(parsed)
+}
+
+fn parse_cpus(header: Option<&str>) -> Result<Vec<String>> {
+    let header = header.ok_or_else(|| Error::Msg("Interrupts without CPUs header".to_string()))?;
+    Ok(header.split_whitespace()
+        .map(|cpu| cpu.trim_start_matches("CPU").to_string())
+        .collect())
+}
+
+fn split_name(line: &str) -> Result<(&str, Vec<&str>)> {
+    let mut fields = line.split_whitespace();
+    let name = fields.next()
+        .filter(|name| name.ends_with(':'))
+        .ok_or_else(|| Error::Msg(format!("Unexpected interrupts format: {}", line)))?;
+    Ok((name.trim_end_matches(':'), fields.collect()))
+}
+
+fn parse_counts(cpus: &[String], counts: &[&str], line: &str) -> Result<Vec<(String, u64)>> {
+    if counts.len() != cpus.len() {
+        return Err(Error::Msg(format!("Unexpected interrupts format: {}", line)));
+    }
+    cpus.iter().zip(counts.iter())
+        .map(|(cpu, count)| {
+            count.parse::<u64>()
+                .map(|count| (cpu.clone(), count))
+                .map_err(|_| Error::Msg(format!("Unexpected interrupts format: {}", line)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(file_name: &str) -> String {
+        std::fs::read_to_string(format!("{}/fixtures/proc/{}", env!("CARGO_MANIFEST_DIR"), file_name)).unwrap()
+    }
+
+    fn per_cpu(counts: &[u64]) -> Vec<(String, u64)> {
+        counts.iter().enumerate().map(|(cpu, count)| (cpu.to_string(), *count)).collect()
+    }
+
+    #[test]
+    fn test_parse_interrupts() {
+        let interrupts = parse_interrupts(&fixture("interrupts")).unwrap();
+        assert_eq!(interrupts.len(), 9);
+        assert_eq!(interrupts[0], Interrupts { name: "0".into(), device: "timer".into(), per_cpu: per_cpu(&[36, 0, 0, 0]) });
+        assert_eq!(interrupts[3].device, "ehci_hcd:usb1, uhci_hcd:usb2");
+        assert_eq!(interrupts[4], Interrupts { name: "27".into(), device: "xhci_hcd".into(), per_cpu: per_cpu(&[190554, 0, 0, 25714]) });
+        assert_eq!(interrupts[5].device, "arch_timer");
+        assert_eq!(interrupts[7], Interrupts { name: "LOC".into(), device: "Local timer interrupts".into(), per_cpu: per_cpu(&[24983641, 21750329, 23016718, 22870024]) });
+        // ERR and MIS have a single count
+        assert!(interrupts.iter().all(|irq| irq.name != "ERR"));
+    }
+
+    #[test]
+    fn test_parse_softirqs() {
+        let softirqs = parse_softirqs(&fixture("softirqs")).unwrap();
+        assert_eq!(softirqs.len(), 10);
+        assert_eq!(softirqs[3], Interrupts { name: "NET_RX".into(), device: String::new(), per_cpu: per_cpu(&[789845, 40012, 52139, 33871]) });
+        assert!(parse_softirqs("    CPU0   CPU1\n  HI:   1\n").is_err());
+    }
+}
@@ -0,0 +1,18 @@
+/// `aggregate_cpus` sums the interrupts of all the CPUs, without the `cpu` tag,
+/// to bound the number of series on hosts with many cores.
+#[derive(Debug, Deserialize, Clone)]
+pub struct InterruptsSettings {
+    pub enabled: bool,
+    pub interval_millis: u64,
+    pub aggregate_cpus: bool,
+}
+
+impl Default for InterruptsSettings {
+    fn default() -> Self {
+        InterruptsSettings {
+            enabled: true,
+            interval_millis: 15_000,
+            aggregate_cpus: false,
+        }
+    }
+}
@@ -0,0 +1,2 @@
+pub mod interrupts_monitor;
+pub mod interrupts_settings;
@@ -0,0 +1,404 @@
+//! Follows a log file, like `tail -F`, and updates the counters and histograms configured for the
+//! lines matching their regexes, e.g. the requests and their latency from the access log of a proxy.
+//!
+//! The file is reopened when it's rotated, which is detected by its inode changing, after reading
+//! the lines left on the rotated one. It's read again from the beginning when it's truncated, which
+//! is detected by its size being smaller than the offset already read.
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use regex::Regex;
+use tokio::fs::{self, File};
+use tokio::io::AsyncReadExt;
+
+use crate::collectors::log_tail_collector::log_tail_settings::{LogMetricSettings, LogMetricType, LogTailSettings};
+use crate::collectors::metric_recorders::{MetricRecorders, to_tag_value};
+use crate::errors::{Error, Result};
+use crate::metrics::counter::CounterBuilder;
+use crate::metrics::histogram::{HistogramBuilder, HistogramSettings};
+use crate::metrics::measurement_unit::{self, Dimension, MEASUREMENT_UNITS, MeasurementUnit};
+use crate::metrics::metric::{MetricDescription, MetricId};
+
+/// Bounds the lines read on a single collection, the rest of them are read on the next ones.
+const MAX_READ_BYTES: usize = 8 * 1024 * 1024;
+
+struct TailedFile {
+    file: File,
+    inode: u64,
+    offset: u64,
+    /// The end of the file without a line break yet.
+    partial_line: Vec<u8>,
+}
+
+impl TailedFile {
+    async fn open(path: &PathBuf, from_end: bool) -> Result<TailedFile> {
+        let mut file = File::open(path).await?;
+        let metadata = file.metadata().await?;
+        let offset = if from_end { metadata.len() } else { 0 };
+        file.seek(SeekFrom::Start(offset)).await?;
+        Ok(TailedFile { file, inode: metadata.ino(), offset, partial_line: vec![] })
+    }
+
+    async fn rewind(&mut self) -> Result<()> {
+        self.offset = self.file.seek(SeekFrom::Start(0)).await?;
+        self.partial_line.clear();
+        Ok(())
+    }
+
+    async fn read_lines(&mut self, lines: &mut Vec<String>) -> Result<()> {
+        let mut buffer = vec![0; 64 * 1024];
+        let mut read = 0;
+        while read < MAX_READ_BYTES {
+            let bytes = self.file.read(&mut buffer).await?;
+            if bytes == 0 {
+                break;
+            }
+            read += bytes;
+            self.offset += bytes as u64;
+            for byte in &buffer[..bytes] {
+                if *byte == b'\n' {
+                    lines.push(String::from_utf8_lossy(&self.partial_line).into_owned());
+                    self.partial_line.clear();
+                } else {
+                    self.partial_line.push(*byte);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The last line of a rotated file is complete even without a line break.
+    fn take_partial_line(&mut self, lines: &mut Vec<String>) {
+        if !self.partial_line.is_empty() {
+            lines.push(String::from_utf8_lossy(&self.partial_line).into_owned());
+            self.partial_line.clear();
+        }
+    }
+}
+
+enum LogUpdate {
+    Counter(CounterBuilder, u64),
+    Histogram(HistogramBuilder, u64),
+}
+
+/// A metric of the configuration, validated when the monitor is created.
+struct LogMetric {
+    settings: LogMetricSettings,
+    regex: Regex,
+    tag_names: Vec<String>,
+    value_unit: &'static MeasurementUnit,
+    histogram_settings: HistogramSettings,
+}
+
+impl LogMetric {
+    fn from(settings: &LogMetricSettings) -> Result<LogMetric> {
+        let regex = Regex::new(&settings.regex)
+            .map_err(|error| Error::Msg(format!("Invalid regex of the log metric {}. Reason: {}", settings.name, error)))?;
+        let value_group = settings.value.as_deref();
+        if let Some(value_group) = value_group {
+            if !regex.capture_names().any(|name| name == Some(value_group)) {
+                return Err(Error::Msg(format!("The regex of the log metric {} doesn't have the capture group '{}'", settings.name, value_group)));
+            }
+        } else if settings.metric_type == LogMetricType::Histogram {
+            return Err(Error::Msg(format!("The histogram {} requires the capture group of its value", settings.name)));
+        }
+        let tag_names: Vec<String> = regex.capture_names()
+            .flatten()
+            .filter(|name| Some(*name) != value_group)
+            .map(|name| name.to_string())
+            .collect();
+        // the tag values are only known when a line matches, but the name and the tag names can be validated upfront
+        let tags = tag_names.iter().map(|name| (name.clone(), String::new())).collect();
+        MetricDescription::from(settings.name.clone(), description(settings), tags)?;
+        let value_unit = settings.unit.to_measurement_units();
+        Ok(LogMetric {
+            settings: settings.clone(),
+            regex,
+            tag_names,
+            value_unit,
+            histogram_settings: histogram_settings(value_unit),
+        })
+    }
+
+    fn value(&self, value: Option<&str>) -> std::result::Result<Option<f64>, String> {
+        value.map(|value| value.parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite() && *value >= 0.0)
+            .ok_or_else(|| format!("invalid value '{}'", value)))
+            .transpose()
+    }
+}
+
+fn description(settings: &LogMetricSettings) -> String {
+    if settings.description.is_empty() { format!("{} read from a log file.", settings.name) } else { settings.description.clone() }
+}
+
+/// The histograms are recorded in nanoseconds up to a day for the time units, in bytes for the information
+/// units, and as they are otherwise.
+fn histogram_settings(value_unit: &'static MeasurementUnit) -> HistogramSettings {
+    match value_unit.dimension() {
+        Dimension::Time => HistogramSettings::from(1, 86_400 * 1_000_000_000, 2, &MEASUREMENT_UNITS.time.nanos),
+        Dimension::Information => HistogramSettings::from(1, 1_000_000_000_000, 2, &MEASUREMENT_UNITS.information.bytes),
+        _ => HistogramSettings::from(1, 1_000_000_000_000, 2, value_unit),
+    }
+}
+
+pub struct LogTailMonitor {
+    path: PathBuf,
+    file_tag: String,
+    from_beginning: bool,
+    interval: Duration,
+    metrics: Vec<LogMetric>,
+    tailed: Option<TailedFile>,
+    recorders: MetricRecorders,
+}
+
+impl LogTailMonitor {
+    pub fn new(config: &LogTailSettings) -> Result<LogTailMonitor> {
+        Ok(LogTailMonitor {
+            path: PathBuf::from(&config.path),
+            file_tag: to_tag_value(&config.path),
+            from_beginning: config.from_beginning,
+            interval: Duration::from_millis(config.interval_millis),
+            metrics: config.metrics.iter().map(LogMetric::from).collect::<Result<Vec<LogMetric>>>()?,
+            tailed: None,
+            recorders: MetricRecorders::new(),
+        })
+    }
+
+    pub async fn start(mut self) {
+        loop {
+            if let Err(error) = self.collect().await {
+                warn!("Log tail collector failed to collect {}. Reason: {}", self.path.display(), error);
+            }
+            tokio::time::delay_for(self.interval).await;
+        }
+    }
+
+    async fn collect(&mut self) -> Result<()> {
+        let (lines, reopened) = self.read_lines().await?;
+        let mut invalid_lines = 0;
+        let mut counters: HashMap<MetricId, (CounterBuilder, u64)> = HashMap::new();
+        let mut histograms: HashMap<MetricId, (HistogramBuilder, Vec<u64>)> = HashMap::new();
+        for line in lines.iter() {
+            if let Err(error) = self.match_line(line, &mut counters, &mut histograms) {
+                debug!("Line of {} discarded. Reason: {}", self.path.display(), error);
+                invalid_lines += 1;
+            }
+        }
+        for (_, (builder, increment)) in counters {
+            self.recorders.counter(builder, increment).await?;
+        }
+        for (_, (builder, values)) in histograms {
+            self.recorders.histogram(builder, &values).await?;
+        }
+        let counters = [
+            ("log_tail_lines_total", "Lines read from the log file.", lines.len() as u64),
+            ("log_tail_invalid_lines_total", "Lines of the log file matching a metric with an invalid tag or value.", invalid_lines),
+            ("log_tail_reopens_total", "Times the log file has been reopened after being rotated or truncated.", reopened),
+        ];
+        for (name, description, increment) in counters.iter() {
+            let builder = CounterBuilder::new(name.to_string(), description.to_string())
+                .with_tags("file".to_string(), self.file_tag.clone());
+            self.recorders.counter(builder, *increment).await?;
+        }
+        Ok(())
+    }
+
+    /// Aggregates the updates of the metrics matching the line. The tag values are validated by
+    /// the description of the metric, so a line with an invalid one doesn't update any metric.
+    fn match_line(&self, line: &str, counters: &mut HashMap<MetricId, (CounterBuilder, u64)>, histograms: &mut HashMap<MetricId, (HistogramBuilder, Vec<u64>)>) -> Result<()> {
+        let mut updates = vec![];
+        for metric in self.metrics.iter() {
+            let captures = match metric.regex.captures(line) {
+                Some(captures) => captures,
+                None => continue,
+            };
+            let tags = metric.tag_names.iter()
+                .map(|name| (name.clone(), captures.name(name).map(|tag| tag.as_str()).unwrap_or_default().to_string()));
+            let value_group = metric.settings.value.as_ref().and_then(|name| captures.name(name)).map(|value| value.as_str());
+            let value = metric.value(value_group).map_err(Error::Msg)?;
+            let (name, description) = (metric.settings.name.clone(), description(&metric.settings));
+            let update = match metric.settings.metric_type {
+                LogMetricType::Counter => {
+                    let builder = tags.fold(CounterBuilder::new(name, description), |builder, (name, value)| builder.with_tags(name, value));
+                    LogUpdate::Counter(builder, value.map(|value| value.round() as u64).unwrap_or(1))
+                },
+                LogMetricType::Histogram => {
+                    let builder = tags.fold(HistogramBuilder::new(name, description), |builder, (name, value)| builder.with_tags(name, value))
+                        .with_settings(metric.histogram_settings.clone());
+                    let value = value.ok_or_else(|| Error::Msg(format!("Histogram {} without value", metric.settings.name)))?;
+                    let value = measurement_unit::convert(value, metric.value_unit, metric.histogram_settings.measurement_unit);
+                    LogUpdate::Histogram(builder, (value.round() as u64).clamp(metric.histogram_settings.low, metric.histogram_settings.high))
+                },
+            };
+            updates.push(update);
+        }
+        let mut metric_ids = Vec::with_capacity(updates.len());
+        for update in updates.iter() {
+            let metric_description = match update {
+                LogUpdate::Counter(builder, _) => builder.metric_description()?,
+                LogUpdate::Histogram(builder, _) => builder.metric_description()?,
+            };
+            metric_ids.push(metric_description.id);
+        }
+        for (metric_id, update) in metric_ids.into_iter().zip(updates) {
+            match update {
+                LogUpdate::Counter(builder, increment) => counters.entry(metric_id).or_insert((builder, 0)).1 += increment,
+                LogUpdate::Histogram(builder, value) => histograms.entry(metric_id).or_insert((builder, vec![])).1.push(value),
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the lines written since the previous collection, and how many times the file was reopened.
+    async fn read_lines(&mut self) -> Result<(Vec<String>, u64)> {
+        let metadata = match fs::metadata(&self.path).await {
+            Ok(metadata) => Some(metadata),
+            Err(error) if error.kind() == ErrorKind::NotFound => None,
+            Err(error) => return Err(error.into()),
+        };
+        let mut lines = vec![];
+        let mut reopened = 0;
+        if let Some(tailed) = self.tailed.as_mut() {
+            if let Some(metadata) = &metadata {
+                if metadata.ino() == tailed.inode && metadata.len() < tailed.offset {
+                    tailed.rewind().await?;
+                    reopened += 1;
+                }
+            }
+            // the lines left on a rotated file are read before following the new one
+            tailed.read_lines(&mut lines).await?;
+            if let Some(metadata) = &metadata {
+                if metadata.ino() != tailed.inode {
+                    tailed.take_partial_line(&mut lines);
+                    self.tailed = None;
+                    reopened += 1;
+                }
+            }
+        }
+        if let (None, Some(_)) = (&self.tailed, &metadata) {
+            // only the first time the file is opened it's read from the end, a new file after a rotation is read completely
+            let from_end = !self.from_beginning && reopened == 0;
+            let mut tailed = TailedFile::open(&self.path, from_end).await?;
+            self.from_beginning = true;
+            tailed.read_lines(&mut lines).await?;
+            self.tailed = Some(tailed);
+        }
+        Ok((lines, reopened))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::path::Path;
+
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    fn metric(name: &str, metric_type: LogMetricType, regex: &str, value: Option<&str>) -> LogMetricSettings {
+        LogMetricSettings {
+            name: name.into(),
+            description: String::new(),
+            metric_type,
+            regex: regex.into(),
+            value: value.map(|value| value.to_string()),
+            unit: crate::settings::TimeUnitsSettings::TimeSeconds,
+        }
+    }
+
+    fn settings(path: &Path) -> LogTailSettings {
+        LogTailSettings {
+            path: path.to_string_lossy().into_owned(),
+            interval_millis: 1_000,
+            from_beginning: false,
+            metrics: vec![
+                metric("log_tail_test_requests_total", LogMetricType::Counter, r#""(?P<method>[A-Z]+) [^"]*" (?P<status>\d+)"#, None),
+                metric("log_tail_test_request_duration_seconds", LogMetricType::Histogram, r#""(?P<method>[A-Z]+) [^"]*" \d+ (?P<duration>[\d.]+)"#, Some("duration")),
+            ],
+        }
+    }
+
+    fn append(path: &PathBuf, text: &str) {
+        std::fs::OpenOptions::new().create(true).append(true).open(path).unwrap()
+            .write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_follow_rotations_and_truncations() {
+        let directory = std::env::temp_dir().join(format!("rusty-advisor-log-tail-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("access.log");
+        append(&path, "GET / 200\n");
+        let mut monitor = LogTailMonitor::new(&settings(&path)).unwrap();
+
+        // the lines already written are skipped
+        assert_eq!(aw!(monitor.read_lines()).unwrap(), (vec![], 0));
+        append(&path, "GET /a 200\nGET /b");
+        assert_eq!(aw!(monitor.read_lines()).unwrap(), (vec!["GET /a 200".to_string()], 0));
+        append(&path, " 404\n");
+        assert_eq!(aw!(monitor.read_lines()).unwrap(), (vec!["GET /b 404".to_string()], 0));
+
+        // rotated: the lines left on the rotated file are read before the new file
+        append(&path, "GET /c 200\nGET /d");
+        std::fs::rename(&path, directory.join("access.log.1")).unwrap();
+        assert_eq!(aw!(monitor.read_lines()).unwrap(), (vec!["GET /c 200".to_string()], 0));
+        append(&path, "GET /e 500\n");
+        assert_eq!(aw!(monitor.read_lines()).unwrap(), (vec!["GET /d".to_string(), "GET /e 500".to_string()], 1));
+
+        // truncated: read again from the beginning
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(aw!(monitor.read_lines()).unwrap(), (vec![], 1));
+        append(&path, "GET /f 200\n");
+        assert_eq!(aw!(monitor.read_lines()).unwrap(), (vec!["GET /f 200".to_string()], 0));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_match_lines() {
+        let monitor = LogTailMonitor::new(&settings(&PathBuf::from("/nonexistent/access.log"))).unwrap();
+        let mut counters = HashMap::new();
+        let mut histograms = HashMap::new();
+        assert!(monitor.match_line(r#"10.0.0.1 "GET /users HTTP/1.1" 200 0.250"#, &mut counters, &mut histograms).is_ok());
+        assert!(monitor.match_line(r#"10.0.0.1 "GET /users HTTP/1.1" 200 1.5"#, &mut counters, &mut histograms).is_ok());
+        assert!(monitor.match_line(r#"10.0.0.1 "POST /users HTTP/1.1" 201"#, &mut counters, &mut histograms).is_ok());
+        assert!(monitor.match_line("unrelated line", &mut counters, &mut histograms).is_ok());
+        assert_eq!(counters.len(), 2);
+        assert_eq!(counters.values().map(|(_, increment)| *increment).sum::<u64>(), 3);
+        let values: Vec<Vec<u64>> = histograms.values().map(|(_, values)| values.clone()).collect();
+        assert_eq!(values, vec![vec![250_000_000, 1_500_000_000]]);
+    }
+
+    #[test]
+    fn test_invalid_metrics_fail() {
+        let path = PathBuf::from("/nonexistent/access.log");
+        let mut config = settings(&path);
+        config.metrics = vec![metric("log_tail_test_durations", LogMetricType::Histogram, r"took (?P<duration>\d+)", None)];
+        assert!(LogTailMonitor::new(&config).is_err());
+        config.metrics = vec![metric("log_tail_test_durations", LogMetricType::Histogram, r"took (?P<duration>\d+)", Some("value"))];
+        assert!(LogTailMonitor::new(&config).is_err());
+        config.metrics = vec![metric("log-tail-test", LogMetricType::Counter, r"took", None)];
+        assert!(LogTailMonitor::new(&config).is_err());
+        config.metrics = vec![metric("log_tail_test_total", LogMetricType::Counter, r"took (", None)];
+        assert!(LogTailMonitor::new(&config).is_err());
+
+        // a tag value out of [a-zA-Z0-9-_./]* discards the line
+        config.metrics = vec![metric("log_tail_test_total", LogMetricType::Counter, r"user (?P<user>\S+)", None)];
+        let monitor = LogTailMonitor::new(&config).unwrap();
+        let (mut counters, mut histograms) = (HashMap::new(), HashMap::new());
+        assert!(monitor.match_line("user john", &mut counters, &mut histograms).is_ok());
+        assert!(monitor.match_line("user john@example", &mut counters, &mut histograms).is_err());
+        assert_eq!(counters.len(), 1);
+    }
+}
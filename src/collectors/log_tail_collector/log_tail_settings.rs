@@ -0,0 +1,50 @@
+use crate::settings::TimeUnitsSettings;
+
+/// A log file followed across rotations and truncations, whose new lines are read every `interval_millis`
+/// and matched against the regexes of its `metrics`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct LogTailSettings {
+    pub path: String,
+    #[serde(default = "default_interval_millis")]
+    pub interval_millis: u64,
+    /// Reads the lines already on the file when it's first opened, instead of only the ones written afterwards.
+    #[serde(default)]
+    pub from_beginning: bool,
+    pub metrics: Vec<LogMetricSettings>,
+}
+
+/// A metric updated by every line matching `regex`. The named capture groups are its tags, but
+/// the `value` one, which is the value of the histograms or the increment of the counters.
+#[derive(Debug, Deserialize, Clone)]
+pub struct LogMetricSettings {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(rename = "type", default)]
+    pub metric_type: LogMetricType,
+    pub regex: String,
+    #[serde(default)]
+    pub value: Option<String>,
+    /// Unit of the captured value. The histograms are recorded in nanoseconds for the time units and in bytes
+    /// for the information units, since their values have to be integers.
+    #[serde(default = "default_unit")]
+    pub unit: TimeUnitsSettings,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LogMetricType {
+    /// Incremented by 1 on every matching line, or by the `value` capture group when it's configured.
+    #[default]
+    Counter,
+    /// Records the `value` capture group of every matching line.
+    Histogram,
+}
+
+fn default_interval_millis() -> u64 {
+    1_000
+}
+
+fn default_unit() -> TimeUnitsSettings {
+    TimeUnitsSettings::None
+}
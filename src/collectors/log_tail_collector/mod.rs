@@ -0,0 +1,2 @@
+pub mod log_tail_monitor;
+pub mod log_tail_settings;
@@ -0,0 +1,469 @@
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use hdrhistogram::Histogram as HdrHistogram;
+
+use crate::collectors::parsers::{MetricFamily, MetricType, Sample};
+use crate::collectors::parsers::prometheus_text;
+use crate::errors::{Error, Result};
+use crate::metrics::counter::{CounterBuilder, CounterRecorder};
+use crate::metrics::gauge::{GaugeBuilder, GaugeRecorder};
+use crate::metrics::histogram::{HistogramBuilder, HistogramRecorder, HistogramSettings};
+use crate::metrics::measurement_unit::{MEASUREMENT_UNITS, MeasurementUnit};
+use crate::metrics::metric::{MetricDescription, MetricId, MetricKind};
+use crate::metrics::registry;
+
+/// Cumulative count of every bucket of a histogram, by upper bound.
+type CumulativeBuckets = Vec<(f64, u64)>;
+
+/// Keeps the recorders of the metrics published by a collector, which are registered the first
+/// time they are seen, e.g. when a new CPU or device shows up, and again when they expired.
+#[derive(Default)]
+pub struct MetricRecorders {
+    counters: HashMap<MetricId, (CounterRecorder, u64)>,
+    gauges: HashMap<MetricId, GaugeRecorder>,
+    histograms: HashMap<MetricId, CumulativeBuckets>,
+    /// The scaled total of the counters which don't have to be integers, and the fraction of it not recorded yet.
+    fractional_counters: HashMap<MetricId, (f64, f64)>,
+    /// The series recorded from families by the last `record_families`.
+    family_series: HashMap<MetricId, (MetricKind, MetricDescription)>,
+}
+
+impl MetricRecorders {
+    pub fn new() -> MetricRecorders {
+        MetricRecorders::default()
+    }
+
+    /// Publishes a counter the kernel keeps as a running total, adding the increment since the
+    /// total seen on the previous collection.
+    pub async fn counter_total(&mut self, builder: CounterBuilder, total: u64) -> Result<()> {
+        let metric_id = builder.metric_description()?.id;
+        let (recorder, last_total) = match self.counters.entry(metric_id) {
+            Entry::Occupied(entry) if !entry.get().0.is_expired() => entry.into_mut(),
+            Entry::Occupied(mut entry) => {
+                entry.get_mut().0 = builder.build().await?;
+                entry.into_mut()
+            },
+            Entry::Vacant(entry) => entry.insert((builder.build().await?, 0)),
+        };
+        recorder.add(increment(*last_total, total));
+        *last_total = total;
+        Ok(())
+    }
+
+    /// Publishes a counter another process keeps as a running total, which doesn't have to be an integer,
+    /// multiplied by `scale`. The fraction of every increment left over is added to the next one.
+    pub async fn fractional_counter_total(&mut self, builder: CounterBuilder, scale: f64, total: f64) -> Result<()> {
+        let metric_id = builder.metric_description()?.id;
+        let (last_total, fraction) = self.fractional_counters.entry(metric_id).or_default();
+        let total = total * scale;
+        let increment = if total >= *last_total { total - *last_total } else { total } + *fraction;
+        *last_total = total;
+        *fraction = increment.fract();
+        self.counter(builder, increment.trunc() as u64).await
+    }
+
+    pub async fn counter(&mut self, builder: CounterBuilder, increment: u64) -> Result<()> {
+        let metric_id = builder.metric_description()?.id;
+        let (recorder, _) = match self.counters.entry(metric_id) {
+            Entry::Occupied(entry) if !entry.get().0.is_expired() => entry.into_mut(),
+            Entry::Occupied(mut entry) => {
+                entry.get_mut().0 = builder.build().await?;
+                entry.into_mut()
+            },
+            Entry::Vacant(entry) => entry.insert((builder.build().await?, 0)),
+        };
+        recorder.add(increment);
+        Ok(())
+    }
+
+    pub async fn gauge(&mut self, builder: GaugeBuilder, value: f64) -> Result<()> {
+        let metric_id = builder.metric_description()?.id;
+        let recorder = match self.gauges.entry(metric_id) {
+            Entry::Occupied(entry) if !entry.get().is_expired() => entry.into_mut(),
+            Entry::Occupied(mut entry) => {
+                entry.insert(builder.build().await?);
+                entry.into_mut()
+            },
+            Entry::Vacant(entry) => entry.insert(builder.build().await?),
+        };
+        recorder.set(value);
+        Ok(())
+    }
+
+    /// Removes a gauge which isn't published anymore from the registry, e.g. the one of a value which changed
+    /// when the value is a tag.
+    pub async fn remove_gauge(&mut self, builder: GaugeBuilder) -> Result<()> {
+        let metric_description = builder.metric_description()?;
+        self.gauges.remove(&metric_description.id);
+        registry::global_registry().remove(&MetricKind::Gauge, &metric_description).await;
+        Ok(())
+    }
+
+    /// Records the values observed since the previous collection on a new recorder, which hands them
+    /// to the histogram when it's dropped at the end of the collection.
+    pub async fn histogram(&mut self, builder: HistogramBuilder, values: &[u64]) -> Result<()> {
+        let mut recorder = builder.build().await?;
+        for value in values {
+            recorder.record(*value)?;
+        }
+        Ok(())
+    }
+
+    /// Publishes a histogram produced by another process as cumulative buckets, recording the observations
+    /// added to every bucket since the previous collection at the upper bound of the bucket, multiplied by `scale`.
+    /// The observations of the `+Inf` bucket are recorded at the highest value of the histogram.
+    ///
+    /// A recorder only hands its observations to the histogram when it records again or it's dropped,
+    /// so a new one is used on every collection instead of keeping one idle between collections.
+    pub async fn histogram_buckets(&mut self, builder: HistogramBuilder, scale: f64, buckets: CumulativeBuckets) -> Result<()> {
+        let metric_id = builder.metric_description()?.id;
+        let high = builder.settings.high;
+        let equivalent_values = HdrHistogram::<u64>::new_with_bounds(builder.settings.low, high, builder.settings.precision)
+            .map_err(|error| Error::Msg(format!("Error creating Histogram. Reason: {}", error)))?;
+        let mut recorder: HistogramRecorder = builder.build().await?;
+        let last_buckets = self.histograms.entry(metric_id).or_default();
+        let bounds_changed = last_buckets.len() != buckets.len()
+            || last_buckets.iter().zip(buckets.iter()).any(|((last_bound, last_count), (bound, count))| last_bound != bound || last_count > count);
+        if bounds_changed {
+            last_buckets.clear();
+        }
+        let mut previous_increment = 0;
+        for (i, (bound, count)) in buckets.iter().enumerate() {
+            let increment = increment(last_buckets.get(i).map(|(_, last_count)| *last_count).unwrap_or(0), *count);
+            let observations = increment.saturating_sub(previous_increment);
+            previous_increment = increment;
+            if observations > 0 {
+                let value = if bound.is_finite() { highest_value_within(&equivalent_values, ((bound * scale).round() as u64).min(high)) } else { high };
+                recorder.record_n(value, observations)?;
+            }
+        }
+        *last_buckets = buckets;
+        Ok(())
+    }
+
+    /// Publishes the families parsed from the output of another process:
+    ///   - the counters as running totals, in nanoseconds when their name ends with `_seconds_total`.
+    ///   - the gauges and the untyped metrics as gauges.
+    ///   - the histograms from their buckets, with the values in nanoseconds when their name ends with `_seconds`.
+    ///   - the quantiles, the sum and the count of the summaries as gauges.
+    ///
+    /// The label values are adapted to the valid tag values. Nothing is recorded when any of the families
+    /// is invalid, and the series recorded by the previous call which aren't on the families anymore are removed.
+    pub async fn record_families(&mut self, families: &[MetricFamily]) -> Result<()> {
+        self.record_series(FamilySeries::from(families)?).await
+    }
+
+    pub async fn record_series(&mut self, family_series: FamilySeries) -> Result<()> {
+        let mut recorded = HashMap::new();
+        for (metric_description, series) in family_series.series {
+            recorded.insert(metric_description.id, (series.kind(), metric_description));
+            let result = match series {
+                SeriesValue::Counter(builder, scale, total) => self.fractional_counter_total(builder, scale, total).await,
+                SeriesValue::Gauge(builder, value) => self.gauge(builder, value).await,
+                SeriesValue::Histogram(builder, scale, buckets) => self.histogram_buckets(builder, scale, buckets).await,
+            };
+            if let Err(error) = result {
+                // the series recorded so far are removed when they aren't recorded again
+                self.family_series.extend(recorded);
+                return Err(error);
+            }
+        }
+        for (metric_id, (kind, metric_description)) in std::mem::replace(&mut self.family_series, recorded) {
+            if !self.family_series.contains_key(&metric_id) {
+                self.remove(kind, &metric_description).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes every series recorded from families, e.g. when the file they were read from is deleted.
+    pub async fn remove_families(&mut self) {
+        for (_, (kind, metric_description)) in std::mem::take(&mut self.family_series) {
+            self.remove(kind, &metric_description).await;
+        }
+    }
+
+    async fn remove(&mut self, kind: MetricKind, metric_description: &MetricDescription) {
+        self.counters.remove(&metric_description.id);
+        self.fractional_counters.remove(&metric_description.id);
+        self.gauges.remove(&metric_description.id);
+        self.histograms.remove(&metric_description.id);
+        registry::global_registry().remove(&kind, metric_description).await;
+    }
+}
+
+/// The series of the families parsed from the output of another process, checked before any of them is recorded.
+pub struct FamilySeries {
+    series: Vec<(MetricDescription, SeriesValue)>,
+}
+
+enum SeriesValue {
+    Counter(CounterBuilder, f64, f64),
+    Gauge(GaugeBuilder, f64),
+    Histogram(HistogramBuilder, f64, CumulativeBuckets),
+}
+
+impl SeriesValue {
+    fn kind(&self) -> MetricKind {
+        match self {
+            SeriesValue::Counter(..) => MetricKind::Counter,
+            SeriesValue::Gauge(..) => MetricKind::Gauge,
+            SeriesValue::Histogram(..) => MetricKind::Histogram,
+        }
+    }
+
+    fn metric_description(&self) -> Result<MetricDescription> {
+        match self {
+            SeriesValue::Counter(builder, ..) => builder.metric_description(),
+            SeriesValue::Gauge(builder, _) => builder.metric_description(),
+            SeriesValue::Histogram(builder, ..) => builder.metric_description(),
+        }
+    }
+}
+
+impl FamilySeries {
+    /// Fails on the counters which aren't finite or are negative, the invalid names, tags or buckets, and the
+    /// series repeated on the families.
+    pub fn from(families: &[MetricFamily]) -> Result<FamilySeries> {
+        let mut series = vec![];
+        for family in families {
+            let description = if family.help.is_empty() { format!("{} read from an external source.", family.name) } else { family.help.clone() };
+            match family.metric_type {
+                MetricType::Counter => {
+                    let (measurement_unit, scale) = counter_unit(&family.name);
+                    for sample in family.samples.iter() {
+                        if !(sample.value >= 0.0 && sample.value.is_finite()) {
+                            return Err(Error::Msg(format!("Counter {} has an invalid value {}", sample.name, sample.value)));
+                        }
+                        let builder = with_labels(CounterBuilder::new(sample.name.clone(), description.clone()), &sample.labels, CounterBuilder::with_tags)
+                            .with_measurement_unit(measurement_unit);
+                        series.push(SeriesValue::Counter(builder, scale, sample.value));
+                    }
+                },
+                MetricType::Gauge | MetricType::Untyped | MetricType::Summary => {
+                    for sample in family.samples.iter() {
+                        let builder = with_labels(GaugeBuilder::new(sample.name.clone(), description.clone()), &sample.labels, GaugeBuilder::with_tags);
+                        series.push(SeriesValue::Gauge(builder, sample.value));
+                    }
+                },
+                MetricType::Histogram => {
+                    let (measurement_unit, scale, high) = histogram_unit(&family.name);
+                    let settings = HistogramSettings::from(1, high, 2, measurement_unit);
+                    for (labels, buckets) in group_buckets(family)? {
+                        let builder = with_labels(HistogramBuilder::new(family.name.clone(), description.clone()), &labels, HistogramBuilder::with_tags)
+                            .with_settings(settings.clone());
+                        series.push(SeriesValue::Histogram(builder, scale, buckets));
+                    }
+                },
+            }
+        }
+        let mut metric_ids = HashSet::new();
+        let series = series.into_iter()
+            .map(|series| {
+                let metric_description = series.metric_description()?;
+                if !metric_ids.insert(metric_description.id) {
+                    return Err(Error::Msg(format!("Series {} with tags {:?} is repeated", metric_description.name(), metric_description.tags())));
+                }
+                Ok((metric_description, series))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(FamilySeries { series })
+    }
+
+    pub fn metric_ids(&self) -> impl Iterator<Item=MetricId> + '_ {
+        self.series.iter().map(|(metric_description, _)| metric_description.id)
+    }
+}
+
+fn with_labels<B>(builder: B, labels: &BTreeMap<String, String>, with_tags: fn(B, String, String) -> B) -> B {
+    labels.iter().fold(builder, |builder, (name, value)| with_tags(builder, name.clone(), to_tag_value(value)))
+}
+
+/// The counters in seconds are recorded in nanoseconds, so their fractions aren't lost when they are recorded as integers.
+fn counter_unit(name: &str) -> (&'static MeasurementUnit, f64) {
+    if name.ends_with("_seconds_total") {
+        (&MEASUREMENT_UNITS.time.nanos, 1e9)
+    } else {
+        (&MEASUREMENT_UNITS.none, 1.0)
+    }
+}
+
+/// The histograms in seconds are recorded in nanoseconds, up to a day, since the values recorded have to be integers.
+fn histogram_unit(name: &str) -> (&'static MeasurementUnit, f64, u64) {
+    if name.ends_with("_seconds") {
+        (&MEASUREMENT_UNITS.time.nanos, 1e9, 86_400 * 1_000_000_000)
+    } else {
+        (&MEASUREMENT_UNITS.none, 1.0, 1_000_000_000_000)
+    }
+}
+
+/// The histograms are exported with the highest value equivalent to every recorded value, so the
+/// observations of a bucket are recorded at the highest value that is still exported within its bound.
+fn highest_value_within(equivalent_values: &HdrHistogram<u64>, bound: u64) -> u64 {
+    if equivalent_values.highest_equivalent(bound) == bound {
+        bound
+    } else {
+        equivalent_values.lowest_equivalent(bound).saturating_sub(1)
+    }
+}
+
+/// Groups the `_bucket` samples of a histogram by their labels, without `le`, sorted by upper bound.
+fn group_buckets(family: &MetricFamily) -> Result<Vec<(BTreeMap<String, String>, CumulativeBuckets)>> {
+    let bucket_name = format!("{}_bucket", family.name);
+    let mut groups: BTreeMap<BTreeMap<String, String>, CumulativeBuckets> = BTreeMap::new();
+    for Sample { name, labels, value } in family.samples.iter().filter(|sample| sample.name == bucket_name) {
+        let mut labels = labels.clone();
+        let bound = labels.remove("le")
+            .and_then(|bound| prometheus_text::parse_value(&bound).ok())
+            .ok_or_else(|| Error::Msg(format!("Bucket of {} without a valid le label", name)))?;
+        groups.entry(labels).or_default().push((bound, *value as u64));
+    }
+    for buckets in groups.values_mut() {
+        buckets.sort_by(|(bound, _), (other_bound, _)| bound.partial_cmp(other_bound).unwrap());
+    }
+    Ok(groups.into_iter().collect())
+}
+
+/// Replaces the characters which aren't valid on a tag value, like spaces or colons, with underscores,
+/// since the names the kernel gives to devices aren't restricted.
+pub fn to_tag_value(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' || c == '/' { c } else { '_' })
+        .collect()
+}
+
+/// A total lower than the previous one means the counter was reset (e.g. a device was re-attached),
+/// so the whole total is the increment.
+fn increment(last_total: u64, total: u64) -> u64 {
+    if total >= last_total {
+        total - last_total
+    } else {
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_increment_of_running_totals() {
+        assert_eq!(increment(0, 120), 120);
+        assert_eq!(increment(120, 150), 30);
+        assert_eq!(increment(150, 150), 0);
+        // reset
+        assert_eq!(increment(150, 20), 20);
+    }
+
+    #[test]
+    fn test_group_histogram_buckets() {
+        let families = prometheus_text::parse(r#"
+# TYPE job_duration_seconds histogram
+job_duration_seconds_bucket{job="backup",le="+Inf"} 9
+job_duration_seconds_bucket{job="backup",le="0.5"} 2
+job_duration_seconds_bucket{job="backup",le="1"} 7
+job_duration_seconds_sum{job="backup"} 12.5
+job_duration_seconds_count{job="backup"} 9
+job_duration_seconds_bucket{job="cleanup",le="+Inf"} 1
+"#).unwrap();
+        let groups = group_buckets(&families[0]).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].0["job"], "backup");
+        assert_eq!(groups[0].1, vec![(0.5, 2), (1.0, 7), (f64::INFINITY, 9)]);
+        assert_eq!(groups[1].1, vec![(f64::INFINITY, 1)]);
+    }
+
+    #[test]
+    fn test_highest_value_within_bound() {
+        let equivalent_values = HdrHistogram::<u64>::new_with_bounds(1, 86_400_000_000_000, 2).unwrap();
+        let value = highest_value_within(&equivalent_values, 60_000_000_000);
+        assert!(value <= 60_000_000_000);
+        assert!(equivalent_values.highest_equivalent(value) <= 60_000_000_000);
+        assert!(value > 59_000_000_000);
+        assert_eq!(highest_value_within(&equivalent_values, 5), 5);
+    }
+
+    #[test]
+    fn test_remove_gauge() {
+        let builder = || GaugeBuilder::new("recorders_test_governor_info".into(), "some description".into())
+            .with_tags("governor".to_string(), "powersave".to_string());
+        let metric_id = builder().metric_description().unwrap().id;
+        let registered = || registry::global_registry().gauges().iter()
+            .any(|gauge| tokio_test::block_on(gauge.read()).metric_description().id == metric_id);
+        let mut recorders = MetricRecorders::new();
+
+        tokio_test::block_on(recorders.gauge(builder(), 1.0)).unwrap();
+        assert!(registered());
+        tokio_test::block_on(recorders.remove_gauge(builder())).unwrap();
+        assert!(!registered());
+        assert!(recorders.gauges.is_empty());
+    }
+
+    fn registered_counter(name: &str) -> Option<u64> {
+        registry::global_registry().counters().iter()
+            .map(|counter| tokio_test::block_on(counter.write()))
+            .find(|counter| counter.metric_description().name() == name)
+            .map(|mut counter| counter.sample().value())
+    }
+
+    fn registered_jobs(name: &str) -> Vec<String> {
+        let mut jobs: Vec<String> = registry::global_registry().counters().iter()
+            .map(|counter| tokio_test::block_on(counter.read()).metric_description().clone())
+            .filter(|metric_description| metric_description.name() == name)
+            .filter_map(|metric_description| metric_description.tags().get("job").cloned())
+            .collect();
+        jobs.sort();
+        jobs
+    }
+
+    fn record(recorders: &mut MetricRecorders, text: &str) -> Result<()> {
+        tokio_test::block_on(recorders.record_families(&prometheus_text::parse(text).unwrap()))
+    }
+
+    #[test]
+    fn test_fractional_counters_keep_their_fractions() {
+        let mut recorders = MetricRecorders::new();
+
+        record(&mut recorders, "# TYPE recorders_test_jobs_total counter\nrecorders_test_jobs_total 1.5\n").unwrap();
+        assert_eq!(registered_counter("recorders_test_jobs_total"), Some(1));
+        record(&mut recorders, "# TYPE recorders_test_jobs_total counter\nrecorders_test_jobs_total 2\n").unwrap();
+        assert_eq!(registered_counter("recorders_test_jobs_total"), Some(1));
+
+        record(&mut recorders, "# TYPE recorders_test_cpu_seconds_total counter\nrecorders_test_cpu_seconds_total 0.25\n").unwrap();
+        assert_eq!(registered_counter("recorders_test_cpu_seconds_total"), Some(250_000_000));
+    }
+
+    #[test]
+    fn test_nothing_is_recorded_from_invalid_families() {
+        let mut recorders = MetricRecorders::new();
+        let text = "# TYPE recorders_test_invalid_gauge gauge\nrecorders_test_invalid_gauge 3\n\
+                    # TYPE recorders_test_invalid_total counter\nrecorders_test_invalid_total -1\n";
+        assert!(record(&mut recorders, text).is_err());
+        assert!(!registry::global_registry().gauges().iter()
+            .any(|gauge| tokio_test::block_on(gauge.read()).metric_description().name() == "recorders_test_invalid_gauge"));
+
+        let repeated = "# TYPE recorders_test_repeated_total counter\nrecorders_test_repeated_total 1\nrecorders_test_repeated_total 2\n";
+        assert!(record(&mut recorders, repeated).is_err());
+        assert_eq!(registered_counter("recorders_test_repeated_total"), None);
+    }
+
+    #[test]
+    fn test_series_gone_from_the_families_are_removed() {
+        let mut recorders = MetricRecorders::new();
+        let text = "# TYPE recorders_test_removed_total counter\nrecorders_test_removed_total{job=\"a\"} 1\nrecorders_test_removed_total{job=\"b\"} 2\n";
+        record(&mut recorders, text).unwrap();
+        assert_eq!(registered_jobs("recorders_test_removed_total"), vec!["a", "b"]);
+        record(&mut recorders, "# TYPE recorders_test_removed_total counter\nrecorders_test_removed_total{job=\"b\"} 3\n").unwrap();
+        assert_eq!(registered_jobs("recorders_test_removed_total"), vec!["b"]);
+        tokio_test::block_on(recorders.remove_families());
+        assert!(registered_jobs("recorders_test_removed_total").is_empty());
+        assert!(recorders.counters.is_empty());
+    }
+
+    #[test]
+    fn test_to_tag_value() {
+        assert_eq!(to_tag_value("xhci_hcd"), "xhci_hcd");
+        assert_eq!(to_tag_value("ehci_hcd:usb1, uhci_hcd:usb2"), "ehci_hcd_usb1__uhci_hcd_usb2");
+    }
+}
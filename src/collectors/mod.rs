@@ -1 +1,16 @@
+pub mod collectors_settings;
+pub mod cpufreq_collector;
+pub mod exec_collector;
 pub mod hiccups_collector;
+pub mod hwmon_collector;
+pub mod interrupts_collector;
+pub mod log_tail_collector;
+pub mod metric_recorders;
+pub mod numa_collector;
+pub mod parsers;
+pub mod probe_collector;
+pub mod schedstat_collector;
+pub mod sockets_collector;
+pub mod sysfs;
+pub mod textfile_collector;
+pub mod timex_collector;
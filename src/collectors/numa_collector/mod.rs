@@ -0,0 +1,2 @@
+pub mod numa_monitor;
+pub mod numa_settings;
@@ -0,0 +1,227 @@
+//! Collects the memory and the allocation stats of every NUMA node from `/sys/devices/system/node/node*`,
+//! and the huge pages of every page size from `/sys/kernel/mm/hugepages`.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::fs;
+
+use crate::collectors::collectors_settings::CollectorsSettings;
+use crate::collectors::metric_recorders::{MetricRecorders, to_tag_value};
+use crate::collectors::sysfs;
+use crate::errors::{Error, Result};
+use crate::metrics::counter::CounterBuilder;
+use crate::metrics::gauge::GaugeBuilder;
+use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
+
+/// Huge pages of a page size, e.g. `2048kB`. The reserved ones are only accounted globally.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HugepagesStats {
+    pub size: String,
+    pub total: u64,
+    pub free: u64,
+    pub reserved: Option<u64>,
+    pub surplus: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeStats {
+    pub node: String,
+    /// Memory of the node by type (e.g. `mem_free` or `active_anon`), in bytes.
+    pub meminfo: Vec<(String, u64)>,
+    /// Pages allocated on the node, by kind of allocation (e.g. `numa_hit` or `other_node`).
+    pub numastat: Vec<(String, u64)>,
+    pub hugepages: Vec<HugepagesStats>,
+}
+
+pub struct NumaMonitor {
+    nodes_path: PathBuf,
+    hugepages_path: PathBuf,
+    interval: Duration,
+    recorders: MetricRecorders,
+}
+
+impl NumaMonitor {
+    pub fn new(config: &CollectorsSettings) -> NumaMonitor {
+        NumaMonitor {
+            nodes_path: PathBuf::from(&config.sysfs_path).join("devices/system/node"),
+            hugepages_path: PathBuf::from(&config.sysfs_path).join("kernel/mm/hugepages"),
+            interval: Duration::from_millis(config.numa.interval_millis),
+            recorders: MetricRecorders::new(),
+        }
+    }
+
+    pub async fn start(mut self) {
+        loop {
+            if let Err(error) = self.collect().await {
+                warn!("NUMA collector failed to collect. Reason: {}", error);
+            }
+            tokio::time::delay_for(self.interval).await;
+        }
+    }
+
+    async fn collect(&mut self) -> Result<()> {
+        for node in read_nodes(&self.nodes_path).await? {
+            for (memory_type, bytes) in node.meminfo.iter() {
+                let builder = GaugeBuilder::new("numa_memory_bytes".into(), "Memory of the NUMA node by type.".into())
+                    .with_tags("node".to_string(), node.node.clone())
+                    .with_tags("type".to_string(), memory_type.clone())
+                    .with_measurement_unit(&MEASUREMENT_UNITS.information.bytes);
+                self.recorders.gauge(builder, *bytes as f64).await?;
+            }
+            for (allocation, pages) in node.numastat.iter() {
+                let builder = CounterBuilder::new(format!("numa_{}_pages_total", allocation.trim_start_matches("numa_")),
+                                                  format!("Pages allocated on the NUMA node counted as {}.", allocation))
+                    .with_tags("node".to_string(), node.node.clone());
+                self.recorders.counter_total(builder, *pages).await?;
+            }
+            for hugepages in node.hugepages.iter() {
+                self.record_hugepages("numa_hugepages", "on the NUMA node ", Some(&node.node), hugepages).await?;
+            }
+        }
+        for hugepages in read_hugepages(&self.hugepages_path).await? {
+            self.record_hugepages("hugepages", "", None, &hugepages).await?;
+        }
+        Ok(())
+    }
+
+    async fn record_hugepages(&mut self, prefix: &str, scope: &str, node: Option<&str>, hugepages: &HugepagesStats) -> Result<()> {
+        let states = [("total", Some(hugepages.total)), ("free", Some(hugepages.free)),
+            ("reserved", hugepages.reserved), ("surplus", Some(hugepages.surplus))];
+        for (state, pages) in states.iter() {
+            if let Some(pages) = pages {
+                let mut builder = GaugeBuilder::new(format!("{}_pages", prefix), format!("Huge pages {}by state and page size.", scope))
+                    .with_tags("state".to_string(), state.to_string())
+                    .with_tags("size".to_string(), hugepages.size.clone());
+                if let Some(node) = node {
+                    builder = builder.with_tags("node".to_string(), node.to_string());
+                }
+                self.recorders.gauge(builder, *pages as f64).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub async fn read_nodes(nodes_path: &Path) -> Result<Vec<NodeStats>> {
+    let mut nodes = Vec::new();
+    for (name, path) in sysfs::list_dir(nodes_path, "node").await? {
+        let node = name.trim_start_matches("node");
+        if node.parse::<u32>().is_err() {
+            continue;
+        }
+        nodes.push(NodeStats {
+            node: node.to_string(),
+            meminfo: parse_node_meminfo(&fs::read_to_string(path.join("meminfo")).await?)?,
+            numastat: parse_numastat(&fs::read_to_string(path.join("numastat")).await?)?,
+            hugepages: read_hugepages(&path.join("hugepages")).await?,
+        });
+    }
+    Ok(nodes)
+}
+
+/// Every page size has its own directory, e.g. `hugepages-2048kB`.
+pub async fn read_hugepages(hugepages_path: &Path) -> Result<Vec<HugepagesStats>> {
+    let mut hugepages = Vec::new();
+    for (name, path) in sysfs::list_dir(hugepages_path, "hugepages-").await? {
+        hugepages.push(HugepagesStats {
+            size: name.trim_start_matches("hugepages-").to_string(),
+            total: sysfs::read_value(path.join("nr_hugepages")).await?,
+            free: sysfs::read_value(path.join("free_hugepages")).await?,
+            reserved: sysfs::read_optional_value(path.join("resv_hugepages")).await?,
+            surplus: sysfs::read_value(path.join("surplus_hugepages")).await?,
+        });
+    }
+    Ok(hugepages)
+}
+
+/// Every line is `Node <N> <Type>: <value> kB`. The lines without unit are counts of huge pages,
+/// which are read from the `hugepages` directory of the node instead.
+pub fn parse_node_meminfo(meminfo: &str) -> Result<Vec<(String, u64)>> {
+    let mut memory = Vec::new();
+    for line in meminfo.lines().filter(|line| !line.trim().is_empty()) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            ["Node", _, memory_type, value, "kB"] => {
+                let kilobytes = value.parse::<u64>()
+                    .map_err(|_| Error::Msg(format!("Unexpected node meminfo format: {}", line)))?;
+                memory.push((to_memory_type(memory_type), kilobytes * 1024));
+            },
+            ["Node", _, _, _] => {},
+            _ => return Err(Error::Msg(format!("Unexpected node meminfo format: {}", line))),
+        }
+    }
+    Ok(memory)
+}
+
+/// `Active(anon):` is reported as `active_anon`.
+fn to_memory_type(memory_type: &str) -> String {
+    let mut snake_case = String::with_capacity(memory_type.len() + 4);
+    let chars: Vec<char> = memory_type.trim_end_matches(':').chars().collect();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 && chars[i - 1].is_ascii_lowercase() {
+            snake_case.push('_');
+        }
+        match c {
+            '(' => snake_case.push('_'),
+            ')' => {},
+            _ => snake_case.push(c.to_ascii_lowercase()),
+        }
+    }
+    to_tag_value(&snake_case)
+}
+
+/// Every line is `<allocation> <pages>`.
+pub fn parse_numastat(numastat: &str) -> Result<Vec<(String, u64)>> {
+    numastat.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            fields.next().zip(fields.next().and_then(|pages| pages.parse::<u64>().ok()))
+                .map(|(allocation, pages)| (allocation.to_string(), pages))
+                .ok_or_else(|| Error::Msg(format!("Unexpected numastat format: {}", line)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::tests::sysfs_fixture;
+
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    #[test]
+    fn test_read_nodes() {
+        let nodes = aw!(read_nodes(&sysfs_fixture("devices/system/node"))).unwrap();
+        assert_eq!(nodes.len(), 2);
+        let node = &nodes[1];
+        assert_eq!(node.node, "1");
+        assert_eq!(node.meminfo, vec![
+            ("mem_total".to_string(), 33_025_012 * 1024),
+            ("mem_free".to_string(), 12_019_372 * 1024),
+            ("mem_used".to_string(), 21_005_640 * 1024),
+            ("active_anon".to_string(), 6_002_312 * 1024),
+            ("inactive_file".to_string(), 3_870_112 * 1024),
+            ("anon_huge_pages".to_string(), 1_024_000 * 1024),
+        ]);
+        assert_eq!(node.numastat[1], ("numa_miss".to_string(), 10_293));
+        assert_eq!(node.numastat[5], ("other_node".to_string(), 285_718));
+        assert_eq!(node.hugepages[1], HugepagesStats { size: "2048kB".into(), total: 512, free: 500, reserved: None, surplus: 0 });
+    }
+
+    #[test]
+    fn test_read_hugepages() {
+        let hugepages = aw!(read_hugepages(&sysfs_fixture("kernel/mm/hugepages"))).unwrap();
+        assert_eq!(hugepages, vec![
+            HugepagesStats { size: "1048576kB".into(), total: 0, free: 0, reserved: Some(0), surplus: 0 },
+            HugepagesStats { size: "2048kB".into(), total: 1024, free: 628, reserved: Some(64), surplus: 0 },
+        ]);
+        assert!(aw!(read_hugepages(&sysfs_fixture("kernel/mm/missing"))).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,14 @@
+#[derive(Debug, Deserialize, Clone)]
+pub struct NumaSettings {
+    pub enabled: bool,
+    pub interval_millis: u64,
+}
+
+impl Default for NumaSettings {
+    fn default() -> Self {
+        NumaSettings {
+            enabled: true,
+            interval_millis: 15_000,
+        }
+    }
+}
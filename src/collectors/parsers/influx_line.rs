@@ -0,0 +1,157 @@
+//! Parser of the InfluxDB line protocol.
+//!
+//! More details can be found at https://docs.influxdata.com/influxdb/v1.8/write_protocols/line_protocol_reference/
+
+use std::collections::BTreeMap;
+
+use crate::collectors::parsers::{MetricFamily, MetricType, Sample};
+use crate::errors::{Error, Result};
+
+/// Parses every field of a line, `measurement[,tag=value...] field=value[,field=value...] [timestamp]`, as
+/// an untyped sample of the `<measurement>_<field>` family, tagged with the tags of the line.
+/// The string fields are skipped, the booleans are 1 or 0 and the timestamp is ignored.
+pub fn parse(text: &str) -> Result<Vec<MetricFamily>> {
+    let mut families: Vec<MetricFamily> = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let samples = parse_line(line)
+            .map_err(|reason| Error::Msg(format!("Line {}: {} on '{}'", line_number + 1, reason, line)))?;
+        for sample in samples {
+            match families.iter_mut().find(|family| family.name == sample.name) {
+                Some(family) => family.samples.push(sample),
+                None => families.push(MetricFamily { name: sample.name.clone(), help: String::new(), metric_type: MetricType::Untyped, samples: vec![sample] }),
+            }
+        }
+    }
+    Ok(families)
+}
+
+fn parse_line(line: &str) -> std::result::Result<Vec<Sample>, String> {
+    let sections = split_unescaped(line, ' ');
+    let (series, fields) = match sections.as_slice() {
+        [series, fields] | [series, fields, _] => (series, fields),
+        _ => return Err("expected the measurement, the fields and an optional timestamp".to_string()),
+    };
+    let mut series = split_unescaped(series, ',').into_iter();
+    let measurement = unescape(&series.next().unwrap_or_default());
+    if measurement.is_empty() {
+        return Err("line without measurement".to_string());
+    }
+    let mut labels = BTreeMap::new();
+    for tag in series {
+        let (name, value) = split_key_value(&tag).ok_or("tag without value")?;
+        labels.insert(unescape(name), unescape(value));
+    }
+    let mut samples = Vec::new();
+    for field in split_unescaped(fields, ',') {
+        let (name, value) = split_key_value(&field).ok_or("field without value")?;
+        if let Some(value) = parse_field_value(value)? {
+            samples.push(Sample { name: format!("{}_{}", measurement, unescape(name)), labels: labels.clone(), value });
+        }
+    }
+    Ok(samples)
+}
+
+/// The strings are skipped, since they can't be a metric.
+fn parse_field_value(value: &str) -> std::result::Result<Option<f64>, String> {
+    let invalid = || format!("invalid field value '{}'", value);
+    if value.starts_with('"') {
+        return if value.len() > 1 && value.ends_with('"') { Ok(None) } else { Err(invalid()) };
+    }
+    match value {
+        "t" | "T" | "true" | "True" | "TRUE" => return Ok(Some(1.0)),
+        "f" | "F" | "false" | "False" | "FALSE" => return Ok(Some(0.0)),
+        _ => {},
+    }
+    let number = value.strip_suffix('i').or_else(|| value.strip_suffix('u')).unwrap_or(value);
+    number.parse::<f64>().map(Some).map_err(|_| invalid())
+}
+
+fn split_key_value(text: &str) -> Option<(&str, &str)> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '=' if !escaped => return Some((&text[..i], &text[i + 1..])),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// Splits on the separators which aren't escaped with `\` or inside a quoted string, keeping the escape sequences.
+fn split_unescaped(text: &str, separator: char) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut piece = String::new();
+    let mut escaped = false;
+    let mut quoted = false;
+    for c in text.chars() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            quoted = !quoted;
+        } else if c == separator && !quoted {
+            pieces.push(std::mem::take(&mut piece));
+            continue;
+        }
+        piece.push(c);
+    }
+    pieces.push(piece);
+    pieces.into_iter().filter(|piece| !piece.is_empty()).collect()
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(',')) | ('\\', Some('=')) | ('\\', Some(' ')) | ('\\', Some('\\')) => unescaped.push(chars.next().unwrap()),
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(labels: &[(&str, &str)]) -> BTreeMap<String, String> {
+        labels.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_parse_lines() {
+        let text = r#"
+# A comment
+disk,device=sda,mount=/data used=1024i,free=2.5e3,ok=true,status="all good, really" 1590000000000000000
+disk,device=sdb,mount=/my\ data used=10u
+replication lag=0.5
+"#;
+        let families = parse(text).unwrap();
+        let names: Vec<&str> = families.iter().map(|family| family.name.as_str()).collect();
+        assert_eq!(names, vec!["disk_used", "disk_free", "disk_ok", "replication_lag"]);
+        assert!(families.iter().all(|family| family.metric_type == MetricType::Untyped));
+        assert_eq!(families[0].samples, vec![
+            Sample { name: "disk_used".into(), labels: labels(&[("device", "sda"), ("mount", "/data")]), value: 1024.0 },
+            Sample { name: "disk_used".into(), labels: labels(&[("device", "sdb"), ("mount", "/my data")]), value: 10.0 },
+        ]);
+        assert_eq!(families[1].samples[0].value, 2500.0);
+        assert_eq!(families[2].samples[0].value, 1.0);
+        assert_eq!(families[3].samples[0], Sample { name: "replication_lag".into(), labels: BTreeMap::new(), value: 0.5 });
+    }
+
+    #[test]
+    fn test_parse_invalid_lines_fails() {
+        assert!(parse("disk").is_err());
+        assert!(parse("disk used").is_err());
+        assert!(parse("disk,device used=1").is_err());
+        assert!(parse("disk used=one").is_err());
+        assert!(parse("disk used=\"unterminated").is_err());
+    }
+}
@@ -0,0 +1,62 @@
+//! Parsers of the metrics produced by other processes, like the files of the textfile collector.
+
+use std::collections::BTreeMap;
+
+pub mod influx_line;
+pub mod prometheus_text;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+    Histogram,
+    Summary,
+    Untyped,
+}
+
+impl MetricType {
+    pub fn from(metric_type: &str) -> Option<MetricType> {
+        match metric_type {
+            "counter" => Some(MetricType::Counter),
+            "gauge" => Some(MetricType::Gauge),
+            "histogram" => Some(MetricType::Histogram),
+            "summary" => Some(MetricType::Summary),
+            "untyped" => Some(MetricType::Untyped),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub name: String,
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricFamily {
+    pub name: String,
+    pub help: String,
+    pub metric_type: MetricType,
+    pub samples: Vec<Sample>,
+}
+
+impl MetricFamily {
+    /// Whether a sample belongs to this family, including the `_bucket`, `_sum` and `_count` samples
+    /// of the histograms and summaries.
+    pub fn contains(&self, sample_name: &str) -> bool {
+        if sample_name == self.name {
+            return true;
+        }
+        let suffix = match sample_name.strip_prefix(&self.name) {
+            Some(suffix) => suffix,
+            None => return false,
+        };
+        match self.metric_type {
+            MetricType::Histogram => suffix == "_bucket" || suffix == "_sum" || suffix == "_count",
+            MetricType::Summary => suffix == "_sum" || suffix == "_count",
+            _ => false,
+        }
+    }
+}
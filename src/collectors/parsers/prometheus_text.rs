@@ -0,0 +1,183 @@
+//! Parser of the Prometheus text exposition format.
+//!
+//! More details can be found at https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format
+
+use std::collections::BTreeMap;
+
+use crate::collectors::parsers::{MetricFamily, MetricType, Sample};
+use crate::errors::{Error, Result};
+
+/// Parses the families in the order they show up. The samples without a `# TYPE` are untyped families
+/// on their own, and the `_bucket`, `_sum` and `_count` samples are grouped into their histogram or summary.
+pub fn parse(text: &str) -> Result<Vec<MetricFamily>> {
+    let mut families: Vec<MetricFamily> = Vec::new();
+    let mut helps: BTreeMap<String, String> = BTreeMap::new();
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let error = |reason: &str| Error::Msg(format!("Line {}: {} on '{}'", line_number + 1, reason, line));
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            let mut tokens = comment.trim_start().splitn(3, char::is_whitespace);
+            match (tokens.next(), tokens.next(), tokens.next()) {
+                (Some("HELP"), Some(name), help) => {
+                    let help = unescape(help.unwrap_or_default().trim(), false).map_err(|reason| error(&reason))?;
+                    match families.iter_mut().find(|family| family.name == name) {
+                        Some(family) => family.help = help,
+                        None => { helps.insert(name.to_string(), help); },
+                    }
+                },
+                (Some("TYPE"), Some(name), Some(metric_type)) => {
+                    if families.iter().any(|family| family.name == name) {
+                        return Err(error("TYPE declared after the samples of the metric, or twice"));
+                    }
+                    let metric_type = MetricType::from(metric_type.trim()).ok_or_else(|| error("unknown metric type"))?;
+                    let help = helps.remove(name).unwrap_or_default();
+                    families.push(MetricFamily { name: name.to_string(), help, metric_type, samples: vec![] });
+                },
+                _ => {},
+            }
+            continue;
+        }
+        let sample = parse_sample(line).map_err(|reason| error(&reason))?;
+        let family_index = families.iter()
+            .rposition(|family| family.contains(&sample.name));
+        match family_index {
+            Some(family_index) => families[family_index].samples.push(sample),
+            None => {
+                let help = helps.remove(&sample.name).unwrap_or_default();
+                families.push(MetricFamily { name: sample.name.clone(), help, metric_type: MetricType::Untyped, samples: vec![sample] });
+            },
+        }
+    }
+    Ok(families)
+}
+
+/// A sample is `name{label="value",...} value [timestamp]`. The timestamp is ignored.
+fn parse_sample(line: &str) -> std::result::Result<Sample, String> {
+    let name_end = line.find(|c: char| c == '{' || c.is_whitespace()).ok_or("sample without value")?;
+    let name = &line[..name_end];
+    if name.is_empty() {
+        return Err("sample without name".to_string());
+    }
+    let mut rest = &line[name_end..];
+    let mut labels = BTreeMap::new();
+    if rest.starts_with('{') {
+        rest = &rest[1..];
+        loop {
+            rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+            if let Some(after_labels) = rest.strip_prefix('}') {
+                rest = after_labels;
+                break;
+            }
+            let equals = rest.find('=').ok_or("label without value")?;
+            let label_name = rest[..equals].trim();
+            rest = rest[equals + 1..].trim_start().strip_prefix('"').ok_or("label value without quotes")?;
+            let value_end = find_closing_quote(rest).ok_or("label value without closing quotes")?;
+            labels.insert(label_name.to_string(), unescape(&rest[..value_end], true)?);
+            rest = &rest[value_end + 1..];
+        }
+    }
+    let value = rest.split_whitespace().next().ok_or("sample without value")?;
+    Ok(Sample { name: name.to_string(), labels, value: parse_value(value)? })
+}
+
+fn find_closing_quote(text: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return Some(i),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// The help escapes `\\` and `\n`, and the label values also escape `\"`.
+fn unescape(text: &str, quotes: bool) -> std::result::Result<String, String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => unescaped.push('\\'),
+            Some('n') => unescaped.push('\n'),
+            Some('"') if quotes => unescaped.push('"'),
+            Some(other) if !quotes => { unescaped.push('\\'); unescaped.push(other); },
+            _ => return Err(format!("invalid escape sequence on '{}'", text)),
+        }
+    }
+    Ok(unescaped)
+}
+
+pub fn parse_value(value: &str) -> std::result::Result<f64, String> {
+    match value {
+        "+Inf" | "Inf" => Ok(f64::INFINITY),
+        "-Inf" => Ok(f64::NEG_INFINITY),
+        "NaN" => Ok(f64::NAN),
+        _ => value.parse::<f64>().map_err(|_| format!("invalid value '{}'", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(labels: &[(&str, &str)]) -> BTreeMap<String, String> {
+        labels.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_parse_families() {
+        let text = r#"
+# HELP backup_runs_total Backups run by the cron job.
+# TYPE backup_runs_total counter
+backup_runs_total{status="ok"} 27 1590000000000
+backup_runs_total{status="failed"} 2
+# TYPE backup_size_bytes gauge
+backup_size_bytes 1.2e+09
+# A comment
+backup_last_success_timestamp_seconds 1590000000
+# HELP backup_duration_seconds Duration of the backups.
+# TYPE backup_duration_seconds histogram
+backup_duration_seconds_bucket{le="60"} 20
+backup_duration_seconds_bucket{le="+Inf"} 29
+backup_duration_seconds_sum 2100.5
+backup_duration_seconds_count 29
+"#;
+        let families = parse(text).unwrap();
+        assert_eq!(families.len(), 4);
+        assert_eq!(families[0].name, "backup_runs_total");
+        assert_eq!(families[0].help, "Backups run by the cron job.");
+        assert_eq!(families[0].metric_type, MetricType::Counter);
+        assert_eq!(families[0].samples, vec![
+            Sample { name: "backup_runs_total".into(), labels: labels(&[("status", "ok")]), value: 27.0 },
+            Sample { name: "backup_runs_total".into(), labels: labels(&[("status", "failed")]), value: 2.0 },
+        ]);
+        assert_eq!(families[1].samples[0].value, 1.2e9);
+        assert_eq!(families[2].metric_type, MetricType::Untyped);
+        assert_eq!(families[2].name, "backup_last_success_timestamp_seconds");
+        assert_eq!(families[3].metric_type, MetricType::Histogram);
+        assert_eq!(families[3].samples.len(), 4);
+        assert_eq!(families[3].samples[1], Sample { name: "backup_duration_seconds_bucket".into(), labels: labels(&[("le", "+Inf")]), value: 29.0 });
+    }
+
+    #[test]
+    fn test_parse_escaped_label_values() {
+        let families = parse(r#"requests{path="/a\"b\\c",method="GET",} 3"#).unwrap();
+        assert_eq!(families[0].samples[0].labels, labels(&[("path", "/a\"b\\c"), ("method", "GET")]));
+    }
+
+    #[test]
+    fn test_parse_invalid_lines_fails() {
+        assert!(parse("requests{path=\"/a} 3").is_err());
+        assert!(parse("requests 3 4\nrequests three").is_err());
+        assert!(parse("# TYPE requests histogramm").is_err());
+        assert!(parse("requests 3\n# TYPE requests counter").is_err());
+    }
+}
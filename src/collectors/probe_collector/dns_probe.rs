@@ -0,0 +1,255 @@
+//! Probes a DNS resolver configured on `[[collectors.dns_probe]]` every interval, sending it a recursive
+//! query over UDP and timing until its response is received.
+//!
+//! The probe succeeds when the resolver answers `NOERROR` with at least one record. The response code
+//! and the number of answers are exported too, so a `NXDOMAIN` can be told from an unreachable resolver.
+//!
+//! More details on the messages can be found at https://tools.ietf.org/html/rfc1035#section-4.1
+
+use std::net::{SocketAddr, UdpSocket as StdUdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tokio::net::UdpSocket;
+
+use crate::collectors::metric_recorders::MetricRecorders;
+use crate::collectors::probe_collector;
+use crate::collectors::probe_collector::probe_settings::{DnsProbeSettings, DnsQueryType};
+use crate::errors::{Error, Result};
+use crate::metrics::gauge::GaugeBuilder;
+
+const HEADER_SIZE: usize = 12;
+/// Largest DNS message over UDP without EDNS.
+const MAX_MESSAGE_SIZE: usize = 512;
+const RECURSION_DESIRED: u16 = 0x0100;
+const RESPONSE: u16 = 0x8000;
+const CLASS_IN: u16 = 1;
+
+#[derive(Debug, PartialEq)]
+pub struct DnsResponse {
+    /// The response code, e.g. 0 for `NOERROR` or 3 for `NXDOMAIN`.
+    pub rcode: u8,
+    pub answers: u16,
+}
+
+/// Encodes a recursive query of one question, of the `IN` class.
+pub fn encode_query(id: u16, name: &str, query_type: DnsQueryType) -> Result<Vec<u8>> {
+    let mut message = Vec::with_capacity(HEADER_SIZE + name.len() + 6);
+    for field in [id, RECURSION_DESIRED, 1, 0, 0, 0].iter() {
+        message.extend_from_slice(&field.to_be_bytes());
+    }
+    let name = name.trim_end_matches('.');
+    if name.len() > 253 {
+        return Err(Error::Msg(format!("Name {} is longer than 253 characters", name)));
+    }
+    for label in name.split('.').filter(|_| !name.is_empty()) {
+        if label.is_empty() || label.len() > 63 {
+            return Err(Error::Msg(format!("Name {} has a label which is empty or longer than 63 characters", name)));
+        }
+        message.push(label.len() as u8);
+        message.extend_from_slice(label.as_bytes());
+    }
+    message.push(0);
+    message.extend_from_slice(&query_type.code().to_be_bytes());
+    message.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(message)
+}
+
+/// Decodes the header of the response to the query `id`, or `None` when the message isn't one.
+pub fn decode_response(id: u16, message: &[u8]) -> Option<DnsResponse> {
+    if message.len() < HEADER_SIZE {
+        return None;
+    }
+    let field = |offset: usize| u16::from_be_bytes([message[offset], message[offset + 1]]);
+    if field(0) != id || field(2) & RESPONSE == 0 {
+        return None;
+    }
+    Some(DnsResponse { rcode: (field(2) & 0x000F) as u8, answers: field(6) })
+}
+
+pub struct DnsProbeMonitor {
+    name: String,
+    resolver: SocketAddr,
+    query_name: String,
+    query_type: DnsQueryType,
+    interval: Duration,
+    timeout: Duration,
+    recorders: MetricRecorders,
+    queries: u16,
+}
+
+impl DnsProbeMonitor {
+    pub fn new(config: &DnsProbeSettings) -> Result<DnsProbeMonitor> {
+        let resolver = config.resolver.parse::<SocketAddr>()
+            .map_err(|error| Error::Msg(format!("Resolver {} isn't an ip:port address. Reason: {}", config.resolver, error)))?;
+        encode_query(0, &config.query_name, config.query_type)?;
+        Ok(DnsProbeMonitor {
+            name: config.name.clone(),
+            resolver,
+            query_name: config.query_name.clone(),
+            query_type: config.query_type,
+            interval: Duration::from_millis(config.interval_millis),
+            timeout: Duration::from_millis(config.timeout_millis),
+            recorders: MetricRecorders::new(),
+            queries: 0,
+        })
+    }
+
+    pub async fn start(mut self) {
+        loop {
+            if let Err(error) = self.collect().await {
+                warn!("DNS probe {} failed to collect. Reason: {}", self.name, error);
+            }
+            tokio::time::delay_for(self.interval).await;
+        }
+    }
+
+    async fn collect(&mut self) -> Result<()> {
+        let start = Instant::now();
+        let id = self.next_id();
+        let result = probe_collector::with_timeout(self.timeout, self.probe(id)).await;
+        let duration = start.elapsed();
+        let success = match &result {
+            Ok(response) => response.rcode == 0 && response.answers > 0,
+            Err(error) => {
+                debug!("DNS probe {} of {} failed. Reason: {}", self.name, self.resolver, error);
+                false
+            },
+        };
+        probe_collector::record_success(&mut self.recorders, &self.name, success).await?;
+        match result {
+            Ok(response) => self.record(&response, duration).await,
+            Err(_) => Ok(()),
+        }
+    }
+
+    async fn record(&mut self, response: &DnsResponse, duration: Duration) -> Result<()> {
+        let builder = GaugeBuilder::new("probe_dns_rcode".into(), "Response code of the resolver to the query of the probe.".into())
+            .with_tags("probe".to_string(), self.name.clone());
+        self.recorders.gauge(builder, response.rcode as f64).await?;
+        let builder = GaugeBuilder::new("probe_dns_answers".into(), "Records on the answer to the query of the probe.".into())
+            .with_tags("probe".to_string(), self.name.clone());
+        self.recorders.gauge(builder, response.answers as f64).await?;
+        probe_collector::record_duration(&mut self.recorders, "probe_dns_duration_seconds", "Duration of the DNS query of the probe.",
+                                         &self.name, None, duration).await
+    }
+
+    /// The ids start from the clock, so they differ between restarts of the probe.
+    fn next_id(&mut self) -> u16 {
+        self.queries = self.queries.wrapping_add(1);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.subsec_nanos()).unwrap_or_default();
+        (nanos as u16) ^ self.queries
+    }
+
+    /// Sends the query from a std socket, since the bind of `mio` 0.6 relies on the memory layout the std
+    /// socket addresses had on older compilers, and waits for its response ignoring any other message.
+    async fn probe(&self, id: u16) -> Result<DnsResponse> {
+        let local: SocketAddr = if self.resolver.is_ipv4() { "0.0.0.0:0".parse() } else { "[::]:0".parse() }
+            .map_err(|error| Error::Msg(format!("Invalid local address. Reason: {}", error)))?;
+        let socket = StdUdpSocket::bind(local)?;
+        socket.connect(self.resolver)?;
+        socket.set_nonblocking(true)?;
+        let mut socket = UdpSocket::from_std(socket)?;
+        socket.send(&encode_query(id, &self.query_name, self.query_type)?).await?;
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+        loop {
+            let count = socket.recv(&mut buffer).await?;
+            if let Some(response) = decode_response(id, &buffer[..count]) {
+                return Ok(response);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    /// Answers `127.0.0.1` to the queries of `example.local`, `NXDOMAIN` to the other ones and nothing
+    /// to `silent.local`, on a random local port.
+    fn serve() -> SocketAddr {
+        let socket = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+            let known = encode_query(0, "example.local", DnsQueryType::A).unwrap();
+            let silent = encode_query(0, "silent.local", DnsQueryType::A).unwrap();
+            while let Ok((count, peer)) = socket.recv_from(&mut buffer) {
+                let query = &buffer[..count];
+                if query[HEADER_SIZE..] == silent[HEADER_SIZE..] {
+                    continue;
+                }
+                let mut response = query.to_vec();
+                if query[HEADER_SIZE..] == known[HEADER_SIZE..] {
+                    response[2..4].copy_from_slice(&0x8180u16.to_be_bytes());
+                    response[6..8].copy_from_slice(&1u16.to_be_bytes());
+                    // a pointer to the name of the question, A, IN, a TTL of 60 and the address
+                    response.extend_from_slice(&[0xC0, 0x0C, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 127, 0, 0, 1]);
+                } else {
+                    response[2..4].copy_from_slice(&0x8183u16.to_be_bytes());
+                }
+                // a stray message first, which the probe has to ignore
+                let _ = socket.send_to(&[0u8; 4], peer);
+                let _ = socket.send_to(&response, peer);
+            }
+        });
+        address
+    }
+
+    fn settings(resolver: SocketAddr, query_name: &str) -> DnsProbeSettings {
+        DnsProbeSettings {
+            name: "dns_probe_test".into(),
+            resolver: resolver.to_string(),
+            query_name: query_name.into(),
+            query_type: DnsQueryType::A,
+            interval_millis: 15_000,
+            timeout_millis: 500,
+        }
+    }
+
+    #[test]
+    fn test_encode_query() {
+        let query = encode_query(0x1234, "example.local.", DnsQueryType::Aaaa).unwrap();
+        assert_eq!(&query[..HEADER_SIZE], &[0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&query[HEADER_SIZE..], b"\x07example\x05local\x00\x00\x1c\x00\x01");
+        assert_eq!(&encode_query(1, ".", DnsQueryType::Ns).unwrap()[HEADER_SIZE..], &[0, 0, 2, 0, 1]);
+        assert!(encode_query(1, "example..local", DnsQueryType::A).is_err());
+        assert!(encode_query(1, &"a".repeat(64), DnsQueryType::A).is_err());
+        assert_eq!(decode_response(0x1234, &query), None);
+    }
+
+    #[test]
+    fn test_probe_local_resolver() {
+        aw!(async {
+            let resolver = serve();
+            let mut monitor = DnsProbeMonitor::new(&settings(resolver, "example.local")).unwrap();
+            assert_eq!(monitor.probe(7).await.unwrap(), DnsResponse { rcode: 0, answers: 1 });
+            assert!(monitor.collect().await.is_ok());
+
+            let monitor = DnsProbeMonitor::new(&settings(resolver, "missing.local")).unwrap();
+            assert_eq!(monitor.probe(8).await.unwrap(), DnsResponse { rcode: 3, answers: 0 });
+
+            let mut monitor = DnsProbeMonitor::new(&settings(resolver, "silent.local")).unwrap();
+            let start = Instant::now();
+            // a timeout is a failed probe, not a failed collection
+            assert!(monitor.collect().await.is_ok());
+            assert!(start.elapsed() < Duration::from_secs(5));
+        });
+    }
+
+    #[test]
+    fn test_invalid_settings_fail() {
+        let resolver: SocketAddr = "127.0.0.1:53".parse().unwrap();
+        let mut config = settings(resolver, "example.local");
+        config.resolver = "localhost".into();
+        assert!(DnsProbeMonitor::new(&config).is_err());
+        assert!(DnsProbeMonitor::new(&settings(resolver, "example..local")).is_err());
+    }
+}
@@ -0,0 +1,411 @@
+//! Probes an HTTP target configured on `[[collectors.http_probe]]` every interval, like a blackbox
+//! check from the host, timing every phase of the request on its own connection:
+//!   - `dns`: the resolution of the host.
+//!   - `connect`: the TCP connection.
+//!   - `tls`: the TLS handshake of the HTTPS targets, verifying their certificate.
+//!   - `first_byte`: from the request being sent to the response headers being received.
+//!   - `total`: the whole probe, including reading the body.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hyper::{Body, Method, Request, Uri};
+use hyper::body::HttpBody;
+use hyper::header::{HOST, USER_AGENT};
+use regex::Regex;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+use tokio_rustls::webpki::{DNSName, DNSNameRef};
+
+use crate::collectors::metric_recorders::MetricRecorders;
+use crate::collectors::probe_collector;
+use crate::collectors::probe_collector::probe_settings::HttpProbeSettings;
+use crate::errors::{Error, Result};
+use crate::metrics::gauge::GaugeBuilder;
+use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
+use crate::strum::AsStaticRef;
+
+/// Bytes of the body kept at most, for the `body_regex` to match. The ones past it are only counted.
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, AsStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum HttpPhase {
+    Dns,
+    Connect,
+    Tls,
+    FirstByte,
+    Total,
+}
+
+#[derive(Debug)]
+pub struct HttpProbeResult {
+    pub status_code: u16,
+    pub phases: Vec<(HttpPhase, Duration)>,
+    pub body_size: u64,
+    /// Whether the body matched the configured regex, if any.
+    pub body_matched: Option<bool>,
+}
+
+pub struct HttpProbeMonitor {
+    name: String,
+    uri: Uri,
+    /// The connector of the HTTPS targets, with the name their certificate is verified against.
+    tls: Option<(TlsConnector, DNSName)>,
+    method: Method,
+    body_regex: Option<Regex>,
+    valid_status_codes: Vec<u16>,
+    interval: Duration,
+    timeout: Duration,
+    recorders: MetricRecorders,
+}
+
+impl HttpProbeMonitor {
+    pub fn new(config: &HttpProbeSettings) -> Result<HttpProbeMonitor> {
+        let uri = config.url.parse::<Uri>()
+            .map_err(|error| Error::Msg(format!("Invalid URL {}. Reason: {}", config.url, error)))?;
+        let tls = match (uri.scheme_str(), uri.host()) {
+            (Some("http"), Some(_)) => None,
+            (Some("https"), Some(host)) => {
+                // the certificates are only verified against host names, not IP addresses
+                let name = DNSNameRef::try_from_ascii_str(host)
+                    .map_err(|_| Error::Msg(format!("HTTPS target {} has to be named by its host name", config.url)))?;
+                Some((tls_connector(config)?, name.to_owned()))
+            },
+            _ => return Err(Error::Msg(format!("URL {} isn't an absolute HTTP or HTTPS one", config.url))),
+        };
+        let method = config.method.parse::<Method>()
+            .map_err(|error| Error::Msg(format!("Invalid HTTP method {}. Reason: {}", config.method, error)))?;
+        let body_regex = match &config.body_regex {
+            Some(body_regex) => Some(Regex::new(body_regex)
+                .map_err(|error| Error::Msg(format!("Invalid body regex of the probe {}. Reason: {}", config.name, error)))?),
+            None => None,
+        };
+        Ok(HttpProbeMonitor {
+            name: config.name.clone(),
+            uri,
+            tls,
+            method,
+            body_regex,
+            valid_status_codes: config.valid_status_codes.clone(),
+            interval: Duration::from_millis(config.interval_millis),
+            timeout: Duration::from_millis(config.timeout_millis),
+            recorders: MetricRecorders::new(),
+        })
+    }
+
+    pub async fn start(mut self) {
+        loop {
+            if let Err(error) = self.collect().await {
+                warn!("HTTP probe {} failed to collect. Reason: {}", self.name, error);
+            }
+            tokio::time::delay_for(self.interval).await;
+        }
+    }
+
+    async fn collect(&mut self) -> Result<()> {
+        let result = probe_collector::with_timeout(self.timeout, self.probe()).await;
+        let success = match &result {
+            Ok(result) => self.is_success(result),
+            Err(error) => {
+                debug!("HTTP probe {} of {} failed. Reason: {}", self.name, self.uri, error);
+                false
+            },
+        };
+        probe_collector::record_success(&mut self.recorders, &self.name, success).await?;
+        match result {
+            Ok(result) => self.record(&result).await,
+            Err(_) => Ok(()),
+        }
+    }
+
+    async fn record(&mut self, result: &HttpProbeResult) -> Result<()> {
+        let builder = GaugeBuilder::new("probe_http_status_code".into(), "Status code of the response to the probe.".into())
+            .with_tags("probe".to_string(), self.name.clone());
+        self.recorders.gauge(builder, result.status_code as f64).await?;
+        let builder = GaugeBuilder::new("probe_http_content_length_bytes".into(), "Size of the body of the response to the probe.".into())
+            .with_tags("probe".to_string(), self.name.clone())
+            .with_measurement_unit(&MEASUREMENT_UNITS.information.bytes);
+        self.recorders.gauge(builder, result.body_size as f64).await?;
+        if let Some(body_matched) = result.body_matched {
+            let builder = GaugeBuilder::new("probe_http_body_match".into(), "Whether the body of the response matched the regex of the probe.".into())
+                .with_tags("probe".to_string(), self.name.clone());
+            self.recorders.gauge(builder, if body_matched { 1.0 } else { 0.0 }).await?;
+        }
+        for (phase, duration) in result.phases.iter() {
+            probe_collector::record_duration(&mut self.recorders, "probe_http_duration_seconds", "Duration of every phase of the HTTP probe.",
+                                             &self.name, Some(phase.as_static()), *duration).await?;
+        }
+        Ok(())
+    }
+
+    fn is_success(&self, result: &HttpProbeResult) -> bool {
+        let valid_status_code = if self.valid_status_codes.is_empty() {
+            (200..300).contains(&result.status_code)
+        } else {
+            self.valid_status_codes.contains(&result.status_code)
+        };
+        valid_status_code && result.body_matched.unwrap_or(true)
+    }
+
+    /// Requests the target on a new connection, without following redirects.
+    async fn probe(&self) -> Result<HttpProbeResult> {
+        let start = Instant::now();
+        let mut phases = Vec::with_capacity(5);
+        let host = self.uri.host().unwrap_or_default();
+        let port = self.uri.port_u16().unwrap_or(if self.tls.is_some() { 443 } else { 80 });
+        // the IPv6 addresses are in brackets on the URLs
+        let address = net::lookup_host((host.trim_start_matches('[').trim_end_matches(']'), port)).await?
+            .next()
+            .ok_or_else(|| Error::Msg(format!("Host {} not resolved", host)))?;
+        phases.push((HttpPhase::Dns, start.elapsed()));
+
+        let phase_start = Instant::now();
+        let stream = probe_collector::connect(address, self.timeout).await?;
+        phases.push((HttpPhase::Connect, phase_start.elapsed()));
+
+        match &self.tls {
+            Some((connector, name)) => {
+                let phase_start = Instant::now();
+                let stream = connector.connect(name.as_ref(), stream).await
+                    .map_err(|error| Error::Msg(format!("TLS handshake failed. Reason: {}", error)))?;
+                phases.push((HttpPhase::Tls, phase_start.elapsed()));
+                self.request(stream, start, phases).await
+            },
+            None => self.request(stream, start, phases).await,
+        }
+    }
+
+    async fn request<S>(&self, stream: S, start: Instant, mut phases: Vec<(HttpPhase, Duration)>) -> Result<HttpProbeResult>
+        where S: AsyncRead + AsyncWrite + Unpin + Send + 'static
+    {
+        let http_error = |error: hyper::Error| Error::Msg(format!("HTTP error: {}", error));
+        let (mut sender, connection) = hyper::client::conn::handshake(stream).await.map_err(http_error)?;
+        tokio::spawn(async move {
+            if let Err(error) = connection.await {
+                debug!("Connection of the HTTP probe closed. Reason: {}", error);
+            }
+        });
+        let path = self.uri.path_and_query().map(|path| path.as_str()).unwrap_or("/");
+        let authority = self.uri.authority().map(|authority| authority.as_str()).unwrap_or_default();
+        let request = Request::builder()
+            .method(self.method.clone())
+            .uri(path)
+            .header(HOST, authority)
+            .header(USER_AGENT, "rusty-advisor")
+            .body(Body::empty())
+            .map_err(|error| Error::Msg(format!("Invalid HTTP request. Reason: {}", error)))?;
+        let phase_start = Instant::now();
+        let response = sender.send_request(request).await.map_err(http_error)?;
+        phases.push((HttpPhase::FirstByte, phase_start.elapsed()));
+
+        let status_code = response.status().as_u16();
+        let mut body = response.into_body();
+        let (mut kept, mut body_size) = (Vec::new(), 0u64);
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.map_err(http_error)?;
+            body_size += chunk.len() as u64;
+            if self.body_regex.is_some() && kept.len() < MAX_BODY_BYTES {
+                kept.extend_from_slice(&chunk[..chunk.len().min(MAX_BODY_BYTES - kept.len())]);
+            }
+        }
+        phases.push((HttpPhase::Total, start.elapsed()));
+        let body_matched = self.body_regex.as_ref().map(|regex| regex.is_match(&String::from_utf8_lossy(&kept)));
+        Ok(HttpProbeResult { status_code, phases, body_size, body_matched })
+    }
+}
+
+/// Trusts the CAs of the system, and the ones of the `ca_file` of the probe.
+fn tls_connector(config: &HttpProbeSettings) -> Result<TlsConnector> {
+    let mut client_config = ClientConfig::new();
+    client_config.root_store = match rustls_native_certs::load_native_certs() {
+        Ok(root_store) => root_store,
+        Err((root_store, error)) => {
+            warn!("CAs of the system couldn't be loaded for the HTTP probe {}. Reason: {}", config.name, error);
+            root_store.unwrap_or_else(RootCertStore::empty)
+        },
+    };
+    if let Some(ca_file) = &config.ca_file {
+        let (added, _) = client_config.root_store.add_pem_file(&mut BufReader::new(File::open(ca_file)?))
+            .map_err(|_| Error::Msg(format!("CA file {} of the HTTP probe {} isn't valid PEM", ca_file, config.name)))?;
+        if added == 0 {
+            return Err(Error::Msg(format!("CA file {} of the HTTP probe {} has no valid certificates", ca_file, config.name)));
+        }
+    }
+    Ok(TlsConnector::from(Arc::new(client_config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use hyper::{Response, Server, StatusCode};
+    use hyper::service::{make_service_fn, service_fn};
+    use tokio_rustls::rustls::{NoClientAuth, ServerConfig};
+    use tokio_rustls::rustls::internal::pemfile;
+    use tokio_rustls::TlsAcceptor;
+
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    async fn respond(request: Request<Body>) -> std::result::Result<Response<Body>, hyper::Error> {
+        let response = match request.uri().path() {
+            "/health" => Response::new(Body::from("status: ok")),
+            "/large" => Response::new(Body::from(format!("{}status: ok", " ".repeat(MAX_BODY_BYTES)))),
+            "/slow" => {
+                tokio::time::delay_for(Duration::from_secs(2)).await;
+                Response::new(Body::from("status: ok"))
+            },
+            _ => Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()).unwrap(),
+        };
+        Ok(response)
+    }
+
+    /// Serves the probes on a random local port.
+    fn serve() -> SocketAddr {
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap())
+            .serve(make_service_fn(|_| async { Ok::<_, hyper::Error>(service_fn(respond)) }));
+        let address = server.local_addr();
+        tokio::spawn(server);
+        address
+    }
+
+    fn settings(url: String) -> HttpProbeSettings {
+        HttpProbeSettings {
+            name: "http_probe_test".into(),
+            url,
+            method: "GET".into(),
+            interval_millis: 15_000,
+            timeout_millis: 1_000,
+            valid_status_codes: vec![],
+            body_regex: Some("status: (ok|degraded)".into()),
+            ca_file: None,
+        }
+    }
+
+    fn fixture(name: &str) -> String {
+        format!("{}/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    /// Serves the probes over TLS, with the certificate of `localhost` signed by the CA of the fixtures.
+    fn serve_tls() -> SocketAddr {
+        let certs = pemfile::certs(&mut BufReader::new(File::open(fixture("localhost.pem")).unwrap())).unwrap();
+        let key = pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(fixture("localhost-key.pem")).unwrap())).unwrap().remove(0);
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config.set_single_cert(certs, key).unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = std::net::TcpListener::bind(("localhost", 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+        // the failed handshakes are skipped, they'd stop the server otherwise
+        let incoming = futures::stream::unfold((listener, acceptor), |(mut listener, acceptor)| async move {
+            loop {
+                let (stream, _) = listener.accept().await.ok()?;
+                if let Ok(stream) = acceptor.accept(stream).await {
+                    return Some((Ok::<_, std::io::Error>(stream), (listener, acceptor)));
+                }
+            }
+        });
+        let server = Server::builder(hyper::server::accept::from_stream(incoming))
+            .serve(make_service_fn(|_| async { Ok::<_, hyper::Error>(service_fn(respond)) }));
+        tokio::spawn(server);
+        address
+    }
+
+    #[test]
+    fn test_probe_local_server() {
+        aw!(async {
+            let address = serve();
+            let mut monitor = HttpProbeMonitor::new(&settings(format!("http://{}/health", address))).unwrap();
+            let result = monitor.probe().await.unwrap();
+            assert_eq!(result.status_code, 200);
+            assert_eq!(result.body_size, 10);
+            assert_eq!(result.body_matched, Some(true));
+            let phases: Vec<HttpPhase> = result.phases.iter().map(|(phase, _)| *phase).collect();
+            assert_eq!(phases, vec![HttpPhase::Dns, HttpPhase::Connect, HttpPhase::FirstByte, HttpPhase::Total]);
+            assert!(monitor.is_success(&result));
+            assert!(monitor.collect().await.is_ok());
+
+            let monitor = HttpProbeMonitor::new(&settings(format!("http://{}/missing", address))).unwrap();
+            let result = monitor.probe().await.unwrap();
+            assert_eq!(result.status_code, 404);
+            assert_eq!(result.body_matched, Some(false));
+            assert!(!monitor.is_success(&result));
+
+            // the body past the bytes kept is counted, but not matched
+            let monitor = HttpProbeMonitor::new(&settings(format!("http://{}/large", address))).unwrap();
+            let result = monitor.probe().await.unwrap();
+            assert_eq!(result.body_size, MAX_BODY_BYTES as u64 + 10);
+            assert_eq!(result.body_matched, Some(false));
+
+            let mut config = settings(format!("http://{}/missing", address));
+            config.valid_status_codes = vec![404];
+            config.body_regex = None;
+            let monitor = HttpProbeMonitor::new(&config).unwrap();
+            assert!(monitor.is_success(&monitor.probe().await.unwrap()));
+        });
+    }
+
+    #[test]
+    fn test_probe_https_server() {
+        aw!(async {
+            let port = serve_tls().port();
+            let mut config = settings(format!("https://localhost:{}/health", port));
+            config.ca_file = Some(fixture("ca.pem"));
+            let mut monitor = HttpProbeMonitor::new(&config).unwrap();
+            let result = monitor.probe().await.unwrap();
+            assert_eq!(result.status_code, 200);
+            assert_eq!(result.body_matched, Some(true));
+            let phases: Vec<HttpPhase> = result.phases.iter().map(|(phase, _)| *phase).collect();
+            assert_eq!(phases, vec![HttpPhase::Dns, HttpPhase::Connect, HttpPhase::Tls, HttpPhase::FirstByte, HttpPhase::Total]);
+            assert!(monitor.collect().await.is_ok());
+
+            // the CA of the fixtures isn't trusted by the system
+            let monitor = HttpProbeMonitor::new(&settings(format!("https://localhost:{}/health", port))).unwrap();
+            assert!(monitor.probe().await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_failed_probes() {
+        aw!(async {
+            let address = serve();
+            let mut monitor = HttpProbeMonitor::new(&settings(format!("http://{}/slow", address))).unwrap();
+            let start = Instant::now();
+            // a timeout is a failed probe, not a failed collection
+            assert!(monitor.collect().await.is_ok());
+            assert!(start.elapsed() < Duration::from_secs(2));
+
+            // nothing listens on the port the server was bound to
+            let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+            let monitor = HttpProbeMonitor::new(&settings(format!("http://{}/health", closed))).unwrap();
+            assert!(monitor.probe().await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_invalid_settings_fail() {
+        assert!(HttpProbeMonitor::new(&settings("https://localhost/health".into())).is_ok());
+        assert!(HttpProbeMonitor::new(&settings("https://127.0.0.1/health".into())).is_err());
+        assert!(HttpProbeMonitor::new(&settings("ftp://localhost/health".into())).is_err());
+        assert!(HttpProbeMonitor::new(&settings("/health".into())).is_err());
+        let mut config = settings("http://localhost/health".into());
+        config.method = "GET POST".into();
+        assert!(HttpProbeMonitor::new(&config).is_err());
+        config.method = "HEAD".into();
+        config.body_regex = Some("(".into());
+        assert!(HttpProbeMonitor::new(&config).is_err());
+        let mut config = settings("https://localhost/health".into());
+        config.ca_file = Some(fixture("missing.pem"));
+        assert!(HttpProbeMonitor::new(&config).is_err());
+    }
+}
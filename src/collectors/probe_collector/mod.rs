@@ -0,0 +1,53 @@
+//! Probes of the targets reachable from the host, like blackbox checks.
+//!
+//! Every probe exports `probe_success{probe}`, and the durations of its phases on a histogram in nanoseconds.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+
+use crate::collectors::metric_recorders::MetricRecorders;
+use crate::errors::{Error, Result};
+use crate::metrics::gauge::GaugeBuilder;
+use crate::metrics::histogram::{HistogramBuilder, HistogramSettings};
+use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
+
+pub mod dns_probe;
+pub mod http_probe;
+pub mod probe_settings;
+pub mod tcp_probe;
+
+/// Connects with a std stream on the blocking thread pool, since the connect of `mio` 0.6 relies on
+/// the memory layout the std socket addresses had on older compilers.
+pub async fn connect(address: SocketAddr, timeout: Duration) -> Result<TcpStream> {
+    let stream = tokio::task::spawn_blocking(move || std::net::TcpStream::connect_timeout(&address, timeout)).await
+        .map_err(|error| Error::Msg(format!("Connection to {} panicked. Reason: {}", address, error)))??;
+    Ok(TcpStream::from_std(stream)?)
+}
+
+pub async fn record_success(recorders: &mut MetricRecorders, probe: &str, success: bool) -> Result<()> {
+    let builder = GaugeBuilder::new("probe_success".into(), "Whether the probe succeeded.".into())
+        .with_tags("probe".to_string(), probe.to_string());
+    recorders.gauge(builder, if success { 1.0 } else { 0.0 }).await
+}
+
+/// Records the duration on the histogram `name` tagged with the probe, and the phase if any.
+pub async fn record_duration(recorders: &mut MetricRecorders, name: &str, description: &str, probe: &str, phase: Option<&str>, duration: Duration) -> Result<()> {
+    let settings = HistogramSettings::from(1, 600_000_000_000, 2, &MEASUREMENT_UNITS.time.nanos);
+    let mut builder = HistogramBuilder::new(name.to_string(), description.to_string())
+        .with_tags("probe".to_string(), probe.to_string());
+    if let Some(phase) = phase {
+        builder = builder.with_tags("phase".to_string(), phase.to_string());
+    }
+    let nanos = (duration.as_nanos() as u64).clamp(settings.low, settings.high);
+    recorders.histogram(builder.with_settings(settings), &[nanos]).await
+}
+
+/// A probe failing to respond within the timeout is a failed probe.
+pub async fn with_timeout<T, F: std::future::Future<Output=Result<T>>>(timeout: Duration, probe: F) -> Result<T> {
+    match tokio::time::timeout(timeout, probe).await {
+        Ok(result) => result,
+        Err(_) => Err(Error::Msg(format!("Timed out after {} millis", timeout.as_millis()))),
+    }
+}
@@ -0,0 +1,101 @@
+/// An HTTP target requested every `interval_millis`, which fails when it doesn't respond within `timeout_millis`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct HttpProbeSettings {
+    /// Name the metrics of the probe are tagged with, e.g. `probe_success{probe="api"}`.
+    pub name: String,
+    pub url: String,
+    #[serde(default = "default_method")]
+    pub method: String,
+    #[serde(default = "default_interval_millis")]
+    pub interval_millis: u64,
+    #[serde(default = "default_timeout_millis")]
+    pub timeout_millis: u64,
+    /// The status codes of a successful probe, any `2xx` one when it's empty.
+    #[serde(default)]
+    pub valid_status_codes: Vec<u16>,
+    /// Regex the body of the response has to match for the probe to succeed.
+    #[serde(default)]
+    pub body_regex: Option<String>,
+    /// PEM file with the CAs trusted to sign the certificate of an HTTPS target, besides the ones of the system.
+    #[serde(default)]
+    pub ca_file: Option<String>,
+}
+
+/// A TCP target connected to every `interval_millis`, which fails when it doesn't respond within `timeout_millis`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TcpProbeSettings {
+    /// Name the metrics of the probe are tagged with, e.g. `probe_success{probe="redis"}`.
+    pub name: String,
+    /// The `host:port` to connect to.
+    pub address: String,
+    #[serde(default = "default_interval_millis")]
+    pub interval_millis: u64,
+    #[serde(default = "default_timeout_millis")]
+    pub timeout_millis: u64,
+    /// Bytes written once connected, e.g. `"PING\r\n"`.
+    #[serde(default)]
+    pub send: Option<String>,
+    /// Regex the bytes read back have to match for the probe to succeed.
+    #[serde(default)]
+    pub expect: Option<String>,
+}
+
+/// A DNS query sent over UDP to the `resolver` every `interval_millis`, which fails when it isn't answered within `timeout_millis`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DnsProbeSettings {
+    /// Name the metrics of the probe are tagged with, e.g. `probe_success{probe="internal_dns"}`.
+    pub name: String,
+    /// The `ip:port` of the resolver.
+    pub resolver: String,
+    pub query_name: String,
+    #[serde(default)]
+    pub query_type: DnsQueryType,
+    #[serde(default = "default_interval_millis")]
+    pub interval_millis: u64,
+    #[serde(default = "default_timeout_millis")]
+    pub timeout_millis: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum DnsQueryType {
+    #[default]
+    A,
+    Ns,
+    Cname,
+    Soa,
+    Ptr,
+    Mx,
+    Txt,
+    Aaaa,
+    Srv,
+}
+
+impl DnsQueryType {
+    /// The code of the type on the DNS messages.
+    pub fn code(&self) -> u16 {
+        match self {
+            DnsQueryType::A => 1,
+            DnsQueryType::Ns => 2,
+            DnsQueryType::Cname => 5,
+            DnsQueryType::Soa => 6,
+            DnsQueryType::Ptr => 12,
+            DnsQueryType::Mx => 15,
+            DnsQueryType::Txt => 16,
+            DnsQueryType::Aaaa => 28,
+            DnsQueryType::Srv => 33,
+        }
+    }
+}
+
+fn default_method() -> String {
+    "GET".into()
+}
+
+fn default_interval_millis() -> u64 {
+    15_000
+}
+
+fn default_timeout_millis() -> u64 {
+    5_000
+}
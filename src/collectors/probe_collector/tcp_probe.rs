@@ -0,0 +1,250 @@
+//! Probes a TCP target configured on `[[collectors.tcp_probe]]` every interval, timing every phase on
+//! its own connection:
+//!   - `dns`: the resolution of the host.
+//!   - `connect`: the TCP connection.
+//!   - `expect`: from the `send` bytes being written to the `expect` regex matching the bytes read back.
+//!   - `total`: the whole probe.
+//!
+//! Without `expect`, a successful connection is a successful probe.
+
+use std::time::{Duration, Instant};
+
+use regex::bytes::Regex;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net;
+
+use crate::collectors::metric_recorders::MetricRecorders;
+use crate::collectors::probe_collector;
+use crate::collectors::probe_collector::probe_settings::TcpProbeSettings;
+use crate::errors::{Error, Result};
+use crate::metrics::gauge::GaugeBuilder;
+use crate::strum::AsStaticRef;
+
+/// Bytes read back from the target at most, for the `expect` regex to match.
+const MAX_EXPECT_BYTES: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, AsStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum TcpPhase {
+    Dns,
+    Connect,
+    Expect,
+    Total,
+}
+
+#[derive(Debug)]
+pub struct TcpProbeResult {
+    pub phases: Vec<(TcpPhase, Duration)>,
+    /// Whether the bytes read back matched the configured regex, if any.
+    pub expect_matched: Option<bool>,
+}
+
+pub struct TcpProbeMonitor {
+    name: String,
+    address: String,
+    send: Option<Vec<u8>>,
+    expect: Option<Regex>,
+    interval: Duration,
+    timeout: Duration,
+    recorders: MetricRecorders,
+}
+
+impl TcpProbeMonitor {
+    pub fn new(config: &TcpProbeSettings) -> Result<TcpProbeMonitor> {
+        if !config.address.contains(':') {
+            return Err(Error::Msg(format!("Address {} isn't a host:port one", config.address)));
+        }
+        let expect = match &config.expect {
+            Some(expect) => Some(Regex::new(expect)
+                .map_err(|error| Error::Msg(format!("Invalid expect regex of the probe {}. Reason: {}", config.name, error)))?),
+            None => None,
+        };
+        Ok(TcpProbeMonitor {
+            name: config.name.clone(),
+            address: config.address.clone(),
+            send: config.send.as_ref().map(|send| send.as_bytes().to_vec()),
+            expect,
+            interval: Duration::from_millis(config.interval_millis),
+            timeout: Duration::from_millis(config.timeout_millis),
+            recorders: MetricRecorders::new(),
+        })
+    }
+
+    pub async fn start(mut self) {
+        loop {
+            if let Err(error) = self.collect().await {
+                warn!("TCP probe {} failed to collect. Reason: {}", self.name, error);
+            }
+            tokio::time::delay_for(self.interval).await;
+        }
+    }
+
+    async fn collect(&mut self) -> Result<()> {
+        let result = probe_collector::with_timeout(self.timeout, self.probe()).await;
+        let success = match &result {
+            Ok(result) => result.expect_matched.unwrap_or(true),
+            Err(error) => {
+                debug!("TCP probe {} of {} failed. Reason: {}", self.name, self.address, error);
+                false
+            },
+        };
+        probe_collector::record_success(&mut self.recorders, &self.name, success).await?;
+        match result {
+            Ok(result) => self.record(&result).await,
+            Err(_) => Ok(()),
+        }
+    }
+
+    async fn record(&mut self, result: &TcpProbeResult) -> Result<()> {
+        if let Some(expect_matched) = result.expect_matched {
+            let builder = GaugeBuilder::new("probe_tcp_expect_match".into(), "Whether the bytes read back matched the expect regex of the probe.".into())
+                .with_tags("probe".to_string(), self.name.clone());
+            self.recorders.gauge(builder, if expect_matched { 1.0 } else { 0.0 }).await?;
+        }
+        for (phase, duration) in result.phases.iter() {
+            probe_collector::record_duration(&mut self.recorders, "probe_tcp_duration_seconds", "Duration of every phase of the TCP probe.",
+                                             &self.name, Some(phase.as_static()), *duration).await?;
+        }
+        Ok(())
+    }
+
+    async fn probe(&self) -> Result<TcpProbeResult> {
+        let start = Instant::now();
+        let mut phases = Vec::with_capacity(4);
+        let address = net::lookup_host(self.address.as_str()).await?
+            .next()
+            .ok_or_else(|| Error::Msg(format!("Host of {} not resolved", self.address)))?;
+        phases.push((TcpPhase::Dns, start.elapsed()));
+
+        let phase_start = Instant::now();
+        let mut stream = probe_collector::connect(address, self.timeout).await?;
+        phases.push((TcpPhase::Connect, phase_start.elapsed()));
+
+        let phase_start = Instant::now();
+        if let Some(send) = &self.send {
+            stream.write_all(send).await?;
+        }
+        let expect_matched = match &self.expect {
+            Some(expect) => {
+                let matched = read_until_match(&mut stream, expect).await?;
+                phases.push((TcpPhase::Expect, phase_start.elapsed()));
+                Some(matched)
+            },
+            None => None,
+        };
+        phases.push((TcpPhase::Total, start.elapsed()));
+        Ok(TcpProbeResult { phases, expect_matched })
+    }
+}
+
+/// Reads until the bytes read match, the target closes the connection or `MAX_EXPECT_BYTES` are read.
+async fn read_until_match(stream: &mut net::TcpStream, expect: &Regex) -> Result<bool> {
+    let mut read = Vec::new();
+    let mut buffer = [0u8; 4096];
+    while read.len() < MAX_EXPECT_BYTES {
+        let count = stream.read(&mut buffer).await?;
+        if count == 0 {
+            break;
+        }
+        read.extend_from_slice(&buffer[..count]);
+        if expect.is_match(&read) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    /// Answers `+PONG` to every `PING` line, on a random local port.
+    fn serve() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut line = String::new();
+                if BufReader::new(&stream).read_line(&mut line).is_ok() && line.trim_end() == "PING" {
+                    let _ = stream.write_all(b"+PONG\r\n");
+                } else {
+                    let _ = stream.write_all(b"-ERR unknown command\r\n");
+                }
+            }
+        });
+        address
+    }
+
+    fn settings(address: SocketAddr, send: &str) -> TcpProbeSettings {
+        TcpProbeSettings {
+            name: "tcp_probe_test".into(),
+            address: address.to_string(),
+            interval_millis: 15_000,
+            timeout_millis: 1_000,
+            send: Some(send.into()),
+            expect: Some("^\\+PONG".into()),
+        }
+    }
+
+    #[test]
+    fn test_probe_local_listener() {
+        aw!(async {
+            let address = serve();
+            let mut monitor = TcpProbeMonitor::new(&settings(address, "PING\r\n")).unwrap();
+            let result = monitor.probe().await.unwrap();
+            assert_eq!(result.expect_matched, Some(true));
+            let phases: Vec<TcpPhase> = result.phases.iter().map(|(phase, _)| *phase).collect();
+            assert_eq!(phases, vec![TcpPhase::Dns, TcpPhase::Connect, TcpPhase::Expect, TcpPhase::Total]);
+            assert!(monitor.collect().await.is_ok());
+
+            let monitor = TcpProbeMonitor::new(&settings(address, "QUIT\r\n")).unwrap();
+            assert_eq!(monitor.probe().await.unwrap().expect_matched, Some(false));
+
+            let mut config = settings(address, "");
+            config.send = None;
+            config.expect = None;
+            let monitor = TcpProbeMonitor::new(&config).unwrap();
+            let result = monitor.probe().await.unwrap();
+            assert_eq!(result.expect_matched, None);
+            assert_eq!(result.phases.len(), 3);
+        });
+    }
+
+    #[test]
+    fn test_failed_probes() {
+        aw!(async {
+            // the listener never answers, so the probe times out waiting for the expected bytes
+            let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+            let mut monitor = TcpProbeMonitor::new(&settings(silent.local_addr().unwrap(), "PING\r\n")).unwrap();
+            let start = Instant::now();
+            assert!(monitor.collect().await.is_ok());
+            assert!(start.elapsed() < Duration::from_secs(5));
+
+            let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+            let monitor = TcpProbeMonitor::new(&settings(closed, "PING\r\n")).unwrap();
+            assert!(monitor.probe().await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_invalid_settings_fail() {
+        let address: SocketAddr = "127.0.0.1:6379".parse().unwrap();
+        let mut config = settings(address, "PING\r\n");
+        config.address = "localhost".into();
+        assert!(TcpProbeMonitor::new(&config).is_err());
+        let mut config = settings(address, "PING\r\n");
+        config.expect = Some("(".into());
+        assert!(TcpProbeMonitor::new(&config).is_err());
+    }
+}
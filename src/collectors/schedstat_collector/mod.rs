@@ -0,0 +1,2 @@
+pub mod schedstat_monitor;
+pub mod schedstat_settings;
@@ -0,0 +1,152 @@
+//! Reads the run queue stats of every CPU from `/proc/schedstat`, the kernel side view of
+//! the time the tasks wait to be scheduled.
+//!
+//! More details can be found at https://www.kernel.org/doc/html/latest/scheduler/sched-stats.html
+
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use tokio::fs;
+
+use crate::collectors::collectors_settings::CollectorsSettings;
+use crate::collectors::metric_recorders::MetricRecorders;
+use crate::errors::{Error, Result};
+use crate::metrics::counter::CounterBuilder;
+use crate::metrics::gauge::GaugeBuilder;
+use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
+
+/// Run queue stats of a CPU since the boot.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CpuSchedstat {
+    /// Time spent running by the tasks on this CPU, in nanoseconds.
+    pub running_nanos: u64,
+    /// Time spent waiting on the run queue by the tasks on this CPU, in nanoseconds.
+    pub waiting_nanos: u64,
+    /// Timeslices run on this CPU.
+    pub timeslices: u64,
+}
+
+pub struct SchedstatMonitor {
+    path: PathBuf,
+    interval: Duration,
+    recorders: MetricRecorders,
+    last_collection: Option<(Instant, HashMap<String, CpuSchedstat>)>,
+}
+
+impl SchedstatMonitor {
+    pub fn new(config: &CollectorsSettings) -> SchedstatMonitor {
+        SchedstatMonitor {
+            path: PathBuf::from(&config.procfs_path).join("schedstat"),
+            interval: Duration::from_millis(config.schedstat.interval_millis),
+            recorders: MetricRecorders::new(),
+            last_collection: None,
+        }
+    }
+
+    pub async fn start(mut self) {
+        loop {
+            match self.collect().await {
+                Ok(_) => {},
+                Err(Error::Io(error)) if error.kind() == io::ErrorKind::NotFound => {
+                    warn!("Schedstat collector is stopped since {} is not available. Reason: {}", self.path.display(), error);
+                    return;
+                },
+                Err(error) => warn!("Schedstat collector failed to collect {}. Reason: {}", self.path.display(), error),
+            }
+            tokio::time::delay_for(self.interval).await;
+        }
+    }
+
+    async fn collect(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let cpus = parse_schedstat(&fs::read_to_string(&self.path).await?)?;
+        for (cpu, schedstat) in cpus.iter() {
+            self.recorders.counter_total(counter("schedstat_running_seconds_total", "Time spent running by the tasks on the CPU.", cpu)
+                                             .with_measurement_unit(&MEASUREMENT_UNITS.time.nanos), schedstat.running_nanos).await?;
+            self.recorders.counter_total(counter("schedstat_waiting_seconds_total", "Time spent waiting on the run queue by the tasks on the CPU.", cpu)
+                                             .with_measurement_unit(&MEASUREMENT_UNITS.time.nanos), schedstat.waiting_nanos).await?;
+            self.recorders.counter_total(counter("schedstat_timeslices_total", "Timeslices run on the CPU.", cpu), schedstat.timeslices).await?;
+            let previous = self.last_collection.as_ref()
+                .and_then(|(last_time, last_cpus)| last_cpus.get(cpu).map(|last_schedstat| (now - *last_time, last_schedstat)));
+            if let Some((elapsed, last_schedstat)) = previous {
+                let (waiting_rate, average_wait) = run_queue_wait(last_schedstat, schedstat, elapsed);
+                self.recorders.gauge(gauge("schedstat_waiting_rate", "Seconds waited on the run queue per second by the tasks on the CPU, during the last interval.", cpu),
+                                     waiting_rate).await?;
+                self.recorders.gauge(gauge("schedstat_average_wait_per_timeslice_seconds", "Average wait on the run queue per timeslice run on the CPU, during the last interval.", cpu)
+                                         .with_measurement_unit(&MEASUREMENT_UNITS.time.nanos), average_wait).await?;
+            }
+        }
+        self.last_collection = Some((now, cpus));
+        Ok(())
+    }
+}
+
+fn counter(name: &str, description: &str, cpu: &str) -> CounterBuilder {
+    CounterBuilder::new(name.into(), description.into())
+        .with_tags("cpu".to_string(), cpu.to_string())
+}
+
+fn gauge(name: &str, description: &str, cpu: &str) -> GaugeBuilder {
+    GaugeBuilder::new(name.into(), description.into())
+        .with_tags("cpu".to_string(), cpu.to_string())
+}
+
+/// Returns the time waited on the run queue per second, and the average wait per timeslice in nanoseconds,
+/// between two collections.
+pub fn run_queue_wait(before: &CpuSchedstat, after: &CpuSchedstat, elapsed: Duration) -> (f64, f64) {
+    let waiting_nanos = after.waiting_nanos.saturating_sub(before.waiting_nanos) as f64;
+    let timeslices = after.timeslices.saturating_sub(before.timeslices);
+    let waiting_rate = if elapsed.as_nanos() > 0 { waiting_nanos / elapsed.as_nanos() as f64 } else { 0.0 };
+    let average_wait = if timeslices > 0 { waiting_nanos / timeslices as f64 } else { 0.0 };
+    (waiting_rate, average_wait)
+}
+
+/// The CPU lines are `cpu<N>` followed by 9 fields, where the last 3 ones are the time running,
+/// the time waiting and the timeslices. The `domain` lines are ignored.
+pub fn parse_schedstat(schedstat: &str) -> Result<HashMap<String, CpuSchedstat>> {
+    let mut cpus = HashMap::new();
+    for line in schedstat.lines() {
+        let mut fields = line.split_whitespace();
+        let cpu = match fields.next() {
+            Some(name) if name.starts_with("cpu") => name.trim_start_matches("cpu"),
+            _ => continue,
+        };
+        let values = fields.map(|field| field.parse::<u64>())
+            .collect::<std::result::Result<Vec<u64>, _>>()
+            .map_err(|_| Error::Msg(format!("Unexpected schedstat format: {}", line)))?;
+        if values.len() < 9 {
+            return Err(Error::Msg(format!("Unexpected schedstat format: {}", line)));
+        }
+        cpus.insert(cpu.to_string(), CpuSchedstat {
+            running_nanos: values[6],
+            waiting_nanos: values[7],
+            timeslices: values[8],
+        });
+    }
+    Ok(cpus)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_schedstat() {
+        let schedstat = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/proc/schedstat")).unwrap();
+        let cpus = parse_schedstat(&schedstat).unwrap();
+        assert_eq!(cpus.len(), 2);
+        assert_eq!(cpus["0"], CpuSchedstat { running_nanos: 1_542_380_541, waiting_nanos: 283_015_430, timeslices: 9_581 });
+        assert_eq!(cpus["1"], CpuSchedstat { running_nanos: 1_384_103_422, waiting_nanos: 198_330_152, timeslices: 8_127 });
+        assert!(parse_schedstat("cpu0 0 0 0 0 0 0 1542380541\n").is_err());
+    }
+
+    #[test]
+    fn test_run_queue_wait_between_collections() {
+        let before = CpuSchedstat { running_nanos: 1_000_000_000, waiting_nanos: 100_000_000, timeslices: 1_000 };
+        let after = CpuSchedstat { running_nanos: 1_500_000_000, waiting_nanos: 150_000_000, timeslices: 1_100 };
+        assert_eq!(run_queue_wait(&before, &after, Duration::from_secs(1)), (0.05, 500_000.0));
+        assert_eq!(run_queue_wait(&before, &before, Duration::from_secs(1)), (0.0, 0.0));
+    }
+}
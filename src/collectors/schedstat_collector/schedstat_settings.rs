@@ -0,0 +1,14 @@
+#[derive(Debug, Deserialize, Clone)]
+pub struct SchedstatSettings {
+    pub enabled: bool,
+    pub interval_millis: u64,
+}
+
+impl Default for SchedstatSettings {
+    fn default() -> Self {
+        SchedstatSettings {
+            enabled: true,
+            interval_millis: 15_000,
+        }
+    }
+}
@@ -0,0 +1,2 @@
+pub mod sockets_monitor;
+pub mod sockets_settings;
@@ -0,0 +1,302 @@
+//! Collects the state of the TCP and UDP sockets of the host from:
+//!   - `/proc/net/tcp{,6}`: the TCP sockets by state, optionally by local port.
+//!   - `/proc/net/sockstat{,6}`: the sockets in use, orphaned, in time wait and their memory.
+//!   - `/proc/net/snmp` and `/proc/net/netstat`: the retransmits, resets, listen overflows and UDP errors.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::fs;
+
+use crate::collectors::collectors_settings::CollectorsSettings;
+use crate::collectors::metric_recorders::MetricRecorders;
+use crate::errors::{Error, Result};
+use crate::metrics::counter::CounterBuilder;
+use crate::metrics::gauge::GaugeBuilder;
+use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
+use crate::strum::AsStaticRef;
+
+/// States of a TCP socket, as they are numbered by the kernel on `include/net/tcp_states.h`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, AsStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum TcpState {
+    Established,
+    SynSent,
+    SynRecv,
+    FinWait1,
+    FinWait2,
+    TimeWait,
+    Close,
+    CloseWait,
+    LastAck,
+    Listen,
+    Closing,
+    NewSynRecv,
+}
+
+impl TcpState {
+    pub const ALL: [TcpState; 12] = [TcpState::Established, TcpState::SynSent, TcpState::SynRecv, TcpState::FinWait1,
+        TcpState::FinWait2, TcpState::TimeWait, TcpState::Close, TcpState::CloseWait, TcpState::LastAck,
+        TcpState::Listen, TcpState::Closing, TcpState::NewSynRecv];
+
+    fn from_hex(state: &str) -> Option<TcpState> {
+        u8::from_str_radix(state, 16).ok()
+            .filter(|state| *state >= 1 && *state as usize <= TcpState::ALL.len())
+            .map(|state| TcpState::ALL[state as usize - 1])
+    }
+}
+
+/// Counters of `/proc/net/snmp` and `/proc/net/netstat` which are exported: section, field, metric name and description.
+const NETSTAT_COUNTERS: [(&str, &str, &str, &str); 11] = [
+    ("Tcp", "RetransSegs", "netstat_tcp_retransmitted_segments_total", "TCP segments retransmitted."),
+    ("Tcp", "AttemptFails", "netstat_tcp_attempt_fails_total", "TCP connections which failed to be established."),
+    ("Tcp", "EstabResets", "netstat_tcp_established_resets_total", "TCP connections reset from the established or close wait states."),
+    ("Tcp", "OutRsts", "netstat_tcp_out_resets_total", "TCP segments sent with the RST flag."),
+    ("Tcp", "InErrs", "netstat_tcp_in_errors_total", "TCP segments received with errors."),
+    ("TcpExt", "ListenOverflows", "netstat_tcp_listen_overflows_total", "Times the accept queue of a listening socket overflowed."),
+    ("TcpExt", "ListenDrops", "netstat_tcp_listen_drops_total", "Connection requests dropped by a listening socket."),
+    ("Udp", "InErrors", "netstat_udp_in_errors_total", "UDP datagrams received with errors."),
+    ("Udp", "NoPorts", "netstat_udp_no_ports_total", "UDP datagrams received for a port without a listener."),
+    ("Udp", "RcvbufErrors", "netstat_udp_receive_buffer_errors_total", "UDP datagrams dropped because the receive buffer was full."),
+    ("Udp", "SndbufErrors", "netstat_udp_send_buffer_errors_total", "UDP datagrams dropped because the send buffer was full."),
+];
+
+pub struct SocketsMonitor {
+    net_path: PathBuf,
+    interval: Duration,
+    ports: Vec<u16>,
+    page_size: u64,
+    recorders: MetricRecorders,
+}
+
+impl SocketsMonitor {
+    pub fn new(config: &CollectorsSettings) -> SocketsMonitor {
+        SocketsMonitor {
+            net_path: PathBuf::from(&config.procfs_path).join("net"),
+            interval: Duration::from_millis(config.sockets.interval_millis),
+            ports: config.sockets.ports.clone(),
+            page_size: unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64,
+            recorders: MetricRecorders::new(),
+        }
+    }
+
+    pub async fn start(mut self) {
+        loop {
+            match self.collect().await {
+                Ok(_) => {},
+                Err(Error::Io(error)) if error.kind() == io::ErrorKind::NotFound => {
+                    warn!("Sockets collector is stopped since {} is not available. Reason: {}", self.net_path.display(), error);
+                    return;
+                },
+                Err(error) => warn!("Sockets collector failed to collect {}. Reason: {}", self.net_path.display(), error),
+            }
+            tokio::time::delay_for(self.interval).await;
+        }
+    }
+
+    async fn collect(&mut self) -> Result<()> {
+        self.collect_tcp_states().await?;
+        self.collect_sockstat().await?;
+        self.collect_netstat().await
+    }
+
+    async fn collect_tcp_states(&mut self) -> Result<()> {
+        let mut sockets = parse_tcp_sockets(&fs::read_to_string(self.net_path.join("tcp")).await?)?;
+        if let Some(tcp6) = self.read_optional("tcp6").await? {
+            sockets.extend(parse_tcp_sockets(&tcp6)?);
+        }
+        let connections = count_by_state(&sockets, |_| true);
+        for state in TcpState::ALL.iter() {
+            let builder = GaugeBuilder::new("tcp_connections".into(), "TCP sockets by state.".into())
+                .with_tags("state".to_string(), state.as_static().to_string());
+            self.recorders.gauge(builder, connections[state] as f64).await?;
+        }
+        for port in self.ports.clone() {
+            let connections = count_by_state(&sockets, |local_port| local_port == port);
+            for state in TcpState::ALL.iter() {
+                let builder = GaugeBuilder::new("tcp_port_connections".into(), "TCP sockets by state and local port.".into())
+                    .with_tags("port".to_string(), port.to_string())
+                    .with_tags("state".to_string(), state.as_static().to_string());
+                self.recorders.gauge(builder, connections[state] as f64).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn collect_sockstat(&mut self) -> Result<()> {
+        let mut sockstat = parse_sockstat(&fs::read_to_string(self.net_path.join("sockstat")).await?)?;
+        if let Some(sockstat6) = self.read_optional("sockstat6").await? {
+            sockstat.extend(parse_sockstat(&sockstat6)?);
+        }
+        for ((protocol, field), value) in sockstat {
+            let protocol = protocol.to_lowercase();
+            let builder = match field.as_str() {
+                // the socket memory is accounted in pages
+                "mem" => GaugeBuilder::new(format!("sockstat_{}_mem_bytes", protocol), format!("Memory allocated by the {} sockets.", protocol))
+                    .with_measurement_unit(&MEASUREMENT_UNITS.information.bytes),
+                "memory" => GaugeBuilder::new(format!("sockstat_{}_memory_bytes", protocol), format!("Memory used by the {} fragments.", protocol))
+                    .with_measurement_unit(&MEASUREMENT_UNITS.information.bytes),
+                _ => GaugeBuilder::new(format!("sockstat_{}_{}", protocol, field), format!("{} sockets {}.", protocol, field)),
+            };
+            let value = if field == "mem" { value * self.page_size } else { value };
+            self.recorders.gauge(builder, value as f64).await?;
+        }
+        Ok(())
+    }
+
+    async fn collect_netstat(&mut self) -> Result<()> {
+        let mut netstat = parse_netstat(&fs::read_to_string(self.net_path.join("snmp")).await?)?;
+        if let Some(tcp_ext) = self.read_optional("netstat").await? {
+            netstat.extend(parse_netstat(&tcp_ext)?);
+        }
+        for (section, field, name, description) in NETSTAT_COUNTERS.iter() {
+            if let Some(value) = netstat.get(&(section.to_string(), field.to_string())) {
+                let builder = CounterBuilder::new(name.to_string(), description.to_string());
+                self.recorders.counter_total(builder, *value).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a file which isn't available on every host, e.g. without IPv6.
+    async fn read_optional(&self, file_name: &str) -> Result<Option<String>> {
+        match fs::read_to_string(self.net_path.join(file_name)).await {
+            Ok(content) => Ok(Some(content)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+/// Returns the local port and the state of every socket of `/proc/net/tcp` or `/proc/net/tcp6`.
+///
+/// Every line after the header is `sl local_address:port rem_address:port st ...`, in hexadecimal.
+pub fn parse_tcp_sockets(tcp: &str) -> Result<Vec<(u16, TcpState)>> {
+    tcp.lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let local_port = fields.get(1)
+                .and_then(|address| address.rsplit(':').next())
+                .and_then(|port| u16::from_str_radix(port, 16).ok());
+            let state = fields.get(3).and_then(|state| TcpState::from_hex(state));
+            local_port.zip(state)
+                .ok_or_else(|| Error::Msg(format!("Unexpected TCP socket format: {}", line)))
+        })
+        .collect()
+}
+
+fn count_by_state<F>(sockets: &[(u16, TcpState)], port_filter: F) -> HashMap<TcpState, u64>
+    where F: Fn(u16) -> bool
+{
+    let mut connections: HashMap<TcpState, u64> = TcpState::ALL.iter().map(|state| (*state, 0)).collect();
+    for (local_port, state) in sockets {
+        if port_filter(*local_port) {
+            *connections.get_mut(state).unwrap() += 1;
+        }
+    }
+    connections
+}
+
+/// Every line of `/proc/net/sockstat` is `PROTOCOL: field value field value ...`,
+/// except for the first one which is `sockets: used value`.
+pub fn parse_sockstat(sockstat: &str) -> Result<HashMap<(String, String), u64>> {
+    let mut values = HashMap::new();
+    for line in sockstat.lines().filter(|line| !line.trim().is_empty()) {
+        let mut fields = line.split_whitespace();
+        let protocol = fields.next()
+            .map(|protocol| protocol.trim_end_matches(':'))
+            .ok_or_else(|| Error::Msg(format!("Unexpected sockstat format: {}", line)))?;
+        let fields: Vec<&str> = fields.collect();
+        for pair in fields.chunks(2) {
+            let value = pair.get(1)
+                .and_then(|value| value.parse::<u64>().ok())
+                .ok_or_else(|| Error::Msg(format!("Unexpected sockstat format: {}", line)))?;
+            values.insert((protocol.to_string(), pair[0].to_string()), value);
+        }
+    }
+    Ok(values)
+}
+
+/// `/proc/net/snmp` and `/proc/net/netstat` have two lines per section, the first one with the names
+/// of the fields and the second one with their values, e.g. `Tcp: RtoAlgorithm RtoMin ...` and `Tcp: 1 200 ...`.
+/// The negative values (e.g. `MaxConn` when it's dynamic) are skipped.
+pub fn parse_netstat(netstat: &str) -> Result<HashMap<(String, String), u64>> {
+    let mut values = HashMap::new();
+    let lines: Vec<&str> = netstat.lines().filter(|line| !line.trim().is_empty()).collect();
+    for pair in lines.chunks(2) {
+        let (names, numbers) = match pair {
+            [names, numbers] => (names, numbers),
+            _ => return Err(Error::Msg(format!("Unexpected netstat format: {}", pair[0]))),
+        };
+        let mut names = names.split_whitespace();
+        let mut numbers = numbers.split_whitespace();
+        let section = names.next().unwrap_or_default();
+        if numbers.next() != Some(section) {
+            return Err(Error::Msg(format!("Unexpected netstat format on section {}", section)));
+        }
+        let section = section.trim_end_matches(':');
+        for (name, number) in names.zip(numbers) {
+            if let Ok(value) = number.parse::<u64>() {
+                values.insert((section.to_string(), name.to_string()), value);
+            }
+        }
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(file_name: &str) -> String {
+        std::fs::read_to_string(format!("{}/fixtures/proc/net/{}", env!("CARGO_MANIFEST_DIR"), file_name)).unwrap()
+    }
+
+    #[test]
+    fn test_count_tcp_sockets_by_state_and_port() {
+        let mut sockets = parse_tcp_sockets(&fixture("tcp")).unwrap();
+        sockets.extend(parse_tcp_sockets(&fixture("tcp6")).unwrap());
+        assert_eq!(sockets.len(), 8);
+
+        let connections = count_by_state(&sockets, |_| true);
+        assert_eq!(connections[&TcpState::Listen], 3);
+        assert_eq!(connections[&TcpState::Established], 3);
+        assert_eq!(connections[&TcpState::TimeWait], 1);
+        assert_eq!(connections[&TcpState::CloseWait], 1);
+        assert_eq!(connections[&TcpState::SynSent], 0);
+
+        let connections = count_by_state(&sockets, |local_port| local_port == 8080);
+        assert_eq!(connections[&TcpState::Listen], 1);
+        assert_eq!(connections[&TcpState::Established], 2);
+        assert_eq!(connections[&TcpState::TimeWait], 1);
+        assert_eq!(connections[&TcpState::CloseWait], 0);
+    }
+
+    #[test]
+    fn test_parse_sockstat() {
+        let sockstat = parse_sockstat(&fixture("sockstat")).unwrap();
+        assert_eq!(sockstat[&("sockets".to_string(), "used".to_string())], 231);
+        assert_eq!(sockstat[&("TCP".to_string(), "orphan".to_string())], 1);
+        assert_eq!(sockstat[&("TCP".to_string(), "tw".to_string())], 1);
+        assert_eq!(sockstat[&("TCP".to_string(), "mem".to_string())], 3);
+        assert_eq!(sockstat[&("UDP".to_string(), "mem".to_string())], 4);
+        let sockstat6 = parse_sockstat(&fixture("sockstat6")).unwrap();
+        assert_eq!(sockstat6[&("TCP6".to_string(), "inuse".to_string())], 2);
+    }
+
+    #[test]
+    fn test_parse_netstat() {
+        let snmp = parse_netstat(&fixture("snmp")).unwrap();
+        assert_eq!(snmp[&("Tcp".to_string(), "RetransSegs".to_string())], 412);
+        assert_eq!(snmp[&("Udp".to_string(), "RcvbufErrors".to_string())], 5);
+        assert!(!snmp.contains_key(&("Tcp".to_string(), "MaxConn".to_string())));
+        let netstat = parse_netstat(&fixture("netstat")).unwrap();
+        assert_eq!(netstat[&("TcpExt".to_string(), "ListenOverflows".to_string())], 9);
+        assert_eq!(netstat[&("TcpExt".to_string(), "ListenDrops".to_string())], 11);
+        assert!(parse_netstat("Tcp: RtoAlgorithm RtoMin\n").is_err());
+    }
+}
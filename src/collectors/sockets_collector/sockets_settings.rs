@@ -0,0 +1,18 @@
+/// `ports` are the local ports whose TCP sockets are also counted by state on their own,
+/// e.g. the ports the services of the host listen on.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SocketsSettings {
+    pub enabled: bool,
+    pub interval_millis: u64,
+    pub ports: Vec<u16>,
+}
+
+impl Default for SocketsSettings {
+    fn default() -> Self {
+        SocketsSettings {
+            enabled: true,
+            interval_millis: 15_000,
+            ports: vec![],
+        }
+    }
+}
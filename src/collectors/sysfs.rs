@@ -0,0 +1,49 @@
+//! Helpers to read the `sysfs` attributes, which hold a single value per file.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use tokio::fs;
+
+use crate::errors::{Error, Result};
+
+/// Reads an attribute, without the trailing new line.
+pub async fn read_string<P: AsRef<Path>>(path: P) -> Result<String> {
+    let content = fs::read_to_string(path.as_ref()).await?;
+    Ok(content.trim().to_string())
+}
+
+pub async fn read_value<T: FromStr, P: AsRef<Path>>(path: P) -> Result<T> {
+    let content = read_string(path.as_ref()).await?;
+    content.parse::<T>()
+        .map_err(|_| Error::Msg(format!("Unexpected value '{}' on {}", content, path.as_ref().display())))
+}
+
+/// Reads an attribute which isn't exposed by every kernel or driver.
+pub async fn read_optional_value<T: FromStr, P: AsRef<Path>>(path: P) -> Result<Option<T>> {
+    match read_value(path).await {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::Io(error)) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Lists the entries of a directory whose name starts with `prefix`, sorted by name.
+/// A missing directory has no entries, e.g. `/sys/devices/system/node` on a kernel without NUMA.
+pub async fn list_dir<P: AsRef<Path>>(path: P, prefix: &str) -> Result<Vec<(String, PathBuf)>> {
+    let mut read_dir = match fs::read_dir(path.as_ref()).await {
+        Ok(read_dir) => read_dir,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(error.into()),
+    };
+    let mut entries = Vec::new();
+    while let Some(entry) = read_dir.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with(prefix) {
+            entries.push((name, entry.path()));
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
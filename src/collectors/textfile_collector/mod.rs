@@ -0,0 +1,2 @@
+pub mod textfile_monitor;
+pub mod textfile_settings;
@@ -0,0 +1,169 @@
+//! Collects the metrics written by other processes, like cron jobs, on the `*.prom` files of a directory
+//! in the Prometheus text format.
+//!
+//! Every file exports its modification time and whether it failed to be parsed, so a broken file
+//! is visible instead of silently dropped. The files are written by other processes, so they should
+//! be written to a temporary file and renamed to be updated atomically.
+//!
+//! Every file has its own recorders, so the series of a file are removed when they are gone from it or the
+//! file is deleted. A series can only be published by one file, the file listed last which publishes it
+//! too fails to be collected.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use tokio::fs;
+
+use crate::collectors::collectors_settings::CollectorsSettings;
+use crate::collectors::metric_recorders::{FamilySeries, MetricRecorders, to_tag_value};
+use crate::collectors::parsers::prometheus_text;
+use crate::collectors::sysfs;
+use crate::errors::{Error, Result};
+use crate::metrics::gauge::GaugeBuilder;
+use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
+use crate::metrics::metric::MetricId;
+
+pub struct TextfileMonitor {
+    directory: PathBuf,
+    interval: Duration,
+    recorders: MetricRecorders,
+    /// The recorders of the series read from every file, by file.
+    files: HashMap<String, MetricRecorders>,
+}
+
+impl TextfileMonitor {
+    pub fn new(config: &CollectorsSettings) -> TextfileMonitor {
+        TextfileMonitor {
+            directory: PathBuf::from(&config.textfile.directory),
+            interval: Duration::from_millis(config.textfile.interval_millis),
+            recorders: MetricRecorders::new(),
+            files: HashMap::new(),
+        }
+    }
+
+    pub async fn start(mut self) {
+        loop {
+            if let Err(error) = self.collect().await {
+                warn!("Textfile collector failed to collect {}. Reason: {}", self.directory.display(), error);
+            }
+            tokio::time::delay_for(self.interval).await;
+        }
+    }
+
+    async fn collect(&mut self) -> Result<()> {
+        let mut files = HashSet::new();
+        let mut published = HashSet::new();
+        for (file_name, path) in sysfs::list_dir(&self.directory, "").await? {
+            if !file_name.ends_with(".prom") {
+                continue;
+            }
+            let file = to_tag_value(&file_name);
+            let error = match self.collect_file(&path, &file, &mut published).await {
+                Ok(_) => 0.0,
+                Err(error) => {
+                    warn!("Textfile {} couldn't be collected. Reason: {}", path.display(), error);
+                    1.0
+                },
+            };
+            self.recorders.gauge(scrape_error(&file), error).await?;
+            files.insert(file);
+        }
+        let deleted: Vec<String> = self.files.keys().filter(|file| !files.contains(*file)).cloned().collect();
+        for file in deleted {
+            if let Some(mut recorders) = self.files.remove(&file) {
+                recorders.remove_families().await;
+            }
+            self.recorders.remove_gauge(mtime(&file)).await?;
+            self.recorders.remove_gauge(scrape_error(&file)).await?;
+        }
+        Ok(())
+    }
+
+    /// Publishes the series of a file, unless any of them was already published by another file.
+    async fn collect_file(&mut self, path: &Path, file: &str, published: &mut HashSet<MetricId>) -> Result<()> {
+        let modified = fs::metadata(path).await?.modified()?;
+        let mtime_seconds = modified.duration_since(UNIX_EPOCH)
+            .map_err(|error| Error::Msg(format!("Modification time before the epoch. Reason: {}", error)))?;
+        self.recorders.gauge(mtime(file), mtime_seconds.as_secs_f64()).await?;
+        // the whole file is parsed and checked before recording anything, so a broken file doesn't publish half of its metrics
+        let series = FamilySeries::from(&prometheus_text::parse(&fs::read_to_string(path).await?)?)?;
+        if series.metric_ids().any(|metric_id| published.contains(&metric_id)) {
+            return Err(Error::Msg("Some of its series are published by another textfile too".to_string()));
+        }
+        published.extend(series.metric_ids());
+        self.files.entry(file.to_string()).or_default().record_series(series).await
+    }
+}
+
+fn mtime(file: &str) -> GaugeBuilder {
+    GaugeBuilder::new("textfile_mtime_seconds".into(), "Modification time of the textfile.".into())
+        .with_tags("file".to_string(), file.to_string())
+        .with_measurement_unit(&MEASUREMENT_UNITS.time.seconds)
+}
+
+fn scrape_error(file: &str) -> GaugeBuilder {
+    GaugeBuilder::new("textfile_scrape_error".into(), "Whether the textfile failed to be read or parsed.".into())
+        .with_tags("file".to_string(), file.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collectors::collectors_settings::CollectorsSettings;
+    use crate::metrics::registry;
+
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    #[test]
+    fn test_collect_files() {
+        let mut config = CollectorsSettings::default();
+        config.textfile.directory = format!("{}/fixtures/textfile", env!("CARGO_MANIFEST_DIR"));
+        let mut monitor = TextfileMonitor::new(&config);
+        let directory = monitor.directory.clone();
+
+        assert!(aw!(monitor.collect_file(&directory.join("backup.prom"), "backup.prom", &mut HashSet::new())).is_ok());
+        // it can be collected again
+        assert!(aw!(monitor.collect_file(&directory.join("backup.prom"), "backup.prom", &mut HashSet::new())).is_ok());
+        assert!(aw!(monitor.collect_file(&directory.join("broken.prom"), "broken.prom", &mut HashSet::new())).is_err());
+        assert!(aw!(monitor.collect_file(&directory.join("missing.prom"), "missing.prom", &mut HashSet::new())).is_err());
+        assert!(aw!(monitor.collect()).is_ok());
+    }
+
+    fn registered_gauges(name: &str) -> usize {
+        registry::global_registry().gauges().iter()
+            .filter(|gauge| aw!(gauge.read()).metric_description().name() == name)
+            .count()
+    }
+
+    #[test]
+    fn test_series_are_published_by_one_file_and_removed_with_it() {
+        let directory = std::env::temp_dir().join(format!("rusty-advisor-textfile-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let text = "# TYPE textfile_test_shared gauge\ntextfile_test_shared 1\n";
+        std::fs::write(directory.join("first.prom"), text).unwrap();
+        std::fs::write(directory.join("second.prom"), text).unwrap();
+        let mut config = CollectorsSettings::default();
+        config.textfile.directory = directory.to_string_lossy().into_owned();
+        let mut monitor = TextfileMonitor::new(&config);
+
+        let mut published = HashSet::new();
+        assert!(aw!(monitor.collect_file(&directory.join("first.prom"), "first.prom", &mut published)).is_ok());
+        assert!(aw!(monitor.collect_file(&directory.join("second.prom"), "second.prom", &mut published)).is_err());
+
+        std::fs::remove_file(directory.join("second.prom")).unwrap();
+        assert!(aw!(monitor.collect()).is_ok());
+        assert_eq!(registered_gauges("textfile_test_shared"), 1);
+        std::fs::remove_file(directory.join("first.prom")).unwrap();
+        assert!(aw!(monitor.collect()).is_ok());
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(registered_gauges("textfile_test_shared"), 0);
+        assert!(monitor.files.is_empty());
+    }
+}
@@ -0,0 +1,17 @@
+/// Reads the metrics of the `*.prom` files of `directory`, e.g. written by cron jobs.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TextfileSettings {
+    pub enabled: bool,
+    pub interval_millis: u64,
+    pub directory: String,
+}
+
+impl Default for TextfileSettings {
+    fn default() -> Self {
+        TextfileSettings {
+            enabled: false,
+            interval_millis: 15_000,
+            directory: "/var/lib/rusty-advisor/textfile".into(),
+        }
+    }
+}
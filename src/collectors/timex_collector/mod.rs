@@ -0,0 +1,2 @@
+pub mod timex_monitor;
+pub mod timex_settings;
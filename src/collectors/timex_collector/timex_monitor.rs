@@ -0,0 +1,134 @@
+//! Collects the state of the kernel clock discipline with the `adjtimex` syscall, to know when
+//! the wall clock of the host, which the timestamps of the snapshots come from, is drifting.
+//!
+//! More details can be found at https://man7.org/linux/man-pages/man2/adjtimex.2.html
+
+use std::{io, mem};
+use std::time::Duration;
+
+use crate::collectors::collectors_settings::CollectorsSettings;
+use crate::collectors::metric_recorders::MetricRecorders;
+use crate::errors::Result;
+use crate::metrics::gauge::GaugeBuilder;
+use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
+
+/// `freq` is in ppm with a 16 bits fractional part.
+const FREQUENCY_SCALE: f64 = 65_536.0 * 1_000_000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimexStats {
+    pub offset_seconds: f64,
+    /// Frequency adjustment applied to the clock, e.g. 0.000001 for 1ppm faster.
+    pub frequency_adjustment_ratio: f64,
+    pub max_error_seconds: f64,
+    pub estimated_error_seconds: f64,
+    pub synchronized: bool,
+    pub tai_offset_seconds: f64,
+}
+
+impl TimexStats {
+    /// `state` is the clock state returned by `adjtimex`, which is `TIME_ERROR` while the clock is unsynchronized.
+    pub fn from(timex: &libc::timex, state: libc::c_int) -> TimexStats {
+        // the offset is in nanoseconds when the clock is in nanosecond resolution mode, or in microseconds otherwise
+        let offset_scale = if timex.status & libc::STA_NANO != 0 { 1e-9 } else { 1e-6 };
+        TimexStats {
+            offset_seconds: timex.offset as f64 * offset_scale,
+            frequency_adjustment_ratio: timex.freq as f64 / FREQUENCY_SCALE,
+            max_error_seconds: timex.maxerror as f64 * 1e-6,
+            estimated_error_seconds: timex.esterror as f64 * 1e-6,
+            synchronized: state != libc::TIME_ERROR && timex.status & libc::STA_UNSYNC == 0,
+            tai_offset_seconds: timex.tai as f64,
+        }
+    }
+}
+
+/// Reads the state of the clock without adjusting it, which doesn't require any privilege.
+pub fn read_timex() -> Result<TimexStats> {
+    let mut timex: libc::timex = unsafe { mem::zeroed() };
+    let state = unsafe { libc::adjtimex(&mut timex) };
+    if state < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(TimexStats::from(&timex, state))
+}
+
+pub struct TimexMonitor {
+    interval: Duration,
+    recorders: MetricRecorders,
+}
+
+impl TimexMonitor {
+    pub fn new(config: &CollectorsSettings) -> TimexMonitor {
+        TimexMonitor {
+            interval: Duration::from_millis(config.timex.interval_millis),
+            recorders: MetricRecorders::new(),
+        }
+    }
+
+    pub async fn start(mut self) {
+        loop {
+            if let Err(error) = self.collect().await {
+                warn!("Timex collector failed to collect. Reason: {}", error);
+            }
+            tokio::time::delay_for(self.interval).await;
+        }
+    }
+
+    async fn collect(&mut self) -> Result<()> {
+        let stats = read_timex()?;
+        let seconds = &MEASUREMENT_UNITS.time.seconds;
+        let gauges = [
+            ("timex_offset_seconds", "Offset between the kernel clock and the time source.", stats.offset_seconds, seconds),
+            ("timex_frequency_adjustment_ratio", "Frequency adjustment applied to the kernel clock.", stats.frequency_adjustment_ratio, &MEASUREMENT_UNITS.none),
+            ("timex_maxerror_seconds", "Maximum error of the kernel clock.", stats.max_error_seconds, seconds),
+            ("timex_estimated_error_seconds", "Estimated error of the kernel clock.", stats.estimated_error_seconds, seconds),
+            ("timex_sync_status", "Whether the kernel clock is synchronized with a time source.", if stats.synchronized { 1.0 } else { 0.0 }, &MEASUREMENT_UNITS.none),
+            ("timex_tai_offset_seconds", "Offset between the International Atomic Time and the UTC.", stats.tai_offset_seconds, seconds),
+        ];
+        for (name, description, value, measurement_unit) in gauges.iter() {
+            let builder = GaugeBuilder::new(name.to_string(), description.to_string())
+                .with_measurement_unit(measurement_unit);
+            self.recorders.gauge(builder, *value).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::tests::ApproxComparison;
+
+    use super::*;
+
+    fn timex(status: libc::c_int) -> libc::timex {
+        let mut timex: libc::timex = unsafe { mem::zeroed() };
+        timex.offset = -1_500;
+        timex.freq = -1_137_414;
+        timex.maxerror = 16_000;
+        timex.esterror = 250;
+        timex.status = status;
+        timex.tai = 37;
+        timex
+    }
+
+    #[test]
+    fn test_timex_stats_in_seconds() {
+        let stats = TimexStats::from(&timex(libc::STA_PLL), libc::TIME_OK);
+        assert!(stats.offset_seconds.is_eq(-0.0015, 2));
+        assert!(stats.frequency_adjustment_ratio.is_eq(-17.355_560_302_734_375e-6, 2));
+        assert!(stats.max_error_seconds.is_eq(0.016, 2));
+        assert!(stats.estimated_error_seconds.is_eq(0.000_25, 2));
+        assert!(stats.synchronized);
+        assert!(stats.tai_offset_seconds.is_eq(37.0, 2));
+
+        let stats = TimexStats::from(&timex(libc::STA_PLL | libc::STA_NANO), libc::TIME_OK);
+        assert!(stats.offset_seconds.is_eq(-0.000_001_5, 2));
+    }
+
+    #[test]
+    fn test_unsynchronized_clock() {
+        assert!(!TimexStats::from(&timex(libc::STA_UNSYNC), libc::TIME_ERROR).synchronized);
+        assert!(!TimexStats::from(&timex(libc::STA_UNSYNC), libc::TIME_OK).synchronized);
+        assert!(read_timex().is_ok());
+    }
+}
@@ -0,0 +1,14 @@
+#[derive(Debug, Deserialize, Clone)]
+pub struct TimexSettings {
+    pub enabled: bool,
+    pub interval_millis: u64,
+}
+
+impl Default for TimexSettings {
+    fn default() -> Self {
+        TimexSettings {
+            enabled: true,
+            interval_millis: 15_000,
+        }
+    }
+}
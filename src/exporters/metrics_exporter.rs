@@ -7,6 +7,8 @@ use hdrhistogram::Histogram as HdrHistogram;
 use tokio::sync::broadcast::Sender;
 use tokio::task::JoinError;
 
+use crate::metrics::counter::Counter;
+use crate::metrics::gauge::Gauge;
 use crate::metrics::histogram::{Histogram, HistogramSettings};
 use crate::metrics::measurement_unit::MeasurementUnit;
 use crate::metrics::metric::MetricDescription;
@@ -47,12 +49,23 @@ impl MetricsExporter {
     async fn tick() -> MetricsSnapshot {
         let start = Instant::now();
         let timestamp_in_millis = time::current_millis();
-        let metrics = registry::global_registry().histograms();
-        let mut samples = Vec::<MetricSample>::with_capacity(metrics.len());
-        for metric in metrics {
+        let registry = registry::global_registry();
+        let histograms = registry.histograms();
+        let counters = registry.counters();
+        let gauges = registry.gauges();
+        let mut samples = Vec::<MetricSample>::with_capacity(histograms.len() + counters.len() + gauges.len());
+        for metric in histograms {
             let mut mut_metric = metric.write().await;
             samples.push(Self::sample_histograms(mut_metric.deref_mut()));
         }
+        for metric in counters {
+            let mut mut_metric = metric.write().await;
+            samples.push(Self::sample_counters(mut_metric.deref_mut()));
+        }
+        for metric in gauges {
+            let mut mut_metric = metric.write().await;
+            samples.push(Self::sample_gauges(mut_metric.deref_mut()));
+        }
         let metric_snapshot = MetricsSnapshot::new(samples, timestamp_in_millis);
         let delta = start.elapsed().as_millis() as u64;
         info!("Metric Snapshot created in {} millis", delta);
@@ -62,20 +75,16 @@ impl MetricsExporter {
     fn sample_histograms(histogram: &mut Histogram) -> MetricSample {
         let histogram_sample = histogram.sample(true);
         MetricSample::Histogram(histogram.metric_description().clone(), histogram_sample)
-        // unimplemented!()
-        // match metric_kind {
-        //     MetricKind::Histogram => {
-        //         let histogram_sample = histogram.sample(true);
-        //         MetricSample::Histogram(metric_description, histogram_sample)
-        //     },
-        //     MetricKind::Counter => {
-        //         unimplemented!()
-        //     },
-        //     MetricKind::Gauge => {
-        //         unimplemented!()
-        //
-        //     },
-        // }
+    }
+
+    fn sample_counters(counter: &mut Counter) -> MetricSample {
+        let counter_sample = counter.sample();
+        MetricSample::Counter(counter.metric_description().clone(), counter_sample)
+    }
+
+    fn sample_gauges(gauge: &mut Gauge) -> MetricSample {
+        let gauge_sample = gauge.sample();
+        MetricSample::Gauge(gauge.metric_description().clone(), gauge_sample)
     }
 }
 
@@ -86,7 +95,7 @@ pub struct MetricsSnapshot {
 }
 
 impl MetricsSnapshot {
-    fn new(samples: Vec<MetricSample>, timestamp_in_millis: u64) -> MetricsSnapshot {
+    pub(crate) fn new(samples: Vec<MetricSample>, timestamp_in_millis: u64) -> MetricsSnapshot {
         MetricsSnapshot {
             samples,
             timestamp_in_millis,
@@ -109,36 +118,93 @@ pub enum MetricSample {
     Histogram(MetricDescription, HistogramSample),
 }
 
+impl MetricSample {
+    pub fn metric_id(&self) -> u64 {
+        match self {
+            MetricSample::Counter(metric_description, _)
+            | MetricSample::Gauge(metric_description, _)
+            | MetricSample::Histogram(metric_description, _) => metric_description.id,
+        }
+    }
+}
+
+/// Holds the increments of a counter since the previous snapshot.
 #[derive(Debug)]
 pub struct CounterSample {
     value: u64,
+    measurement_unit: &'static MeasurementUnit,
+    updated: bool,
 }
 
 impl CounterSample {
-    pub fn new(value: u64) -> CounterSample {
+    pub fn new(value: u64, measurement_unit: &'static MeasurementUnit) -> CounterSample {
         CounterSample {
             value,
+            measurement_unit,
+            updated: true,
         }
     }
+
+    /// Whether the counter was updated since the previous sample, even if it wasn't incremented.
+    pub fn with_updated(mut self, updated: bool) -> CounterSample {
+        self.updated = updated;
+        self
+    }
+
+    pub fn updated(&self) -> bool {
+        self.updated
+    }
+
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+
+    pub fn measurement_unit(&self) -> &'static MeasurementUnit {
+        self.measurement_unit
+    }
 }
 
+/// Holds the value of a gauge at the moment of the snapshot.
 #[derive(Debug)]
 pub struct GaugeSample {
-    value: u64,
+    value: f64,
+    measurement_unit: &'static MeasurementUnit,
+    updated: bool,
 }
 
 impl GaugeSample {
-    pub fn new(value: u64) -> GaugeSample {
+    pub fn new(value: f64, measurement_unit: &'static MeasurementUnit) -> GaugeSample {
         GaugeSample {
             value,
+            measurement_unit,
+            updated: true,
         }
     }
+
+    /// Whether the gauge was set since the previous sample, even if to the same value.
+    pub fn with_updated(mut self, updated: bool) -> GaugeSample {
+        self.updated = updated;
+        self
+    }
+
+    pub fn updated(&self) -> bool {
+        self.updated
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    pub fn measurement_unit(&self) -> &'static MeasurementUnit {
+        self.measurement_unit
+    }
 }
 
 #[derive(Debug)]
 pub struct HistogramSample {
     hdr_histogram: HdrHistogram<u64>,
     histogram_settings: HistogramSettings,
+    updated: bool,
 }
 
 impl HistogramSample {
@@ -146,9 +212,20 @@ impl HistogramSample {
         HistogramSample {
             hdr_histogram,
             histogram_settings,
+            updated: true,
         }
     }
 
+    /// Whether the histogram was recorded on since the previous sample, or got a new recorder.
+    pub fn with_updated(mut self, updated: bool) -> HistogramSample {
+        self.updated = updated;
+        self
+    }
+
+    pub fn updated(&self) -> bool {
+        self.updated
+    }
+
     pub fn hdr_histogram(&self) -> &HdrHistogram<u64> {
         &self.hdr_histogram
     }
@@ -0,0 +1,126 @@
+//! Negotiation of the compression of the scrape responses, from the `Accept-Encoding` header of the scrape.
+
+use std::io::Write;
+
+use flate2::Compression;
+use flate2::write::{GzEncoder, ZlibEncoder};
+
+use crate::errors::Result;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContentEncoding {
+    Identity,
+    Gzip,
+    /// The zlib format, which is what `deflate` means on HTTP.
+    Deflate,
+}
+
+impl ContentEncoding {
+    /// The encoding with the highest quality on the `Accept-Encoding` header, the first one on a tie.
+    /// A wildcard accepts the encodings which aren't on the header, gzip before deflate.
+    pub fn negotiate(accept_encoding: Option<&str>) -> ContentEncoding {
+        let mut qualities: Vec<(ContentEncoding, f32)> = Vec::new();
+        let mut wildcard_quality = None;
+        for coding in accept_encoding.unwrap_or_default().split(',') {
+            let mut params = coding.split(';').map(str::trim);
+            let name = params.next().unwrap_or_default().to_ascii_lowercase();
+            let quality = params
+                .filter_map(|param| param.strip_prefix("q="))
+                .map(|value| value.trim().parse().unwrap_or(0.0))
+                .next()
+                .unwrap_or(1f32);
+            let encoding = match name.as_str() {
+                "gzip" | "x-gzip" => ContentEncoding::Gzip,
+                "deflate" => ContentEncoding::Deflate,
+                "*" => {
+                    wildcard_quality = Some(quality);
+                    continue;
+                },
+                _ => continue,
+            };
+            if !qualities.iter().any(|(listed, _)| *listed == encoding) {
+                qualities.push((encoding, quality));
+            }
+        }
+        if let Some(quality) = wildcard_quality {
+            for encoding in [ContentEncoding::Gzip, ContentEncoding::Deflate].iter() {
+                if !qualities.iter().any(|(listed, _)| listed == encoding) {
+                    qualities.push((*encoding, quality));
+                }
+            }
+        }
+        let mut negotiated = (ContentEncoding::Identity, 0f32);
+        for (encoding, quality) in qualities {
+            if quality > negotiated.1 {
+                negotiated = (encoding, quality);
+            }
+        }
+        negotiated.0
+    }
+
+    /// The value of the `Content-Encoding` header, none when the body isn't compressed.
+    pub fn header_value(&self) -> Option<&'static str> {
+        match self {
+            ContentEncoding::Identity => None,
+            ContentEncoding::Gzip => Some("gzip"),
+            ContentEncoding::Deflate => Some("deflate"),
+        }
+    }
+
+    pub fn encode(&self, body: Vec<u8>) -> Result<Vec<u8>> {
+        let compressed = match self {
+            ContentEncoding::Identity => return Ok(body),
+            ContentEncoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::with_capacity(body.len() / 4), Compression::fast());
+                encoder.write_all(&body)?;
+                encoder.finish()?
+            },
+            ContentEncoding::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::with_capacity(body.len() / 4), Compression::fast());
+                encoder.write_all(&body)?;
+                encoder.finish()?
+            },
+        };
+        Ok(compressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::{GzDecoder, ZlibDecoder};
+
+    use super::*;
+
+    #[test]
+    fn test_negotiate_encoding() {
+        assert_eq!(ContentEncoding::negotiate(Some("gzip, deflate, br")), ContentEncoding::Gzip);
+        assert_eq!(ContentEncoding::negotiate(Some("gzip;q=0.5, deflate")), ContentEncoding::Deflate);
+        assert_eq!(ContentEncoding::negotiate(Some("br, *;q=0.1")), ContentEncoding::Gzip);
+        assert_eq!(ContentEncoding::negotiate(Some("gzip;q=0, identity")), ContentEncoding::Identity);
+        assert_eq!(ContentEncoding::negotiate(Some("br")), ContentEncoding::Identity);
+        // the wildcard doesn't accept an encoding refused explicitly
+        assert_eq!(ContentEncoding::negotiate(Some("gzip;q=0, *")), ContentEncoding::Deflate);
+        assert_eq!(ContentEncoding::negotiate(Some("*, gzip;q=0, deflate;q=0")), ContentEncoding::Identity);
+        assert_eq!(ContentEncoding::negotiate(None), ContentEncoding::Identity);
+    }
+
+    #[test]
+    fn test_encode_body() {
+        let body = "hiccups_duration_seconds_bucket{le=\"0.0001\"} 3\n".repeat(100).into_bytes();
+
+        let compressed = ContentEncoding::Gzip.encode(body.clone()).unwrap();
+        assert!(compressed.len() < body.len());
+        let mut decoded = vec![];
+        GzDecoder::new(&compressed[..]).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, body);
+
+        let compressed = ContentEncoding::Deflate.encode(body.clone()).unwrap();
+        let mut decoded = vec![];
+        ZlibDecoder::new(&compressed[..]).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, body);
+
+        assert_eq!(ContentEncoding::Identity.encode(body.clone()).unwrap(), body);
+    }
+}
@@ -0,0 +1,82 @@
+//! Negotiation of the format the metrics are exposed in, from the `Accept` header of the scrape.
+//!
+//! The Prometheus text format is the default, when the header is missing or doesn't accept any other format.
+
+const TEXT_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+const PROTOBUF_CONTENT_TYPE: &str = "application/vnd.google.protobuf; proto=io.prometheus.client.MetricFamily; encoding=delimited";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExpositionFormat {
+    Text,
+    OpenMetrics,
+    Protobuf,
+}
+
+impl ExpositionFormat {
+    /// The format with the highest quality on the `Accept` header, the first one on a tie.
+    pub fn negotiate(accept: Option<&str>) -> ExpositionFormat {
+        let mut negotiated = (ExpositionFormat::Text, 0f32);
+        for media_range in accept.unwrap_or_default().split(',') {
+            let mut params = media_range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+            let mut quality = 1f32;
+            let mut version = None;
+            let mut proto = None;
+            let mut encoding = None;
+            for param in params {
+                match param.split_once('=') {
+                    Some(("q", value)) => quality = value.trim().parse().unwrap_or(0.0),
+                    Some(("version", value)) => version = Some(value.trim().trim_matches('"')),
+                    Some(("proto", value)) => proto = Some(value.trim().trim_matches('"')),
+                    Some(("encoding", value)) => encoding = Some(value.trim().trim_matches('"')),
+                    _ => {},
+                }
+            }
+            let format = match (media_type.as_str(), version) {
+                ("application/openmetrics-text", None) | ("application/openmetrics-text", Some("1.0.0")) |
+                ("application/openmetrics-text", Some("0.0.1")) => ExpositionFormat::OpenMetrics,
+                ("application/vnd.google.protobuf", _)
+                if proto.unwrap_or("io.prometheus.client.MetricFamily") == "io.prometheus.client.MetricFamily" &&
+                    encoding.unwrap_or("delimited") == "delimited" => ExpositionFormat::Protobuf,
+                ("text/plain", _) => ExpositionFormat::Text,
+                _ => continue,
+            };
+            if quality > negotiated.1 {
+                negotiated = (format, quality);
+            }
+        }
+        negotiated.0
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExpositionFormat::Text => TEXT_CONTENT_TYPE,
+            ExpositionFormat::OpenMetrics => OPENMETRICS_CONTENT_TYPE,
+            ExpositionFormat::Protobuf => PROTOBUF_CONTENT_TYPE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_format() {
+        // the header sent by Prometheus 2.x when OpenMetrics is enabled
+        let prometheus = "application/openmetrics-text;version=1.0.0,application/openmetrics-text;version=0.0.1;q=0.75,\
+                          text/plain;version=0.0.4;q=0.5,*/*;q=0.1";
+        assert_eq!(ExpositionFormat::negotiate(Some(prometheus)), ExpositionFormat::OpenMetrics);
+        assert_eq!(ExpositionFormat::negotiate(Some("text/plain;version=0.0.4;q=0.9,application/openmetrics-text;q=0.5")), ExpositionFormat::Text);
+        assert_eq!(ExpositionFormat::negotiate(Some("application/openmetrics-text; version=2.0.0")), ExpositionFormat::Text);
+        assert_eq!(ExpositionFormat::negotiate(Some("application/openmetrics-text;q=0")), ExpositionFormat::Text);
+        assert_eq!(ExpositionFormat::negotiate(Some("*/*")), ExpositionFormat::Text);
+        // the header sent by Prometheus 2.x before OpenMetrics
+        let prometheus = "application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=delimited;q=0.7,\
+                          text/plain;version=0.0.4;q=0.3,*/*;q=0.1";
+        assert_eq!(ExpositionFormat::negotiate(Some(prometheus)), ExpositionFormat::Protobuf);
+        assert_eq!(ExpositionFormat::negotiate(Some("application/vnd.google.protobuf;encoding=text")), ExpositionFormat::Text);
+        assert_eq!(ExpositionFormat::negotiate(None), ExpositionFormat::Text);
+    }
+}
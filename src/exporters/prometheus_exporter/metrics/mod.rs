@@ -1,2 +1,98 @@
+use crate::metrics::measurement_unit;
+use crate::metrics::measurement_unit::{Dimension, MEASUREMENT_UNITS, MeasurementUnit};
+use crate::metrics::metric::MetricDescription;
+
+pub mod native_histogram;
 pub mod prometheus_histogram;
 pub mod prometheus_counter;
+pub mod prometheus_gauge;
+pub mod prometheus_summary;
+
+/// The NaN Prometheus takes as the end of a series, instead of as a value.
+pub(crate) const STALE_NAN: f64 = f64::from_bits(0x7ff0_0000_0000_0002);
+
+/// A series of the exporter, by the name it's exported with and its tags.
+pub(crate) trait Series {
+    fn name(&self) -> &str;
+
+    fn metric_description(&self) -> &MetricDescription;
+}
+
+/// A series exported until it expires for not being updated, when it can be exported once more with the
+/// staleness marker on the protobuf scrapes before being removed.
+pub(crate) trait ExpiringSeries: Series {
+    /// The timestamp of the last snapshot the series was updated on.
+    fn updated_ms(&self) -> u64;
+
+    fn is_stale(&self) -> bool;
+
+    /// Replaces the values of the series, the ones which are floats, by the staleness marker.
+    fn mark_stale(&mut self);
+}
+
+/// Prometheus expects every value in the base unit of its dimension: seconds, bytes and ratios.
+/// The values without a dimension are exported as they were recorded.
+pub(crate) fn to_prometheus_unit(value: f64, measurement_unit: &MeasurementUnit) -> f64 {
+    match measurement_unit.dimension() {
+        Dimension::Time => measurement_unit::convert(value, measurement_unit, &MEASUREMENT_UNITS.time.seconds),
+        Dimension::Information => measurement_unit::convert(value, measurement_unit, &MEASUREMENT_UNITS.information.bytes),
+        Dimension::Percentage => value / 100f64,
+        Dimension::None => value,
+    }
+}
+
+/// The unit of the values once converted by `to_prometheus_unit`, when it's a base unit of Prometheus.
+pub(crate) fn prometheus_unit_name(measurement_unit: &MeasurementUnit) -> Option<&'static str> {
+    match measurement_unit.dimension() {
+        Dimension::Time => Some("seconds"),
+        Dimension::Information => Some("bytes"),
+        Dimension::Percentage => Some("ratio"),
+        Dimension::None => None,
+    }
+}
+
+/// The name the metric is exported with, suffixed with its Prometheus unit when it doesn't end with it yet.
+/// The unit goes before the `_total` suffix of the counters, and replaces a `_percent` suffix on the ratios.
+pub(crate) fn prometheus_name(name: &str, measurement_unit: &MeasurementUnit) -> String {
+    let unit = match prometheus_unit_name(measurement_unit) {
+        Some(unit) => unit,
+        None => return name.to_string(),
+    };
+    let (base, total) = match name.strip_suffix("_total") {
+        Some(base) => (base, "_total"),
+        None => (name, ""),
+    };
+    let base = match unit {
+        "ratio" => base.strip_suffix("_percentage").or_else(|| base.strip_suffix("_percent")).unwrap_or(base),
+        _ => base,
+    };
+    if base.ends_with(&format!("_{}", unit)) {
+        format!("{}{}", base, total)
+    } else {
+        format!("{}_{}{}", base, unit, total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_into_the_base_units() {
+        assert_eq!(to_prometheus_unit(1_500.0, &MEASUREMENT_UNITS.time.millis), 1.5);
+        assert_eq!(to_prometheus_unit(2.0, &MEASUREMENT_UNITS.information.kilobytes), 2_048.0);
+        assert_eq!(to_prometheus_unit(25.0, &MEASUREMENT_UNITS.percentage), 0.25);
+        assert_eq!(to_prometheus_unit(25.0, &MEASUREMENT_UNITS.none), 25.0);
+    }
+
+    #[test]
+    fn test_suffix_the_names_with_the_unit() {
+        assert_eq!(prometheus_name("probe_duration", &MEASUREMENT_UNITS.time.nanos), "probe_duration_seconds");
+        assert_eq!(prometheus_name("probe_duration_seconds", &MEASUREMENT_UNITS.time.nanos), "probe_duration_seconds");
+        assert_eq!(prometheus_name("socket_received_total", &MEASUREMENT_UNITS.information.kilobytes), "socket_received_bytes_total");
+        assert_eq!(prometheus_name("socket_received_bytes_total", &MEASUREMENT_UNITS.information.bytes), "socket_received_bytes_total");
+        assert_eq!(prometheus_name("disk_usage_percent", &MEASUREMENT_UNITS.percentage), "disk_usage_ratio");
+        assert_eq!(prometheus_name("disk_usage", &MEASUREMENT_UNITS.percentage), "disk_usage_ratio");
+        assert_eq!(prometheus_name("exec_runs_total", &MEASUREMENT_UNITS.none), "exec_runs_total");
+    }
+}
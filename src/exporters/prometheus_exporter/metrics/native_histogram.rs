@@ -0,0 +1,157 @@
+//! Buckets of the Prometheus native histograms, which are exponential and sparse, so they adapt to the
+//! recorded values instead of being configured.
+//!
+//! The bucket `i` of the schema `s` holds the values on `(base^(i-1), base^i]`, being `base = 2^(2^-s)`,
+//! and the values up to the `zero_threshold` are counted on their own zero bucket.
+//!
+//! More details can be found at https://prometheus.io/docs/concepts/metric_types/#histogram
+
+use std::collections::BTreeMap;
+
+use crate::exporters::prometheus_exporter::prometheus_settings::NativeHistogramSettings;
+
+/// The schemas supported by Prometheus, from a base of 65536 to a base of about 1.0027.
+pub const MIN_SCHEMA: i32 = -4;
+pub const MAX_SCHEMA: i32 = 8;
+
+#[derive(Debug, Clone)]
+pub struct NativeBuckets {
+    schema: i32,
+    zero_threshold: f64,
+    max_buckets: usize,
+    zero_count: u64,
+    /// The cumulative count of every populated bucket, by index.
+    positive: BTreeMap<i32, u64>,
+}
+
+impl NativeBuckets {
+    /// The settings were validated when they were loaded.
+    pub fn new(settings: &NativeHistogramSettings) -> NativeBuckets {
+        NativeBuckets {
+            schema: settings.schema,
+            zero_threshold: settings.zero_threshold,
+            max_buckets: settings.max_buckets,
+            zero_count: 0,
+            positive: BTreeMap::new(),
+        }
+    }
+
+    /// Counts the value, halving the resolution when there are more populated buckets than `max_buckets`.
+    /// The negative values are counted on the zero bucket, since the histograms only record positive ones.
+    pub fn record(&mut self, value: f64, count: u64) {
+        if value <= self.zero_threshold || value.is_nan() {
+            self.zero_count += count;
+            return;
+        }
+        *self.positive.entry(bucket_index(value, self.schema)).or_default() += count;
+        while self.positive.len() > self.max_buckets && self.schema > MIN_SCHEMA {
+            self.reduce_resolution();
+        }
+    }
+
+    /// Every bucket of the lower schema is the union of two consecutive buckets of the current one.
+    fn reduce_resolution(&mut self) {
+        let mut reduced = BTreeMap::new();
+        for (index, count) in self.positive.iter() {
+            *reduced.entry((index + 1).div_euclid(2)).or_default() += count;
+        }
+        self.positive = reduced;
+        self.schema -= 1;
+    }
+
+    pub fn schema(&self) -> i32 {
+        self.schema
+    }
+
+    pub fn zero_threshold(&self) -> f64 {
+        self.zero_threshold
+    }
+
+    pub fn zero_count(&self) -> u64 {
+        self.zero_count
+    }
+
+    /// The populated buckets as spans of consecutive buckets, each one with the offset of its first bucket
+    /// from the end of the previous span, and the count of every bucket as a delta from the previous one.
+    pub fn positive_spans(&self) -> (Vec<(i32, u32)>, Vec<i64>) {
+        let mut spans: Vec<(i32, u32)> = Vec::new();
+        let mut deltas = Vec::with_capacity(self.positive.len());
+        let mut next_index = None;
+        let mut previous_count = 0i64;
+        for (index, count) in self.positive.iter() {
+            match next_index {
+                Some(next) if next == *index => spans.last_mut().unwrap().1 += 1,
+                Some(next) => spans.push((index - next, 1)),
+                None => spans.push((*index, 1)),
+            }
+            next_index = Some(index + 1);
+            deltas.push(*count as i64 - previous_count);
+            previous_count = *count as i64;
+        }
+        (spans, deltas)
+    }
+}
+
+/// The index of the bucket holding the positive value, `ceil(log2(value) * 2^schema)`, corrected for
+/// the rounding errors at the bounds of the buckets.
+pub fn bucket_index(value: f64, schema: i32) -> i32 {
+    let factor = 2f64.powi(schema);
+    let upper_bound = |index: i32| 2f64.powf(index as f64 / factor);
+    let mut index = (value.log2() * factor).ceil() as i32;
+    if upper_bound(index - 1) >= value {
+        index -= 1;
+    } else if upper_bound(index) < value {
+        index += 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(schema: i32, max_buckets: usize) -> NativeHistogramSettings {
+        NativeHistogramSettings { schema, zero_threshold: 0.0, max_buckets }
+    }
+
+    #[test]
+    fn test_bucket_index() {
+        assert_eq!(bucket_index(1.0, 0), 0);
+        assert_eq!(bucket_index(1.5, 0), 1);
+        assert_eq!(bucket_index(2.0, 0), 1);
+        assert_eq!(bucket_index(3.0, 0), 2);
+        assert_eq!(bucket_index(0.5, 0), -1);
+        assert_eq!(bucket_index(0.001, 0), -9);
+        // the bounds of the schema 3 are the powers of 2^(1/8)
+        assert_eq!(bucket_index(2f64.powf(3.0 / 8.0), 3), 3);
+        assert_eq!(bucket_index(2f64.powf(3.0 / 8.0) * 1.000_001, 3), 4);
+        assert_eq!(bucket_index(16.0, -2), 1);
+        assert_eq!(bucket_index(17.0, -2), 2);
+    }
+
+    #[test]
+    fn test_spans_and_deltas() {
+        let mut buckets = NativeBuckets::new(&settings(0, 160));
+        buckets.record(0.0, 4);
+        buckets.record(1.0, 2);
+        buckets.record(2.0, 5);
+        buckets.record(16.0, 3);
+        buckets.record(1.5, 1);
+        assert_eq!(buckets.zero_count(), 4);
+        // the buckets 0, 1 and 4
+        assert_eq!(buckets.positive_spans(), (vec![(0, 2), (2, 1)], vec![2, 4, -3]));
+    }
+
+    #[test]
+    fn test_reduce_resolution_beyond_max_buckets() {
+        let mut buckets = NativeBuckets::new(&settings(1, 2));
+        buckets.record(1.0, 1);
+        buckets.record(1.5, 1);
+        assert_eq!(buckets.schema(), 1);
+        // 1.0, 1.5 and 3.0 are on the buckets 0, 2 and 4 of the schema 1, but on 0, 1 and 2 of the schema 0
+        // and on 0 and 1 of the schema -1
+        buckets.record(3.0, 1);
+        assert_eq!(buckets.schema(), -1);
+        assert_eq!(buckets.positive_spans(), (vec![(0, 2)], vec![1, 1]));
+    }
+}
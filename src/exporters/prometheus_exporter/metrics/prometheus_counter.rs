@@ -1,45 +1,153 @@
 use std::sync::Arc;
 
+use crate::exporters::metrics_exporter::CounterSample;
+use crate::exporters::prometheus_exporter::metrics::{ExpiringSeries, Series, STALE_NAN, prometheus_name, prometheus_unit_name, to_prometheus_unit};
 use crate::exporters::prometheus_exporter::prometheus_settings::PrometheusSettings;
+use crate::metrics::measurement_unit::MeasurementUnit;
 use crate::metrics::metric::MetricDescription;
 use crate::utils::time;
 
 #[derive(Debug)]
 pub struct PrometheusCounter {
     metric_description: Arc<MetricDescription>,
-    count: u64,
+    name: String,
+    value: f64,
     timestamp_ms: u64,
+    updated_ms: u64,
+    stale: bool,
+    created_ms: u64,
+    measurement_unit: &'static MeasurementUnit,
 }
 
-// TODO: implement prometheus counter
 impl PrometheusCounter {
-    pub fn new(metric_description: Arc<MetricDescription>, _settings: PrometheusSettings) -> Self {
+    pub fn new(metric_description: Arc<MetricDescription>, _settings: PrometheusSettings, measurement_unit: &'static MeasurementUnit) -> Self {
         PrometheusCounter {
+            name: prometheus_name(metric_description.name(), measurement_unit),
             metric_description,
-            count: 0,
+            value: 0 as f64,
             timestamp_ms: time::current_millis(),
+            updated_ms: time::current_millis(),
+            stale: false,
+            created_ms: time::current_millis(),
+            measurement_unit,
         }
     }
 
-    /// Insert counter sample values on Prometheus Counter
-    pub fn add_snapshot(&mut self, _timestamp_in_millis: u64) {
-        unimplemented!()
+    /// Accumulates the increments of the counter sample on the Prometheus Counter
+    pub fn add_snapshot(&mut self, counter_sample: &CounterSample, timestamp_in_millis: u64) {
+        self.value += to_prometheus_unit(counter_sample.value() as f64, counter_sample.measurement_unit());
+        self.timestamp_ms = timestamp_in_millis;
+        if counter_sample.updated() {
+            self.updated_ms = timestamp_in_millis;
+        }
     }
 
     pub fn metric_description(&self) -> &MetricDescription {
         &self.metric_description
     }
 
-    pub fn count(&self) -> u64 {
-        self.count
+    /// The name the metric is exported with, suffixed with its unit.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
     }
 
     pub fn timestamp_ms(&self) -> u64 {
         self.timestamp_ms
     }
+
+    /// When the series started to be exported, since its value accumulates from then.
+    pub fn created_ms(&self) -> u64 {
+        self.created_ms
+    }
+
+    /// The unit of the exported values.
+    pub fn unit(&self) -> Option<&'static str> {
+        prometheus_unit_name(self.measurement_unit)
+    }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-// }
+impl Series for PrometheusCounter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn metric_description(&self) -> &MetricDescription {
+        &self.metric_description
+    }
+}
+
+impl ExpiringSeries for PrometheusCounter {
+    fn updated_ms(&self) -> u64 {
+        self.updated_ms
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    fn mark_stale(&mut self) {
+        self.value = STALE_NAN;
+        self.stale = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
+    use crate::utils::tests::ApproxComparison;
+
+    use super::*;
+
+    #[test]
+    fn test_add_snapshots_accumulates_the_increments() {
+        let metric_description = MetricDescription::from("metric_name_total".into(), "some description".into(), hashmap! {}).unwrap();
+        let mut prometheus_counter = PrometheusCounter::new(Arc::new(metric_description), PrometheusSettings::default(), &MEASUREMENT_UNITS.none);
+
+        prometheus_counter.add_snapshot(&CounterSample::new(3, &MEASUREMENT_UNITS.none), 1);
+        prometheus_counter.add_snapshot(&CounterSample::new(0, &MEASUREMENT_UNITS.none), 2);
+        prometheus_counter.add_snapshot(&CounterSample::new(7, &MEASUREMENT_UNITS.none), 3);
+
+        assert!(prometheus_counter.value().is_eq(10f64, 0));
+        assert_eq!(prometheus_counter.timestamp_ms(), 3);
+    }
+
+    #[test]
+    fn test_add_snapshots_converts_time_values_into_seconds() {
+        let metric_description = MetricDescription::from("metric_name_seconds_total".into(), "some description".into(), hashmap! {}).unwrap();
+        let mut prometheus_counter = PrometheusCounter::new(Arc::new(metric_description), PrometheusSettings::default(), &MEASUREMENT_UNITS.time.nanos);
+
+        prometheus_counter.add_snapshot(&CounterSample::new(1_500_000_000, &MEASUREMENT_UNITS.time.nanos), 1);
+        prometheus_counter.add_snapshot(&CounterSample::new(500_000_000, &MEASUREMENT_UNITS.time.nanos), 2);
+
+        assert!(prometheus_counter.value().is_eq(2f64, 1));
+        assert_eq!(prometheus_counter.name(), "metric_name_seconds_total");
+    }
+
+    #[test]
+    fn test_name_is_suffixed_with_the_unit_before_total() {
+        let metric_description = MetricDescription::from("metric_name_total".into(), "some description".into(), hashmap! {}).unwrap();
+        let prometheus_counter = PrometheusCounter::new(Arc::new(metric_description), PrometheusSettings::default(), &MEASUREMENT_UNITS.time.nanos);
+
+        assert_eq!(prometheus_counter.name(), "metric_name_seconds_total");
+        assert_eq!(prometheus_counter.unit(), Some("seconds"));
+    }
+
+    #[test]
+    fn test_mark_stale_replaces_the_value_by_the_staleness_marker() {
+        let metric_description = MetricDescription::from("metric_name_total".into(), "some description".into(), hashmap! {}).unwrap();
+        let mut prometheus_counter = PrometheusCounter::new(Arc::new(metric_description), PrometheusSettings::default(), &MEASUREMENT_UNITS.none);
+
+        prometheus_counter.add_snapshot(&CounterSample::new(3, &MEASUREMENT_UNITS.none), 1);
+        prometheus_counter.add_snapshot(&CounterSample::new(0, &MEASUREMENT_UNITS.none).with_updated(false), 2);
+        assert_eq!(prometheus_counter.updated_ms(), 1);
+        assert!(!prometheus_counter.is_stale());
+
+        prometheus_counter.mark_stale();
+        assert!(prometheus_counter.is_stale());
+        assert_eq!(prometheus_counter.value().to_bits(), STALE_NAN.to_bits());
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use crate::exporters::metrics_exporter::GaugeSample;
+use crate::exporters::prometheus_exporter::metrics::{ExpiringSeries, Series, STALE_NAN, prometheus_name, prometheus_unit_name, to_prometheus_unit};
+use crate::exporters::prometheus_exporter::prometheus_settings::PrometheusSettings;
+use crate::metrics::measurement_unit::MeasurementUnit;
+use crate::metrics::metric::MetricDescription;
+use crate::utils::time;
+
+#[derive(Debug)]
+pub struct PrometheusGauge {
+    metric_description: Arc<MetricDescription>,
+    name: String,
+    value: f64,
+    timestamp_ms: u64,
+    updated_ms: u64,
+    stale: bool,
+    measurement_unit: &'static MeasurementUnit,
+}
+
+impl PrometheusGauge {
+    pub fn new(metric_description: Arc<MetricDescription>, _settings: PrometheusSettings, measurement_unit: &'static MeasurementUnit) -> Self {
+        PrometheusGauge {
+            name: prometheus_name(metric_description.name(), measurement_unit),
+            metric_description,
+            value: 0 as f64,
+            timestamp_ms: time::current_millis(),
+            updated_ms: time::current_millis(),
+            stale: false,
+            measurement_unit,
+        }
+    }
+
+    /// Replaces the value of the Prometheus Gauge by the one from the gauge sample
+    pub fn add_snapshot(&mut self, gauge_sample: &GaugeSample, timestamp_in_millis: u64) {
+        self.value = to_prometheus_unit(gauge_sample.value(), gauge_sample.measurement_unit());
+        self.timestamp_ms = timestamp_in_millis;
+        if gauge_sample.updated() {
+            self.updated_ms = timestamp_in_millis;
+        }
+    }
+
+    pub fn metric_description(&self) -> &MetricDescription {
+        &self.metric_description
+    }
+
+    /// The name the metric is exported with, suffixed with its unit.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    pub fn timestamp_ms(&self) -> u64 {
+        self.timestamp_ms
+    }
+
+    /// The unit of the exported values.
+    pub fn unit(&self) -> Option<&'static str> {
+        prometheus_unit_name(self.measurement_unit)
+    }
+}
+
+impl Series for PrometheusGauge {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn metric_description(&self) -> &MetricDescription {
+        &self.metric_description
+    }
+}
+
+impl ExpiringSeries for PrometheusGauge {
+    fn updated_ms(&self) -> u64 {
+        self.updated_ms
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    fn mark_stale(&mut self) {
+        self.value = STALE_NAN;
+        self.stale = true;
+    }
+}
@@ -2,9 +2,11 @@ use std::sync::Arc;
 use std::time::Instant;
 
 use crate::exporters::metrics_exporter::HistogramSample;
+use crate::exporters::prometheus_exporter::metrics::native_histogram::NativeBuckets;
+use crate::exporters::prometheus_exporter::metrics::{ExpiringSeries, Series, STALE_NAN, prometheus_name, prometheus_unit_name, to_prometheus_unit};
 use crate::exporters::prometheus_exporter::prometheus_settings::{PrometheusHistogramSettings, PrometheusSettings};
-use crate::metrics::measurement_unit;
-use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
+use crate::metrics::histogram::HistogramSettings;
+use crate::metrics::measurement_unit::MeasurementUnit;
 use crate::metrics::metric::MetricDescription;
 use crate::prometheus::core::Number;
 use crate::utils::time;
@@ -14,26 +16,50 @@ type BucketHolder = Vec<(f64, u64)>;
 #[derive(Debug)]
 pub struct PrometheusHistogram {
     metric_description: Arc<MetricDescription>,
+    name: String,
     buckets: BucketHolder,
+    native: Option<NativeBuckets>,
     count: u64,
     sum: f64,
     timestamp_ms: u64,
+    updated_ms: u64,
+    stale: bool,
+    created_ms: u64,
+    measurement_unit: &'static MeasurementUnit,
 }
 
 impl PrometheusHistogram {
-    pub fn new(metric_description: Arc<MetricDescription>, settings: PrometheusSettings) -> Self {
-        let buckets = Self::create_buckets(&metric_description, &settings.metrics.histograms);
+    /// The buckets and the native histogram are configured by the name the metric is exported with, and their
+    /// bounds are in the Prometheus unit of the histogram.
+    pub fn new(metric_description: Arc<MetricDescription>, settings: PrometheusSettings, histogram_settings: &HistogramSettings) -> Self {
+        let name = prometheus_name(metric_description.name(), histogram_settings.measurement_unit);
+        let buckets = Self::create_buckets(&name, &settings.metrics.histograms, histogram_settings);
+        let native = settings.metrics.histograms.native.get(&name).map(NativeBuckets::new);
         PrometheusHistogram {
             metric_description,
+            name,
             buckets,
+            native,
             count: 0,
             sum: 0 as f64,
             timestamp_ms: time::current_millis(),
+            updated_ms: time::current_millis(),
+            stale: false,
+            created_ms: time::current_millis(),
+            measurement_unit: histogram_settings.measurement_unit,
         }
     }
 
-    fn create_buckets(metric_description: &Arc<MetricDescription>, histo_settings: &PrometheusHistogramSettings) -> BucketHolder {
-        let buckets = histo_settings.buckets.from(&metric_description.name).clone();
+    /// Resolves the buckets configured for the metric, from the settings of its source histogram when they are
+    /// derived from them. The rest were validated with the settings, so only the derived buckets can be invalid
+    /// here, which are reported and leave the histogram with the `+Inf` one alone.
+    fn create_buckets(name: &String, histo_settings: &PrometheusHistogramSettings, histogram_settings: &HistogramSettings) -> BucketHolder {
+        let buckets = histo_settings.buckets.from(name)
+            .resolve(histogram_settings)
+            .unwrap_or_else(|error| {
+                error!("Buckets of {} couldn't be created. Reason: {:?}", name, error);
+                vec![]
+            });
         let mut buckets_holder = Vec::<(f64, u64)>::with_capacity(buckets.len() + 1);
         for bucket in &buckets {
             buckets_holder.push((bucket.into_f64(), 0 as u64));
@@ -60,7 +86,7 @@ impl PrometheusHistogram {
     ///
     /// For more example take a look to the unit tests.
     pub fn add_snapshot(&mut self, histogram_sample: &HistogramSample, timestamp_in_millis: u64) {
-        if self.buckets.len() <= 0 {
+        if self.buckets.is_empty() {
             return;
         }
         let start = Instant::now();
@@ -73,8 +99,11 @@ impl PrometheusHistogram {
         let hdr_histogram = histogram_sample.hdr_histogram();
 
         for record in hdr_histogram.iter_recorded() {
-            let value = measurement_unit::convert(record.value_iterated_to() as f64, histogram_sample.measurement_unit(), &MEASUREMENT_UNITS.time.seconds);
+            let value = to_prometheus_unit(record.value_iterated_to() as f64, histogram_sample.measurement_unit());
             let count = record.count_at_value();
+            if let Some(native) = &mut self.native {
+                native.record(value, count);
+            }
 
             while value > next_bucket && next_bucket_index <= self.buckets.len() - 1 {
                 self.buckets[next_bucket_index].1 += count_samples;
@@ -94,6 +123,9 @@ impl PrometheusHistogram {
         self.count += count_samples;
 
         self.timestamp_ms = timestamp_in_millis;
+        if histogram_sample.updated() {
+            self.updated_ms = timestamp_in_millis;
+        }
 
         let delta = start.elapsed().as_millis() as u64;
         info!("Inserted {} values on prometheus histogram in {} millis", hdr_histogram.len(), delta);
@@ -103,10 +135,20 @@ impl PrometheusHistogram {
         &self.metric_description
     }
 
+    /// The name the metric is exported with, suffixed with its unit.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn buckets(&self) -> &BucketHolder {
         &self.buckets
     }
 
+    /// The exponential buckets, when the histogram is exported as a native one too.
+    pub fn native(&self) -> Option<&NativeBuckets> {
+        self.native.as_ref()
+    }
+
     pub fn sum(&self) -> f64 {
         self.sum
     }
@@ -118,14 +160,48 @@ impl PrometheusHistogram {
     pub fn timestamp_ms(&self) -> u64 {
         self.timestamp_ms
     }
+
+    /// When the series started to be exported, since its value accumulates from then.
+    pub fn created_ms(&self) -> u64 {
+        self.created_ms
+    }
+
+    /// The unit of the exported values.
+    pub fn unit(&self) -> Option<&'static str> {
+        prometheus_unit_name(self.measurement_unit)
+    }
+}
+
+impl Series for PrometheusHistogram {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn metric_description(&self) -> &MetricDescription {
+        &self.metric_description
+    }
 }
 
+impl ExpiringSeries for PrometheusHistogram {
+    fn updated_ms(&self) -> u64 {
+        self.updated_ms
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    fn mark_stale(&mut self) {
+        self.sum = STALE_NAN;
+        self.stale = true;
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use hdrhistogram::Histogram as HdrHistogram;
 
-    use crate::metrics::histogram::HistogramSettings;
+    use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
     use crate::utils::tests::ApproxComparison;
 
     use super::*;
@@ -155,7 +231,7 @@ mod tests {
         let hdr_histogram = HdrHistogram::<u64>::new_with_bounds(histogram_settings.low, histogram_settings.high, histogram_settings.precision)
             .unwrap();
         let histogram_sample = HistogramSample::new(hdr_histogram, histogram_settings.clone());
-        let mut prometheus_histogram = PrometheusHistogram::new(Arc::new(metric_description), PrometheusSettings::default());
+        let mut prometheus_histogram = PrometheusHistogram::new(Arc::new(metric_description), PrometheusSettings::default(), &histogram_settings);
         prometheus_histogram.add_snapshot(&histogram_sample, DEFAULTS.timestamp_in_millis);
 
         assert_eq!(prometheus_histogram.buckets.len(), 10);
@@ -169,7 +245,7 @@ mod tests {
         let mut settings = PrometheusSettings::default();
         settings.metrics.histograms.buckets.default = vec![
             2f64, 4f64, 6f64, 8f64, 10f64,
-        ];
+        ].into();
         let metric_description = DEFAULTS.metric_description.clone();
         let histogram_settings = HistogramSettings::from(1, 1000, 2, &MEASUREMENT_UNITS.time.seconds);
         let mut hdr_histogram = HdrHistogram::<u64>::new_with_bounds(histogram_settings.low, histogram_settings.high, histogram_settings.precision)
@@ -180,7 +256,7 @@ mod tests {
         hdr_histogram.record_n(5, 10);
         hdr_histogram.record_n(6, 7);
         let histogram_sample = HistogramSample::new(hdr_histogram, histogram_settings.clone());
-        let mut prometheus_histogram = PrometheusHistogram::new(Arc::new(metric_description), settings);
+        let mut prometheus_histogram = PrometheusHistogram::new(Arc::new(metric_description), settings, &histogram_settings);
         prometheus_histogram.add_snapshot(&histogram_sample, DEFAULTS.timestamp_in_millis);
 
         assert_eq!(prometheus_histogram.buckets.len(), 6);
@@ -199,11 +275,11 @@ mod tests {
         let mut settings = PrometheusSettings::default();
         settings.metrics.histograms.buckets.default = vec![
             2f64, 4f64, 6f64, 8f64, 10f64,
-        ];
+        ].into();
         let metric_description = DEFAULTS.metric_description.clone();
-        let mut prometheus_histogram = PrometheusHistogram::new(Arc::new(metric_description), settings);
-
         let histogram_settings = HistogramSettings::from(1, 1000, 2, &MEASUREMENT_UNITS.time.seconds);
+        let mut prometheus_histogram = PrometheusHistogram::new(Arc::new(metric_description), settings, &histogram_settings);
+
         let mut hdr_histogram = HdrHistogram::<u64>::new_with_bounds(histogram_settings.low, histogram_settings.high, histogram_settings.precision)
             .unwrap();
         hdr_histogram.record_n(0, 3);
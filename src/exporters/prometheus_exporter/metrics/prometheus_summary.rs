@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use hdrhistogram::Histogram as HdrHistogram;
+
+use crate::exporters::metrics_exporter::HistogramSample;
+use crate::exporters::prometheus_exporter::metrics::{ExpiringSeries, Series, STALE_NAN, prometheus_name, prometheus_unit_name, to_prometheus_unit};
+use crate::exporters::prometheus_exporter::prometheus_settings::SummarySettings;
+use crate::metrics::measurement_unit::MeasurementUnit;
+use crate::metrics::metric::MetricDescription;
+use crate::utils::time;
+
+/// A histogram exported as a Prometheus summary, keeping the full distribution of its last snapshots
+/// to compute the quantiles from, instead of collapsing it into buckets.
+#[derive(Debug)]
+pub struct PrometheusSummary {
+    metric_description: Arc<MetricDescription>,
+    name: String,
+    window: VecDeque<HdrHistogram<u64>>,
+    window_snapshots: usize,
+    /// The quantiles over the window, with their values.
+    quantiles: Vec<(f64, f64)>,
+    count: u64,
+    sum: f64,
+    timestamp_ms: u64,
+    updated_ms: u64,
+    stale: bool,
+    created_ms: u64,
+    measurement_unit: &'static MeasurementUnit,
+}
+
+impl PrometheusSummary {
+    pub fn new(metric_description: Arc<MetricDescription>, settings: &SummarySettings, measurement_unit: &'static MeasurementUnit) -> Self {
+        PrometheusSummary {
+            name: prometheus_name(metric_description.name(), measurement_unit),
+            metric_description,
+            window: VecDeque::with_capacity(settings.window_snapshots.max(1)),
+            window_snapshots: settings.window_snapshots.max(1),
+            quantiles: settings.quantiles.iter().map(|quantile| (*quantile, f64::NAN)).collect(),
+            count: 0,
+            sum: 0 as f64,
+            timestamp_ms: time::current_millis(),
+            updated_ms: time::current_millis(),
+            stale: false,
+            created_ms: time::current_millis(),
+            measurement_unit,
+        }
+    }
+
+    /// Slides the window to the snapshot, computing the quantiles over the window again, and accumulates
+    /// its count and sum.
+    pub fn add_snapshot(&mut self, histogram_sample: &HistogramSample, timestamp_in_millis: u64) {
+        let measurement_unit = histogram_sample.measurement_unit();
+        let hdr_histogram = histogram_sample.hdr_histogram();
+        for record in hdr_histogram.iter_recorded() {
+            self.sum += to_prometheus_unit(record.value_iterated_to() as f64, measurement_unit) * record.count_at_value() as f64;
+        }
+        self.count += hdr_histogram.len();
+
+        if self.window.len() == self.window_snapshots {
+            self.window.pop_front();
+        }
+        self.window.push_back(hdr_histogram.clone());
+        let mut merged = HdrHistogram::<u64>::new_from(hdr_histogram);
+        for snapshot in self.window.iter() {
+            if let Err(error) = merged.add(snapshot) {
+                warn!("Snapshot of {} couldn't be added to the window of the summary. Reason: {:?}", self.metric_description.name(), error);
+            }
+        }
+        for (quantile, value) in self.quantiles.iter_mut() {
+            *value = if merged.is_empty() {
+                f64::NAN
+            } else {
+                to_prometheus_unit(merged.value_at_quantile(*quantile) as f64, measurement_unit)
+            };
+        }
+
+        self.timestamp_ms = timestamp_in_millis;
+        if histogram_sample.updated() {
+            self.updated_ms = timestamp_in_millis;
+        }
+    }
+
+    pub fn metric_description(&self) -> &MetricDescription {
+        &self.metric_description
+    }
+
+    /// The name the metric is exported with, suffixed with its unit.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn quantiles(&self) -> &[(f64, f64)] {
+        &self.quantiles
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn timestamp_ms(&self) -> u64 {
+        self.timestamp_ms
+    }
+
+    /// When the series started to be exported, since its count and sum accumulate from then.
+    pub fn created_ms(&self) -> u64 {
+        self.created_ms
+    }
+
+    /// The unit of the exported values.
+    pub fn unit(&self) -> Option<&'static str> {
+        prometheus_unit_name(self.measurement_unit)
+    }
+}
+
+impl Series for PrometheusSummary {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn metric_description(&self) -> &MetricDescription {
+        &self.metric_description
+    }
+}
+
+impl ExpiringSeries for PrometheusSummary {
+    fn updated_ms(&self) -> u64 {
+        self.updated_ms
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    fn mark_stale(&mut self) {
+        self.sum = STALE_NAN;
+        for (_, value) in self.quantiles.iter_mut() {
+            *value = STALE_NAN;
+        }
+        self.stale = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::histogram::HistogramSettings;
+    use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
+    use crate::utils::tests::ApproxComparison;
+
+    use super::*;
+
+    fn sample(values: &[(u64, u64)]) -> HistogramSample {
+        let histogram_settings = HistogramSettings::from(1, 1_000_000_000, 3, &MEASUREMENT_UNITS.time.millis);
+        let mut hdr_histogram = HdrHistogram::<u64>::new_with_bounds(histogram_settings.low, histogram_settings.high, histogram_settings.precision)
+            .unwrap();
+        for (value, count) in values {
+            hdr_histogram.record_n(*value, *count).unwrap();
+        }
+        HistogramSample::new(hdr_histogram, histogram_settings)
+    }
+
+    fn summary(window_snapshots: usize) -> PrometheusSummary {
+        let metric_description = MetricDescription::from("metric_name_seconds".into(), "some description".into(), hashmap! {}).unwrap();
+        PrometheusSummary::new(Arc::new(metric_description), &SummarySettings { quantiles: vec![0.5, 0.99], window_snapshots }, &MEASUREMENT_UNITS.time.millis)
+    }
+
+    #[test]
+    fn test_quantiles_of_the_last_snapshot() {
+        let mut summary = summary(1);
+        assert!(summary.quantiles().iter().all(|(_, value)| value.is_nan()));
+
+        summary.add_snapshot(&sample(&[(10, 98), (500, 2)]), 1);
+        assert!(summary.quantiles()[0].1.is_eq(0.010, 3));
+        assert!(summary.quantiles()[1].1.is_eq(0.500, 3));
+
+        summary.add_snapshot(&sample(&[(20, 100)]), 2);
+        assert!(summary.quantiles()[0].1.is_eq(0.020, 3));
+        assert!(summary.quantiles()[1].1.is_eq(0.020, 3));
+        assert_eq!(summary.count(), 200);
+        assert!(summary.sum().is_eq(0.98 + 1.0 + 2.0, 6));
+        assert_eq!(summary.timestamp_ms(), 2);
+        assert_eq!(summary.unit(), Some("seconds"));
+
+        // an idle interval has no quantiles, but keeps its count and sum
+        summary.add_snapshot(&sample(&[]), 3);
+        assert!(summary.quantiles()[0].1.is_nan());
+        assert_eq!(summary.count(), 200);
+    }
+
+    #[test]
+    fn test_quantiles_of_a_sliding_window() {
+        let mut summary = summary(2);
+        summary.add_snapshot(&sample(&[(10, 101)]), 1);
+        summary.add_snapshot(&sample(&[(20, 100)]), 2);
+        assert!(summary.quantiles()[0].1.is_eq(0.010, 3));
+        assert!(summary.quantiles()[1].1.is_eq(0.020, 3));
+
+        // the first snapshot slides out of the window
+        summary.add_snapshot(&sample(&[(30, 100)]), 3);
+        assert!(summary.quantiles()[0].1.is_eq(0.020, 3));
+        assert!(summary.quantiles()[1].1.is_eq(0.030, 3));
+        assert_eq!(summary.count(), 301);
+    }
+}
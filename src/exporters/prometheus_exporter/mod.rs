@@ -1,4 +1,11 @@
+pub mod content_encoding;
+pub mod exposition_format;
 pub mod prometheus_encoder;
 pub mod metrics;
+pub mod openmetrics_encoder;
 pub mod prometheus_reporter;
+pub mod protobuf_encoder;
 pub mod prometheus_settings;
+pub mod scrape_auth;
+pub mod scrape_filter;
+pub mod scrape_tls;
@@ -0,0 +1,256 @@
+//! Encoder of the OpenMetrics 1.0 exposition format, the Prometheus text format successor.
+//!
+//! Unlike the text format, the counter families are named without their `_total` suffix, every counter and
+//! histogram has a `_created` sample, the unit of a family is declared when its name ends with it, the
+//! timestamps are in seconds and the exposition ends with `# EOF`.
+//!
+//! More details can be found at https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use prometheus::proto::{MetricFamily, MetricType};
+
+use crate::errors::Result;
+use crate::exporters::prometheus_exporter::metrics::prometheus_counter::PrometheusCounter;
+use crate::exporters::prometheus_exporter::metrics::prometheus_gauge::PrometheusGauge;
+use crate::exporters::prometheus_exporter::metrics::prometheus_histogram::PrometheusHistogram;
+use crate::exporters::prometheus_exporter::metrics::prometheus_summary::PrometheusSummary;
+use crate::exporters::prometheus_exporter::prometheus_encoder::{add_label_pairs, escape_string, group_by_name};
+
+/// Encodes the histograms, grouping the ones with the same name under a single metric family.
+pub fn encode_histograms<'a, I, W>(histograms: I, writer: &mut W) -> Result<()>
+    where I: IntoIterator<Item=&'a PrometheusHistogram>, W: Write
+{
+    for (name, family) in group_by_name(histograms, PrometheusHistogram::name) {
+        write_header(name, family[0].metric_description().description(), "histogram", family[0].unit(), writer)?;
+        for histogram in family {
+            let tags = histogram.metric_description().tags();
+            let timestamp = Some(histogram.timestamp_ms());
+            let last = histogram.buckets().len() - 1;
+            for (i, (bound, value)) in histogram.buckets().iter().enumerate() {
+                let bound = if i == last { "+Inf".to_string() } else { format_value(*bound) };
+                write_sample(&format!("{}_bucket", name), tags, vec!(("le", &bound)), &value.to_string(), timestamp, writer)?;
+            }
+            write_sample(&format!("{}_count", name), tags, vec!(), &histogram.count().to_string(), timestamp, writer)?;
+            write_sample(&format!("{}_sum", name), tags, vec!(), &format_value(histogram.sum()), timestamp, writer)?;
+            write_sample(&format!("{}_created", name), tags, vec!(), &format_timestamp(histogram.created_ms()), timestamp, writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Encodes the summaries, grouping the ones with the same name under a single metric family.
+pub fn encode_summaries<'a, I, W>(summaries: I, writer: &mut W) -> Result<()>
+    where I: IntoIterator<Item=&'a PrometheusSummary>, W: Write
+{
+    for (name, family) in group_by_name(summaries, PrometheusSummary::name) {
+        write_header(name, family[0].metric_description().description(), "summary", family[0].unit(), writer)?;
+        for summary in family {
+            let tags = summary.metric_description().tags();
+            let timestamp = Some(summary.timestamp_ms());
+            for (quantile, value) in summary.quantiles() {
+                write_sample(name, tags, vec!(("quantile", &format_value(*quantile))), &format_value(*value), timestamp, writer)?;
+            }
+            write_sample(&format!("{}_count", name), tags, vec!(), &summary.count().to_string(), timestamp, writer)?;
+            write_sample(&format!("{}_sum", name), tags, vec!(), &format_value(summary.sum()), timestamp, writer)?;
+            write_sample(&format!("{}_created", name), tags, vec!(), &format_timestamp(summary.created_ms()), timestamp, writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Encodes the counters, grouping the ones with the same name under a single metric family named without `_total`.
+pub fn encode_counters<'a, I, W>(counters: I, writer: &mut W) -> Result<()>
+    where I: IntoIterator<Item=&'a PrometheusCounter>, W: Write
+{
+    for (name, family) in group_by_name(counters, PrometheusCounter::name) {
+        let name = name.strip_suffix("_total").unwrap_or(name);
+        write_header(name, family[0].metric_description().description(), "counter", family[0].unit(), writer)?;
+        for counter in family {
+            let tags = counter.metric_description().tags();
+            let timestamp = Some(counter.timestamp_ms());
+            write_sample(&format!("{}_total", name), tags, vec!(), &format_value(counter.value()), timestamp, writer)?;
+            write_sample(&format!("{}_created", name), tags, vec!(), &format_timestamp(counter.created_ms()), timestamp, writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Encodes the gauges, grouping the ones with the same name under a single metric family.
+pub fn encode_gauges<'a, I, W>(gauges: I, writer: &mut W) -> Result<()>
+    where I: IntoIterator<Item=&'a PrometheusGauge>, W: Write
+{
+    for (name, family) in group_by_name(gauges, PrometheusGauge::name) {
+        write_header(name, family[0].metric_description().description(), "gauge", family[0].unit(), writer)?;
+        for gauge in family {
+            write_sample(name, gauge.metric_description().tags(), vec!(), &format_value(gauge.value()), Some(gauge.timestamp_ms()), writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Encodes the families gathered from the registry of the `prometheus` crate, which have neither units nor creation times.
+pub fn encode_families<W: Write>(families: &[MetricFamily], writer: &mut W) -> Result<()> {
+    let no_tags = HashMap::new();
+    for family in families {
+        let (name, metric_type) = match family.get_field_type() {
+            MetricType::COUNTER => (family.get_name().strip_suffix("_total").unwrap_or_else(|| family.get_name()), "counter"),
+            MetricType::GAUGE => (family.get_name(), "gauge"),
+            MetricType::HISTOGRAM => (family.get_name(), "histogram"),
+            MetricType::SUMMARY => (family.get_name(), "summary"),
+            MetricType::UNTYPED => (family.get_name(), "unknown"),
+        };
+        write_header(name, family.get_help(), metric_type, None, writer)?;
+        for metric in family.get_metric() {
+            let labels: Vec<(&str, &str)> = metric.get_label().iter().map(|label| (label.get_name(), label.get_value())).collect();
+            let sample = |suffix: &str, extra_label: Option<(&str, &str)>, value: String, writer: &mut W| {
+                let mut labels = labels.clone();
+                labels.extend(extra_label);
+                write_sample(&format!("{}{}", name, suffix), &no_tags, labels, &value, None, writer)
+            };
+            match family.get_field_type() {
+                MetricType::COUNTER => sample("_total", None, format_value(metric.get_counter().get_value()), writer)?,
+                MetricType::GAUGE => sample("", None, format_value(metric.get_gauge().get_value()), writer)?,
+                MetricType::UNTYPED => sample("", None, format_value(metric.get_untyped().get_value()), writer)?,
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    for bucket in histogram.get_bucket() {
+                        let bound = format_value(bucket.get_upper_bound());
+                        sample("_bucket", Some(("le", &bound)), bucket.get_cumulative_count().to_string(), writer)?;
+                    }
+                    if histogram.get_bucket().last().is_none_or(|bucket| bucket.get_upper_bound().is_finite()) {
+                        sample("_bucket", Some(("le", "+Inf")), histogram.get_sample_count().to_string(), writer)?;
+                    }
+                    sample("_count", None, histogram.get_sample_count().to_string(), writer)?;
+                    sample("_sum", None, format_value(histogram.get_sample_sum()), writer)?;
+                },
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        let rank = format_value(quantile.get_quantile());
+                        sample("", Some(("quantile", &rank)), format_value(quantile.get_value()), writer)?;
+                    }
+                    sample("_count", None, summary.get_sample_count().to_string(), writer)?;
+                    sample("_sum", None, format_value(summary.get_sample_sum()), writer)?;
+                },
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Ends the exposition, so the scraper can tell it from a truncated one.
+pub fn write_eof<W: Write>(writer: &mut W) -> Result<()> {
+    writeln!(writer, "# EOF")?;
+    Ok(())
+}
+
+/// The unit is only declared when the name of the family ends with it, as OpenMetrics requires.
+fn write_header(name: &str, help: &str, metric_type: &str, unit: Option<&str>, writer: &mut dyn Write) -> Result<()> {
+    writeln!(writer, "# TYPE {} {}", name, metric_type)?;
+    if let Some(unit) = unit.filter(|unit| name.ends_with(&format!("_{}", unit))) {
+        writeln!(writer, "# UNIT {} {}", name, unit)?;
+    }
+    if !help.is_empty() {
+        writeln!(writer, "# HELP {} {}", name, escape_string(help, true))?;
+    }
+    Ok(())
+}
+
+fn write_sample(
+    name: &str,
+    tags: &HashMap<String, String>,
+    additional_labels: Vec<(&str, &str)>,
+    value: &str,
+    timestamp_ms: Option<u64>,
+    writer: &mut dyn Write,
+) -> Result<()> {
+    writer.write_all(name.as_bytes())?;
+    add_label_pairs(tags, &additional_labels, writer)?;
+    write!(writer, " {}", value)?;
+    if let Some(timestamp_ms) = timestamp_ms {
+        write!(writer, " {}", format_timestamp(timestamp_ms))?;
+    }
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// The floats always have a decimal point or an exponent, and the infinities are `+Inf` and `-Inf`.
+fn format_value(value: f64) -> String {
+    if value.is_infinite() {
+        if value > 0.0 { "+Inf".into() } else { "-Inf".into() }
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+/// The timestamps of OpenMetrics are in seconds.
+fn format_timestamp(timestamp_ms: u64) -> String {
+    format!("{}.{:03}", timestamp_ms / 1000, timestamp_ms % 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use prometheus::{Counter, Opts, Registry};
+
+    use crate::exporters::metrics_exporter::{CounterSample, GaugeSample};
+    use crate::exporters::prometheus_exporter::prometheus_settings::PrometheusSettings;
+    use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
+    use crate::metrics::metric::MetricDescription;
+
+    use super::*;
+
+    fn description(name: &str) -> Arc<MetricDescription> {
+        Arc::new(MetricDescription::from(name.into(), "Some \"description\".".into(), hashmap! {"probe".into() => "api".into()}).unwrap())
+    }
+
+    #[test]
+    fn test_encode_counters_and_gauges() {
+        let mut counter = PrometheusCounter::new(description("exec_runs_total"), PrometheusSettings::default(), &MEASUREMENT_UNITS.none);
+        counter.add_snapshot(&CounterSample::new(3, &MEASUREMENT_UNITS.none), 1_590_000_000_500);
+        let mut gauge = PrometheusGauge::new(description("probe_duration_seconds"), PrometheusSettings::default(), &MEASUREMENT_UNITS.time.millis);
+        gauge.add_snapshot(&GaugeSample::new(250.0, &MEASUREMENT_UNITS.time.millis), 1_590_000_000_500);
+
+        let mut buffer = vec![];
+        encode_counters(vec![&counter], &mut buffer).unwrap();
+        encode_gauges(vec![&gauge], &mut buffer).unwrap();
+        write_eof(&mut buffer).unwrap();
+        let created = format_timestamp(counter.created_ms());
+        assert_eq!(String::from_utf8(buffer).unwrap(), format!(r#"# TYPE exec_runs counter
+# HELP exec_runs Some \"description\".
+exec_runs_total{{probe="api"}} 3.0 1590000000.500
+exec_runs_created{{probe="api"}} {} 1590000000.500
+# TYPE probe_duration_seconds gauge
+# UNIT probe_duration_seconds seconds
+# HELP probe_duration_seconds Some \"description\".
+probe_duration_seconds{{probe="api"}} 0.25 1590000000.500
+# EOF
+"#, created));
+    }
+
+    #[test]
+    fn test_encode_registry_families() {
+        let registry = Registry::new();
+        let counter = Counter::with_opts(Opts::new("prometheus_http_requests_total", "Requests.").const_label("handler", "all")).unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        counter.inc();
+
+        let mut buffer = vec![];
+        encode_families(&registry.gather(), &mut buffer).unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap(), "# TYPE prometheus_http_requests counter\n\
+                                                        # HELP prometheus_http_requests Requests.\n\
+                                                        prometheus_http_requests_total{handler=\"all\"} 1.0\n");
+    }
+
+    #[test]
+    fn test_format_values() {
+        assert_eq!(format_value(10.0), "10.0");
+        assert_eq!(format_value(0.000_000_05), "5e-8");
+        assert_eq!(format_value(f64::INFINITY), "+Inf");
+        assert_eq!(format_value(f64::NAN), "NaN");
+        assert_eq!(format_timestamp(1_590_000_000_005), "1590000000.005");
+    }
+}
@@ -1,21 +1,76 @@
 use std::borrow::Borrow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Display;
 use std::io::Write;
 
 use crate::errors::Result;
+use crate::exporters::prometheus_exporter::metrics::prometheus_counter::PrometheusCounter;
+use crate::exporters::prometheus_exporter::metrics::prometheus_gauge::PrometheusGauge;
 use crate::exporters::prometheus_exporter::metrics::prometheus_histogram::PrometheusHistogram;
+use crate::exporters::prometheus_exporter::metrics::prometheus_summary::PrometheusSummary;
 use crate::metrics::metric::MetricDescription;
 
-pub fn encode_histogram<W: Write>(histogram: &PrometheusHistogram, writer: &mut W) -> Result<()> {
-    let metric_description = histogram.metric_description();
-    let name = metric_description.name();
-    let help = metric_description.description();
+/// Encodes the histograms, grouping the ones with the same name under a single metric family.
+pub fn encode_histograms<'a, I, W>(histograms: I, writer: &mut W) -> Result<()>
+    where I: IntoIterator<Item=&'a PrometheusHistogram>, W: Write
+{
+    for (name, family) in group_by_name(histograms, PrometheusHistogram::name) {
+        write_header(name, family[0].metric_description(), "histogram", writer)?;
+        for histogram in family {
+            encode_histogram(histogram, writer)?;
+        }
+    }
+    Ok(())
+}
 
-    if !help.is_empty() {
-        writeln!(writer, "# HELP {} {}", name, escape_string(help, false))?;
+/// Encodes the summaries, grouping the ones with the same name under a single metric family.
+pub fn encode_summaries<'a, I, W>(summaries: I, writer: &mut W) -> Result<()>
+    where I: IntoIterator<Item=&'a PrometheusSummary>, W: Write
+{
+    for (name, family) in group_by_name(summaries, PrometheusSummary::name) {
+        write_header(name, family[0].metric_description(), "summary", writer)?;
+        for summary in family {
+            let metric_description = summary.metric_description();
+            let timestamp = Some(summary.timestamp_ms());
+            for (quantile, value) in summary.quantiles() {
+                write_sample(name, metric_description, vec!(("quantile", &quantile.to_string())), value, timestamp, writer)?;
+            }
+            write_sample(&format!("{}_sum", name), metric_description, vec!(), summary.sum(), timestamp, writer)?;
+            write_sample(&format!("{}_count", name), metric_description, vec!(), summary.count(), timestamp, writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Encodes the counters, grouping the ones with the same name under a single metric family.
+pub fn encode_counters<'a, I, W>(counters: I, writer: &mut W) -> Result<()>
+    where I: IntoIterator<Item=&'a PrometheusCounter>, W: Write
+{
+    for (name, family) in group_by_name(counters, PrometheusCounter::name) {
+        write_header(name, family[0].metric_description(), "counter", writer)?;
+        for counter in family {
+            write_sample(name, counter.metric_description(), vec!(), counter.value(), Some(counter.timestamp_ms()), writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Encodes the gauges, grouping the ones with the same name under a single metric family.
+pub fn encode_gauges<'a, I, W>(gauges: I, writer: &mut W) -> Result<()>
+    where I: IntoIterator<Item=&'a PrometheusGauge>, W: Write
+{
+    for (name, family) in group_by_name(gauges, PrometheusGauge::name) {
+        write_header(name, family[0].metric_description(), "gauge", writer)?;
+        for gauge in family {
+            write_sample(name, gauge.metric_description(), vec!(), gauge.value(), Some(gauge.timestamp_ms()), writer)?;
+        }
     }
-    writeln!(writer, "# TYPE {} histogram", name)?;
+    Ok(())
+}
+
+fn encode_histogram<W: Write>(histogram: &PrometheusHistogram, writer: &mut W) -> Result<()> {
+    let metric_description = histogram.metric_description();
+    let name = histogram.name();
 
     for (i, bucket) in histogram.buckets().iter().enumerate() {
         let bucket_bound = bucket.0.to_string();
@@ -53,6 +108,28 @@ pub fn encode_histogram<W: Write>(histogram: &PrometheusHistogram, writer: &mut
     Ok(())
 }
 
+/// Groups the metrics by the name they are exported with, sorted by name, since Prometheus requires all
+/// the metrics of a family to be together under a single `# HELP` and `# TYPE`.
+pub(crate) fn group_by_name<'a, T, I, F>(metrics: I, name: F) -> BTreeMap<&'a str, Vec<&'a T>>
+    where I: IntoIterator<Item=&'a T>, F: Fn(&'a T) -> &'a str
+{
+    let mut families = BTreeMap::<&str, Vec<&T>>::new();
+    for metric in metrics {
+        families.entry(name(metric)).or_default().push(metric);
+    }
+    families
+}
+
+fn write_header(name: &str, metric_description: &MetricDescription, metric_type: &str, writer: &mut dyn Write) -> Result<()> {
+    let help = metric_description.description();
+
+    if !help.is_empty() {
+        writeln!(writer, "# HELP {} {}", name, escape_string(help, false))?;
+    }
+    writeln!(writer, "# TYPE {} {}", name, metric_type)?;
+    Ok(())
+}
+
 fn write_sample<V>(
     name: &str,
     metric_description: &MetricDescription,
@@ -85,7 +162,7 @@ fn write_sample<V>(
     Ok(())
 }
 
-fn add_label_pairs(
+pub(crate) fn add_label_pairs(
     tags: &HashMap<String, String>,
     additional_labels: &Vec<(&str, &str)>,
     writer: &mut dyn Write,
@@ -130,7 +207,7 @@ fn add_label_pairs(
 
 /// Replaces `\` by `\\`, new line character by `\n`, and `"` by `\"` if
 /// `include_double_quote` is true.
-fn escape_string(v: &str, include_double_quote: bool) -> String {
+pub(crate) fn escape_string(v: &str, include_double_quote: bool) -> String {
     let mut escaped = String::with_capacity(v.len() * 2);
 
     for c in v.chars() {
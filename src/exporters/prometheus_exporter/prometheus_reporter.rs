@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -7,20 +7,30 @@ use std::time::Instant;
 
 use hyper::{
     Body,
-    header::CONTENT_TYPE,
-    Request, Response, Server, service::{make_service_fn, service_fn},
+    header::{ACCEPT, ACCEPT_ENCODING, AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE, VARY, WWW_AUTHENTICATE},
+    Request, Response, Server, server::accept, service::{make_service_fn, service_fn}, StatusCode,
 };
 use prometheus::{Counter, Encoder, Gauge, HistogramVec, TextEncoder};
 use tokio::sync::broadcast::Receiver;
 use tokio::sync::RwLock;
 
 use crate::exporters::metrics_exporter::{MetricSample, MetricsSnapshot};
+use crate::exporters::prometheus_exporter::metrics::{ExpiringSeries, prometheus_name};
 use crate::exporters::prometheus_exporter::metrics::prometheus_counter::PrometheusCounter;
+use crate::exporters::prometheus_exporter::metrics::prometheus_gauge::PrometheusGauge;
 use crate::exporters::prometheus_exporter::metrics::prometheus_histogram::PrometheusHistogram;
-use crate::exporters::prometheus_exporter::prometheus_encoder;
-use crate::exporters::prometheus_exporter::prometheus_settings::PrometheusSettings;
+use crate::exporters::prometheus_exporter::metrics::prometheus_summary::PrometheusSummary;
+use crate::exporters::prometheus_exporter::content_encoding::ContentEncoding;
+use crate::exporters::prometheus_exporter::exposition_format::ExpositionFormat;
+use crate::exporters::prometheus_exporter::{openmetrics_encoder, prometheus_encoder, protobuf_encoder};
+use crate::exporters::prometheus_exporter::prometheus_settings::{PrometheusCompressionSettings, PrometheusExpirySettings, PrometheusSettings};
+use crate::exporters::prometheus_exporter::scrape_auth::ScrapeAuth;
+use crate::exporters::prometheus_exporter::scrape_filter::ScrapeRoutes;
+use crate::exporters::prometheus_exporter::scrape_tls::ScrapeTls;
 use crate::metrics::histogram::{HistogramBuilder, HistogramRecorder, HistogramSettings};
 use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
+use crate::metrics::metric::MetricKind;
+use crate::metrics::registry;
 
 lazy_static! {
     static ref HTTP_COUNTER: Counter = register_counter!(opts!(
@@ -30,9 +40,16 @@ lazy_static! {
     ))
     .unwrap();
 
-    static ref HTTP_BODY_GAUGE: Gauge = register_gauge!(opts!(
+    static ref HTTP_UNCOMPRESSED_BODY_GAUGE: Gauge = register_gauge!(opts!(
         "prometheus_http_response_size_bytes",
-        "The HTTP response sizes in bytes on the Prometheus service.",
+        "The HTTP response sizes in bytes on the Prometheus service, before compressing them.",
+        labels! {"handler" => "all",}
+    ))
+    .unwrap();
+
+    static ref HTTP_COMPRESSED_BODY_GAUGE: Gauge = register_gauge!(opts!(
+        "prometheus_http_response_compressed_size_bytes",
+        "The HTTP response sizes in bytes on the Prometheus service, as sent after compressing them.",
         labels! {"handler" => "all",}
     ))
     .unwrap();
@@ -44,37 +61,150 @@ lazy_static! {
     .unwrap();
 }
 
-async fn serve_req(metrics_holder: MetricsHolder, _req: Request<Body>,
-                   http_req_histo: Arc<RwLock<HistogramRecorder>>) -> Result<Response<Body>, hyper::Error> {
+/// The gauges of the size of the last response, before and after compressing it.
+#[derive(Clone)]
+struct ResponseSizes {
+    uncompressed: Gauge,
+    compressed: Gauge,
+}
+
+impl Default for ResponseSizes {
+    fn default() -> Self {
+        ResponseSizes {
+            uncompressed: HTTP_UNCOMPRESSED_BODY_GAUGE.clone(),
+            compressed: HTTP_COMPRESSED_BODY_GAUGE.clone(),
+        }
+    }
+}
+
+/// Everything the scrapes are served with.
+#[derive(Clone)]
+struct Scrapes {
+    metrics_holder: MetricsHolder,
+    http_req_histo: Arc<RwLock<HistogramRecorder>>,
+    compression: PrometheusCompressionSettings,
+    response_sizes: ResponseSizes,
+    routes: Arc<ScrapeRoutes>,
+    auth: Arc<Option<ScrapeAuth>>,
+}
+
+impl Scrapes {
+    async fn serve(self, req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
+        serve_req(self.metrics_holder, req, self.http_req_histo, self.compression, self.response_sizes, self.routes, self.auth).await
+    }
+}
+
+async fn serve_req(metrics_holder: MetricsHolder, req: Request<Body>,
+                   http_req_histo: Arc<RwLock<HistogramRecorder>>,
+                   compression: PrometheusCompressionSettings,
+                   response_sizes: ResponseSizes,
+                   routes: Arc<ScrapeRoutes>,
+                   auth: Arc<Option<ScrapeAuth>>) -> Result<Response<Body>, hyper::Error> {
+    if let Some(auth) = auth.as_ref() {
+        if !auth.authorizes(req.headers().get(AUTHORIZATION).and_then(|authorization| authorization.to_str().ok())).await {
+            let response = auth.challenges().into_iter()
+                .fold(Response::builder().status(StatusCode::UNAUTHORIZED), |response, challenge| response.header(WWW_AUTHENTICATE, challenge));
+            return Ok(response.body(Body::empty()).unwrap());
+        }
+    }
+    let filter = match routes.filter(req.uri()) {
+        Some(filter) => filter,
+        None => return Ok(Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()).unwrap()),
+    };
     let encoder = TextEncoder::new();
+    let format = ExpositionFormat::negotiate(req.headers().get(ACCEPT).and_then(|accept| accept.to_str().ok()));
+    let content_encoding = if compression.enabled {
+        ContentEncoding::negotiate(req.headers().get(ACCEPT_ENCODING).and_then(|accept_encoding| accept_encoding.to_str().ok()))
+    } else {
+        ContentEncoding::Identity
+    };
 
     HTTP_COUNTER.inc();
     let start = Instant::now();
     let mut http_req_histo_writer = http_req_histo.write().await;
+    if http_req_histo_writer.is_expired() {
+        *http_req_histo_writer = http_request_histogram().await;
+    }
     let mut rusty_timer = http_req_histo_writer.start_timer();
     let timer = HTTP_REQ_HISTOGRAM.with_label_values(&["all"]).start_timer();
 
-    let metric_families = prometheus::gather();
+    let metric_families = filter.select_families(prometheus::gather());
     let mut buffer = vec![];
 
-    let guard = metrics_holder.histograms.read().await;
-    for histogram in guard.values() {
-        prometheus_encoder::encode_histogram(histogram, &mut buffer).unwrap()
-    }
-    drop(guard);
+    match format {
+        ExpositionFormat::Text => {
+            let guard = metrics_holder.histograms.read().await;
+            prometheus_encoder::encode_histograms(fresh(filter.select(&guard)), &mut buffer).unwrap();
+            drop(guard);
 
-    let guard = metrics_holder.counters.read().await;
-    for _counter in guard.values() {
-        unimplemented!()
-    }
-    drop(guard);
+            let guard = metrics_holder.summaries.read().await;
+            prometheus_encoder::encode_summaries(fresh(filter.select(&guard)), &mut buffer).unwrap();
+            drop(guard);
+
+            let guard = metrics_holder.counters.read().await;
+            prometheus_encoder::encode_counters(fresh(filter.select(&guard)), &mut buffer).unwrap();
+            drop(guard);
+
+            let guard = metrics_holder.gauges.read().await;
+            prometheus_encoder::encode_gauges(fresh(filter.select(&guard)), &mut buffer).unwrap();
+            drop(guard);
+
+            encoder.encode(&metric_families, &mut buffer).unwrap();
+        },
+        ExpositionFormat::OpenMetrics => {
+            let guard = metrics_holder.histograms.read().await;
+            openmetrics_encoder::encode_histograms(fresh(filter.select(&guard)), &mut buffer).unwrap();
+            drop(guard);
+
+            let guard = metrics_holder.summaries.read().await;
+            openmetrics_encoder::encode_summaries(fresh(filter.select(&guard)), &mut buffer).unwrap();
+            drop(guard);
+
+            let guard = metrics_holder.counters.read().await;
+            openmetrics_encoder::encode_counters(fresh(filter.select(&guard)), &mut buffer).unwrap();
+            drop(guard);
+
+            let guard = metrics_holder.gauges.read().await;
+            openmetrics_encoder::encode_gauges(fresh(filter.select(&guard)), &mut buffer).unwrap();
+            drop(guard);
 
-    encoder.encode(&metric_families, &mut buffer).unwrap();
-    HTTP_BODY_GAUGE.set(buffer.len() as f64);
+            openmetrics_encoder::encode_families(&metric_families, &mut buffer).unwrap();
+            openmetrics_encoder::write_eof(&mut buffer).unwrap();
+        },
+        ExpositionFormat::Protobuf => {
+            let guard = metrics_holder.histograms.read().await;
+            protobuf_encoder::encode_histograms(filter.select(&guard), &mut buffer).unwrap();
+            drop(guard);
 
-    let response = Response::builder()
+            let guard = metrics_holder.summaries.read().await;
+            protobuf_encoder::encode_summaries(filter.select(&guard), &mut buffer).unwrap();
+            drop(guard);
+
+            let guard = metrics_holder.counters.read().await;
+            protobuf_encoder::encode_counters(filter.select(&guard), &mut buffer).unwrap();
+            drop(guard);
+
+            let guard = metrics_holder.gauges.read().await;
+            protobuf_encoder::encode_gauges(filter.select(&guard), &mut buffer).unwrap();
+            drop(guard);
+
+            protobuf_encoder::encode_families(&metric_families, &mut buffer).unwrap();
+        },
+    }
+    response_sizes.uncompressed.set(buffer.len() as f64);
+    // compressing the small responses costs more than sending them
+    let content_encoding = if (buffer.len() as u64) < compression.min_size_bytes { ContentEncoding::Identity } else { content_encoding };
+    let buffer = content_encoding.encode(buffer).unwrap();
+    response_sizes.compressed.set(buffer.len() as f64);
+
+    let mut response = Response::builder()
         .status(200)
-        .header(CONTENT_TYPE, encoder.format_type())
+        .header(CONTENT_TYPE, format.content_type())
+        .header(VARY, "Accept, Accept-Encoding");
+    if let Some(header_value) = content_encoding.header_value() {
+        response = response.header(CONTENT_ENCODING, header_value);
+    }
+    let response = response
         .body(Body::from(buffer))
         .unwrap();
 
@@ -88,17 +218,32 @@ async fn serve_req(metrics_holder: MetricsHolder, _req: Request<Body>,
     Ok(response)
 }
 
+/// The histogram of the scrapes, registered again when it expired for not being scraped.
+async fn http_request_histogram() -> HistogramRecorder {
+    HistogramBuilder::new(
+        "prometheus_http_request_duration_seconds".into(),
+        "The HTTP request latencies in seconds on the Prometheus service.".into())
+        .with_settings(HistogramSettings::from(1, 600_000, 0, &MEASUREMENT_UNITS.time.millis))
+        .build()
+        .await
+        .unwrap()
+}
+
 #[derive(Debug, Clone)]
 struct MetricsHolder {
     histograms: Arc<RwLock<HashMap<u64, PrometheusHistogram>>>,
+    summaries: Arc<RwLock<HashMap<u64, PrometheusSummary>>>,
     counters: Arc<RwLock<HashMap<u64, PrometheusCounter>>>,
+    gauges: Arc<RwLock<HashMap<u64, PrometheusGauge>>>,
 }
 
 impl Default for MetricsHolder {
     fn default() -> Self {
         MetricsHolder {
             histograms: Arc::new(RwLock::default()),
+            summaries: Arc::new(RwLock::default()),
             counters: Arc::new(RwLock::default()),
+            gauges: Arc::new(RwLock::default()),
         }
     }
 }
@@ -108,41 +253,73 @@ pub struct PrometheusExporter {
     handle: Option<thread::JoinHandle<()>>,
     running: Arc<AtomicBool>,
     metrics_holder: MetricsHolder,
+    auth: Arc<Option<ScrapeAuth>>,
+    tls: Option<ScrapeTls>,
 }
 
 impl PrometheusExporter {
-    pub fn new(config: PrometheusSettings) -> PrometheusExporter {
-        PrometheusExporter {
+    /// Fails when the authentication or the TLS of the scrapes can't be set up, rather than serving the scrapes
+    /// without them.
+    pub fn new(config: PrometheusSettings) -> crate::errors::Result<PrometheusExporter> {
+        let auth = Arc::new(ScrapeAuth::from(&config.auth)?);
+        let tls = ScrapeTls::from(&config.tls)?;
+        Ok(PrometheusExporter {
             config,
             handle: Option::None,
             running: Arc::new(AtomicBool::new(false)),
             metrics_holder: MetricsHolder::default(),
-        }
+            auth,
+            tls,
+        })
     }
 
     pub async fn start_server(&self) {
         let addr = format!("{}:{}", self.config.host, self.config.port).parse::<SocketAddr>().unwrap();
-        info!("Prometheus Exporter listening at http://{}", addr);
-
-        let metrics_holder = MetricsHolder::clone(&self.metrics_holder);
-        let prometheus_http_req_histogram = Arc::new(RwLock::new(HistogramBuilder::new(
-            "prometheus_http_request_duration_seconds".into(),
-            "The HTTP request latencies in seconds on the Prometheus service.".into())
-            .with_settings(HistogramSettings::from(1, 600_000, 0, &MEASUREMENT_UNITS.time.millis))
-            .build()
-            .await
-            .unwrap()));
-
-        let serve_future = Server::bind(&addr)
-            .serve(make_service_fn(move |_| {
-                let mh = metrics_holder.clone();
-                let http_req_histo = prometheus_http_req_histogram.clone();
-                async move {
-                    Ok::<_, hyper::Error>(service_fn(move |req| serve_req(MetricsHolder::clone(&mh), req, Arc::clone(&http_req_histo))))
-                }
-            }));
+        match std::net::TcpListener::bind(addr) {
+            Ok(listener) => self.serve(listener).await,
+            Err(error) => error!("Prometheus Exporter couldn't listen at {}. Reason: {}", addr, error),
+        }
+    }
+
+    async fn serve(&self, listener: std::net::TcpListener) {
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        info!("Prometheus Exporter listening at {}://{}", scheme, listener.local_addr().map(|addr| addr.to_string()).unwrap_or_default());
 
-        if let Err(err) = serve_future.await {
+        let scrapes = Scrapes {
+            metrics_holder: MetricsHolder::clone(&self.metrics_holder),
+            http_req_histo: Arc::new(RwLock::new(http_request_histogram().await)),
+            compression: self.config.compression,
+            response_sizes: ResponseSizes::default(),
+            routes: Arc::new(ScrapeRoutes::new(&self.config.path, self.config.groups.clone())),
+            auth: Arc::clone(&self.auth),
+        };
+        let result = match &self.tls {
+            Some(tls) => {
+                let listener = match tokio::net::TcpListener::from_std(listener) {
+                    Ok(listener) => listener,
+                    Err(error) => {
+                        error!("Prometheus Exporter couldn't listen. Reason: {}", error);
+                        return;
+                    },
+                };
+                Server::builder(accept::from_stream(tls.incoming(listener)))
+                    .serve(make_service_fn(move |_| {
+                        let scrapes = scrapes.clone();
+                        async move { Ok::<_, hyper::Error>(service_fn(move |req| scrapes.clone().serve(req))) }
+                    }))
+                    .await
+            },
+            None => match Server::from_tcp(listener) {
+                Ok(builder) => builder
+                    .serve(make_service_fn(move |_| {
+                        let scrapes = scrapes.clone();
+                        async move { Ok::<_, hyper::Error>(service_fn(move |req| scrapes.clone().serve(req))) }
+                    }))
+                    .await,
+                Err(error) => Err(error),
+            },
+        };
+        if let Err(err) = result {
             error!("Server error: {}", err);
         }
     }
@@ -167,24 +344,319 @@ impl PrometheusExporter {
     }
 
     async fn consume_snapshot(&self, metrics_snapshot: Arc<MetricsSnapshot>) {
+        if self.config.expiry.enabled {
+            // the stale series were served with the staleness marker since the previous snapshot
+            remove_stale_series(&self.metrics_holder.histograms).await;
+            remove_stale_series(&self.metrics_holder.summaries).await;
+            remove_stale_series(&self.metrics_holder.counters).await;
+            remove_stale_series(&self.metrics_holder.gauges).await;
+        }
         for sample in metrics_snapshot.samples() {
             info!("Prometheus Exporter received metrics snapshot {:?}", sample);
             match sample {
-                MetricSample::Counter(_metric_desc, _counter_sample) => unimplemented!(),
-                MetricSample::Gauge(_metric_desc, _gauge_sample) => unimplemented!(),
+                MetricSample::Counter(metric_desc, counter_sample) => {
+                    let mut guard = self.metrics_holder.counters.write().await;
+                    let prometheus_counter = guard
+                        .entry(metric_desc.id)
+                        .or_insert_with(|| PrometheusCounter::new(Arc::new(metric_desc.clone()), self.config.clone(), counter_sample.measurement_unit()));
+                    prometheus_counter.add_snapshot(counter_sample, metrics_snapshot.timestamp_in_millis());
+                },
+                MetricSample::Gauge(metric_desc, gauge_sample) => {
+                    let mut guard = self.metrics_holder.gauges.write().await;
+                    let prometheus_gauge = guard
+                        .entry(metric_desc.id)
+                        .or_insert_with(|| PrometheusGauge::new(Arc::new(metric_desc.clone()), self.config.clone(), gauge_sample.measurement_unit()));
+                    prometheus_gauge.add_snapshot(gauge_sample, metrics_snapshot.timestamp_in_millis());
+                },
                 MetricSample::Histogram(metric_desc, histogram_sample) => {
+                    let name = prometheus_name(metric_desc.name(), histogram_sample.measurement_unit());
+                    if let Some(summary_settings) = self.config.metrics.histograms.summaries.get(&name) {
+                        let mut guard = self.metrics_holder.summaries.write().await;
+                        let prometheus_summary = guard
+                            .entry(metric_desc.id)
+                            .or_insert_with(|| PrometheusSummary::new(Arc::new(metric_desc.clone()), summary_settings, histogram_sample.measurement_unit()));
+                        prometheus_summary.add_snapshot(histogram_sample, metrics_snapshot.timestamp_in_millis());
+                        continue;
+                    }
                     info!("Receiving Metric ID {}", metric_desc.id);
                     let mut guard = self.metrics_holder.histograms.write().await;
                     let prometheus_histogram = guard
                         .entry(metric_desc.id)
                         .or_insert_with(|| {
                             info!("Metric {} didn't find on Map", metric_desc.id);
-                            PrometheusHistogram::new(Arc::new(metric_desc.clone()), self.config.clone()).into()
+                            PrometheusHistogram::new(Arc::new(metric_desc.clone()), self.config.clone(), histogram_sample.histogram_settings()).into()
                         });
                     prometheus_histogram.add_snapshot(histogram_sample, metrics_snapshot.timestamp_in_millis());
                 },
             }
         }
+        // every metric of the registry is sampled, so the missing ones were removed from it
+        let sampled: HashSet<u64> = metrics_snapshot.samples().iter().map(MetricSample::metric_id).collect();
+        retain_sampled(&self.metrics_holder.histograms, &sampled).await;
+        retain_sampled(&self.metrics_holder.summaries, &sampled).await;
+        retain_sampled(&self.metrics_holder.counters, &sampled).await;
+        retain_sampled(&self.metrics_holder.gauges, &sampled).await;
+        if self.config.expiry.enabled {
+            let (expiry, timestamp_ms) = (self.config.expiry, metrics_snapshot.timestamp_in_millis());
+            expire_series(&self.metrics_holder.histograms, MetricKind::Histogram, expiry, timestamp_ms).await;
+            expire_series(&self.metrics_holder.summaries, MetricKind::Histogram, expiry, timestamp_ms).await;
+            expire_series(&self.metrics_holder.counters, MetricKind::Counter, expiry, timestamp_ms).await;
+            expire_series(&self.metrics_holder.gauges, MetricKind::Gauge, expiry, timestamp_ms).await;
+        }
     }
 }
 
+/// The series which aren't stale, since the staleness marker is a NaN like any other on the text formats.
+fn fresh<'a, S: ExpiringSeries + 'a, I: Iterator<Item=&'a S>>(series: I) -> impl Iterator<Item=&'a S> {
+    series.filter(|series| !series.is_stale())
+}
+
+async fn remove_stale_series<S: ExpiringSeries>(series: &RwLock<HashMap<u64, S>>) {
+    series.write().await.retain(|_, series| !series.is_stale());
+}
+
+async fn retain_sampled<S>(series: &RwLock<HashMap<u64, S>>, sampled: &HashSet<u64>) {
+    series.write().await.retain(|metric_id, _| sampled.contains(metric_id));
+}
+
+/// Removes the series which were not updated for the TTL from the registry, and from the exporter too unless
+/// they're kept with the staleness marker until the next snapshot.
+async fn expire_series<S: ExpiringSeries>(series: &RwLock<HashMap<u64, S>>, kind: MetricKind,
+                                          expiry: PrometheusExpirySettings, timestamp_ms: u64) {
+    let mut expired = vec![];
+    series.write().await.retain(|_, series| {
+        if timestamp_ms.saturating_sub(series.updated_ms()) < expiry.ttl_millis {
+            return true;
+        }
+        expired.push(series.metric_description().clone());
+        series.mark_stale();
+        expiry.stale_marker
+    });
+    for metric_description in expired.iter() {
+        info!("Series {} {:?} expired", metric_description.name(), metric_description.tags());
+        registry::global_registry().remove(&kind, metric_description).await;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use tokio_rustls::rustls::internal::pemfile;
+
+    use crate::collectors::probe_collector;
+    use crate::exporters::metrics_exporter::{CounterSample, GaugeSample};
+    use crate::exporters::prometheus_exporter::metrics::STALE_NAN;
+    use crate::exporters::prometheus_exporter::prometheus_settings::{PrometheusAuthSettings, PrometheusTlsSettings};
+    use crate::metrics::counter::CounterBuilder;
+    use crate::metrics::gauge::GaugeBuilder;
+
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    fn exporter() -> PrometheusExporter {
+        let expiry = PrometheusExpirySettings { enabled: true, ttl_millis: 1_000, stale_marker: false };
+        PrometheusExporter::new(PrometheusSettings { expiry, ..PrometheusSettings::default() }).unwrap()
+    }
+
+    fn encoded_counters(exporter: &PrometheusExporter) -> String {
+        let mut buffer = vec![];
+        prometheus_encoder::encode_counters(aw!(exporter.metrics_holder.counters.read()).values(), &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    /// The gauges of the response sizes of a test, not registered so other tests scraping can't set them.
+    fn response_sizes() -> ResponseSizes {
+        ResponseSizes {
+            uncompressed: Gauge::new("test_response_size_bytes", "some description").unwrap(),
+            compressed: Gauge::new("test_response_compressed_size_bytes", "some description").unwrap(),
+        }
+    }
+
+    fn scrape(compression: PrometheusCompressionSettings, response_sizes: ResponseSizes) -> Response<Body> {
+        let request = Request::get("/metrics").header(ACCEPT_ENCODING, "gzip").body(Body::empty()).unwrap();
+        scrape_with(request, compression, response_sizes, None)
+    }
+
+    fn scrape_with(request: Request<Body>, compression: PrometheusCompressionSettings, response_sizes: ResponseSizes,
+                   auth: Option<ScrapeAuth>) -> Response<Body> {
+        let http_req_histo = Arc::new(RwLock::new(aw!(http_request_histogram())));
+        let routes = Arc::new(ScrapeRoutes::new("/metrics", HashMap::new()));
+        aw!(serve_req(MetricsHolder::default(), request, http_req_histo, compression, response_sizes, routes, Arc::new(auth))).unwrap()
+    }
+
+    /// The body of a scrape of the series of the exporter in the format of `accept`.
+    fn scrape_exporter(exporter: &PrometheusExporter, accept: &str) -> Vec<u8> {
+        let request = Request::get("/metrics").header(ACCEPT, accept).body(Body::empty()).unwrap();
+        let http_req_histo = Arc::new(RwLock::new(aw!(http_request_histogram())));
+        let routes = Arc::new(ScrapeRoutes::new("/metrics", HashMap::new()));
+        let compression = PrometheusCompressionSettings { enabled: false, ..PrometheusCompressionSettings::default() };
+        let response = aw!(serve_req(exporter.metrics_holder.clone(), request, http_req_histo, compression, response_sizes(), routes, Arc::new(None))).unwrap();
+        aw!(hyper::body::to_bytes(response.into_body())).unwrap().to_vec()
+    }
+
+    fn fixture(name: &str) -> String {
+        format!("{}/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    /// Scrapes the exporter at `localhost:<port>` over TLS, trusting the CA of the fixtures, with the client
+    /// certificate of the fixtures when `client_cert`.
+    async fn scrape_tls(port: u16, client_cert: bool) -> crate::errors::Result<StatusCode> {
+        let mut config = tokio_rustls::rustls::ClientConfig::new();
+        config.root_store.add_pem_file(&mut BufReader::new(File::open(fixture("ca.pem"))?)).unwrap();
+        if client_cert {
+            let certs = pemfile::certs(&mut BufReader::new(File::open(fixture("client.pem"))?)).unwrap();
+            let key = pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(fixture("client-key.pem"))?)).unwrap().remove(0);
+            config.set_single_client_cert(certs, key).unwrap();
+        }
+        let address = std::net::ToSocketAddrs::to_socket_addrs(&("localhost", port))?.next().unwrap();
+        let stream = probe_collector::connect(address, std::time::Duration::from_secs(1)).await?;
+        let name = tokio_rustls::webpki::DNSNameRef::try_from_ascii_str("localhost").unwrap();
+        let stream = tokio_rustls::TlsConnector::from(Arc::new(config)).connect(name, stream).await?;
+        let http_error = |error: hyper::Error| crate::errors::Error::Msg(error.to_string());
+        let (mut sender, connection) = hyper::client::conn::handshake(stream).await.map_err(http_error)?;
+        tokio::spawn(connection);
+        let request = Request::get("/metrics").header(hyper::header::HOST, "localhost").body(Body::empty()).unwrap();
+        Ok(sender.send_request(request).await.map_err(http_error)?.status())
+    }
+
+    #[test]
+    fn test_scrapes_have_to_be_authenticated() {
+        let settings = PrometheusAuthSettings {
+            basic_users: hashmap! {"prometheus".to_string() => bcrypt::hash("s3cr3t", 4).unwrap()},
+            ..PrometheusAuthSettings::default()
+        };
+        let auth = || ScrapeAuth::from(&settings).unwrap();
+        let compression = PrometheusCompressionSettings::default();
+
+        let response = scrape_with(Request::get("/metrics").body(Body::empty()).unwrap(), compression, response_sizes(), auth());
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[WWW_AUTHENTICATE], "Basic realm=\"rusty-advisor\"");
+
+        let authorization = format!("Basic {}", base64::encode("prometheus:s3cr3t"));
+        let request = Request::get("/metrics").header(AUTHORIZATION, authorization).body(Body::empty()).unwrap();
+        assert_eq!(scrape_with(request, compression, response_sizes(), auth()).status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_serve_the_scrapes_over_tls() {
+        aw!(async {
+            let tls = PrometheusTlsSettings {
+                enabled: true,
+                cert_file: fixture("localhost.pem"),
+                key_file: fixture("localhost-key.pem"),
+                client_ca_file: None,
+            };
+            let mut config = PrometheusSettings { tls, ..PrometheusSettings::default() };
+            let listener = std::net::TcpListener::bind(("localhost", 0)).unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let exporter = PrometheusExporter::new(config.clone()).unwrap();
+            tokio::spawn(async move { exporter.serve(listener).await });
+            assert_eq!(scrape_tls(port, false).await.unwrap(), StatusCode::OK);
+
+            // the scrapers need a client certificate signed by the client CA
+            config.tls.client_ca_file = Some(fixture("ca.pem"));
+            let listener = std::net::TcpListener::bind(("localhost", 0)).unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let exporter = PrometheusExporter::new(config).unwrap();
+            tokio::spawn(async move { exporter.serve(listener).await });
+            assert!(scrape_tls(port, false).await.is_err());
+            assert_eq!(scrape_tls(port, true).await.unwrap(), StatusCode::OK);
+        });
+    }
+
+    #[test]
+    fn test_compress_the_scrapes_from_min_size_bytes() {
+        let sizes = response_sizes();
+        let response = scrape(PrometheusCompressionSettings { enabled: true, min_size_bytes: 0 }, sizes.clone());
+        assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+        assert_eq!(response.headers()[VARY], "Accept, Accept-Encoding");
+        let body = aw!(hyper::body::to_bytes(response.into_body())).unwrap();
+        assert_eq!(sizes.compressed.get(), body.len() as f64);
+        assert!(sizes.uncompressed.get() > sizes.compressed.get());
+
+        let sizes = response_sizes();
+        let response = scrape(PrometheusCompressionSettings { enabled: true, min_size_bytes: u64::MAX }, sizes.clone());
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+        assert_eq!(response.headers()[VARY], "Accept, Accept-Encoding");
+        let body = aw!(hyper::body::to_bytes(response.into_body())).unwrap();
+        assert_eq!(sizes.uncompressed.get(), body.len() as f64);
+        assert_eq!(sizes.compressed.get(), body.len() as f64);
+    }
+
+    #[test]
+    fn test_expired_series_are_removed_from_the_scrapes_and_the_registry() {
+        let exporter = exporter();
+        let builder = CounterBuilder::new("reporter_test_expired_total".into(), "some description".into());
+        let metric_description = builder.metric_description().unwrap();
+        let _recorder = aw!(builder.build()).unwrap();
+        let sample = |updated| MetricSample::Counter(metric_description.clone(), CounterSample::new(3, &MEASUREMENT_UNITS.none).with_updated(updated));
+
+        aw!(exporter.consume_snapshot(Arc::new(MetricsSnapshot::new(vec![sample(true)], 10_000))));
+        assert!(encoded_counters(&exporter).contains("reporter_test_expired_total 3 10000\n"));
+
+        aw!(exporter.consume_snapshot(Arc::new(MetricsSnapshot::new(vec![sample(false)], 10_500))));
+        assert!(encoded_counters(&exporter).contains("reporter_test_expired_total 6 10500\n"));
+
+        aw!(exporter.consume_snapshot(Arc::new(MetricsSnapshot::new(vec![sample(false)], 11_000))));
+        assert_eq!(encoded_counters(&exporter), "");
+        assert!(registry::global_registry().counters().iter()
+            .all(|counter| aw!(counter.read()).metric_description().id != metric_description.id));
+    }
+
+    #[test]
+    fn test_expired_series_are_served_once_more_with_the_staleness_marker_on_protobuf() {
+        let expiry = PrometheusExpirySettings { enabled: true, ttl_millis: 1_000, stale_marker: true };
+        let exporter = PrometheusExporter::new(PrometheusSettings { expiry, ..PrometheusSettings::default() }).unwrap();
+        let builder = CounterBuilder::new("reporter_test_stale_total".into(), "some description".into());
+        let metric_description = builder.metric_description().unwrap();
+        let _recorder = aw!(builder.build()).unwrap();
+        let sample = |updated| MetricSample::Counter(metric_description.clone(), CounterSample::new(3, &MEASUREMENT_UNITS.none).with_updated(updated));
+        let stale_value = |buffer: &[u8]| {
+            let mut input = protobuf::CodedInputStream::from_bytes(buffer);
+            let mut value = None;
+            while !input.eof().unwrap() {
+                let family = input.read_message::<prometheus::proto::MetricFamily>().unwrap();
+                if family.get_name() == "reporter_test_stale_total" {
+                    value = Some(family.get_metric()[0].get_counter().get_value());
+                }
+            }
+            value
+        };
+        let protobuf = "application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=delimited";
+
+        aw!(exporter.consume_snapshot(Arc::new(MetricsSnapshot::new(vec![sample(true)], 10_000))));
+        assert_eq!(stale_value(&scrape_exporter(&exporter, protobuf)), Some(3.0));
+
+        aw!(exporter.consume_snapshot(Arc::new(MetricsSnapshot::new(vec![sample(false)], 11_000))));
+        assert!(registry::global_registry().counters().iter()
+            .all(|counter| aw!(counter.read()).metric_description().id != metric_description.id));
+        assert_eq!(stale_value(&scrape_exporter(&exporter, protobuf)).map(f64::to_bits), Some(STALE_NAN.to_bits()));
+        // the text formats can't carry the marker, so the series is left out of them
+        assert!(!String::from_utf8(scrape_exporter(&exporter, "text/plain")).unwrap().contains("reporter_test_stale_total"));
+        assert!(!String::from_utf8(scrape_exporter(&exporter, "application/openmetrics-text")).unwrap().contains("reporter_test_stale"));
+
+        aw!(exporter.consume_snapshot(Arc::new(MetricsSnapshot::new(vec![], 11_500))));
+        assert_eq!(stale_value(&scrape_exporter(&exporter, protobuf)), None);
+    }
+
+    #[test]
+    fn test_series_removed_from_the_registry_are_removed_from_the_scrapes() {
+        let exporter = exporter();
+        let metric_description = GaugeBuilder::new("reporter_test_removed".into(), "some description".into()).metric_description().unwrap();
+        let sample = MetricSample::Gauge(metric_description, GaugeSample::new(1.0, &MEASUREMENT_UNITS.none));
+
+        aw!(exporter.consume_snapshot(Arc::new(MetricsSnapshot::new(vec![sample], 10_000))));
+        assert_eq!(aw!(exporter.metrics_holder.gauges.read()).len(), 1);
+
+        aw!(exporter.consume_snapshot(Arc::new(MetricsSnapshot::new(vec![], 10_500))));
+        assert!(aw!(exporter.metrics_holder.gauges.read()).is_empty());
+    }
+}
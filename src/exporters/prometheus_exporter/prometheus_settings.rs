@@ -1,5 +1,12 @@
 use std::collections::HashMap;
 
+use hdrhistogram::Histogram as HdrHistogram;
+
+use crate::errors::{Error, Result};
+use crate::exporters::prometheus_exporter::metrics::native_histogram::{MAX_SCHEMA, MIN_SCHEMA};
+use crate::exporters::prometheus_exporter::metrics::to_prometheus_unit;
+use crate::metrics::histogram::HistogramSettings;
+
 pub type BucketName = String;
 pub type BucketValues = Vec<f64>;
 
@@ -9,6 +16,69 @@ pub struct PrometheusSettings {
     pub port: u16,
     pub path: String,
     pub metrics: PrometheusMetricsSettings,
+    pub compression: PrometheusCompressionSettings,
+    pub expiry: PrometheusExpirySettings,
+    /// The name prefixes of the series served at `<path>/<group>`, by group.
+    pub groups: HashMap<String, Vec<String>>,
+    pub auth: PrometheusAuthSettings,
+    pub tls: PrometheusTlsSettings,
+}
+
+impl PrometheusSettings {
+    pub fn validate(&self) -> Result<()> {
+        let buckets = &self.metrics.histograms.buckets;
+        buckets.default.validate().map_err(|error| Error::Msg(format!("Default buckets are invalid. Reason: {}", error)))?;
+        for (name, layout) in buckets.custom_buckets.iter() {
+            layout.validate().map_err(|error| Error::Msg(format!("Buckets of {} are invalid. Reason: {}", name, error)))?;
+        }
+        for (name, summary) in self.metrics.histograms.summaries.iter() {
+            summary.validate().map_err(|error| Error::Msg(format!("Summary {} is invalid. Reason: {}", name, error)))?;
+        }
+        for (name, native) in self.metrics.histograms.native.iter() {
+            native.validate().map_err(|error| Error::Msg(format!("Native histogram {} is invalid. Reason: {}", name, error)))?;
+            // the summary takes the place of the histogram, so the native one would never be served
+            if self.metrics.histograms.summaries.contains_key(name) {
+                return Err(Error::Msg(format!("Histogram {} is configured as both a summary and a native histogram", name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The scrape responses are compressed when the scraper accepts it and they are at least `min_size_bytes` long.
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct PrometheusCompressionSettings {
+    pub enabled: bool,
+    pub min_size_bytes: u64,
+}
+
+/// The series which are not updated for `ttl_millis` are removed from the exporter and the registry.
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct PrometheusExpirySettings {
+    pub enabled: bool,
+    pub ttl_millis: u64,
+    /// Serve the expired series to the protobuf scrapes once more with the staleness marker, which the text
+    /// formats can't carry.
+    pub stale_marker: bool,
+}
+
+/// The scrapes have to carry the token of `bearer_token_file` on their `Authorization` header when it's set,
+/// or the password of any of the `basic_users`.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PrometheusAuthSettings {
+    pub bearer_token_file: Option<String>,
+    /// The bcrypt hash of the password of the users allowed to scrape with basic auth, by user.
+    pub basic_users: HashMap<String, String>,
+}
+
+/// The scrapes are served over TLS when it's enabled, with the certificate chain of `cert_file` and its key. The
+/// scrapers have to present a certificate signed by any of the CAs of `client_ca_file` too when it's set.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PrometheusTlsSettings {
+    pub enabled: bool,
+    pub cert_file: String,
+    pub key_file: String,
+    pub client_ca_file: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone, Default)]
@@ -19,20 +89,223 @@ pub struct PrometheusMetricsSettings {
 #[derive(Debug, Deserialize, Clone, Default)]
 pub struct PrometheusHistogramSettings {
     pub buckets: Buckets,
+    /// The histograms exported as a Prometheus summary instead, by name.
+    #[serde(default)]
+    pub summaries: HashMap<BucketName, SummarySettings>,
+    /// The histograms exported as a Prometheus native histogram too, by name.
+    #[serde(default)]
+    pub native: HashMap<BucketName, NativeHistogramSettings>,
+}
+
+/// The buckets of a native histogram grow by a factor of `2^(2^-schema)`, and the resolution is halved
+/// whenever there are more than `max_buckets`. They are only served on the protobuf format.
+#[derive(Debug, Deserialize, Clone)]
+pub struct NativeHistogramSettings {
+    #[serde(default = "default_schema")]
+    pub schema: i32,
+    #[serde(default)]
+    pub zero_threshold: f64,
+    #[serde(default = "default_max_buckets")]
+    pub max_buckets: usize,
+}
+
+impl NativeHistogramSettings {
+    pub fn validate(&self) -> Result<()> {
+        if !(MIN_SCHEMA..=MAX_SCHEMA).contains(&self.schema) {
+            return Err(Error::Msg(format!("Invalid native histogram schema {}: it has to be between {} and {}", self.schema, MIN_SCHEMA, MAX_SCHEMA)));
+        }
+        if !(self.zero_threshold.is_finite() && self.zero_threshold >= 0.0) {
+            return Err(Error::Msg(format!("Invalid native histogram zero threshold {}: it can't be negative", self.zero_threshold)));
+        }
+        if self.max_buckets == 0 {
+            return Err(Error::Msg("Invalid native histogram max buckets: it has to be at least 1".into()));
+        }
+        Ok(())
+    }
+}
+
+/// The quantiles are computed over the last `window_snapshots` snapshots of the histogram, while the sum and
+/// the count accumulate every snapshot like the ones of a histogram.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SummarySettings {
+    #[serde(default = "default_quantiles")]
+    pub quantiles: Vec<f64>,
+    #[serde(default = "default_window_snapshots")]
+    pub window_snapshots: usize,
+}
+
+impl SummarySettings {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(quantile) = self.quantiles.iter().find(|quantile| !(0.0..=1.0).contains(*quantile)) {
+            return Err(Error::Msg(format!("Invalid summary quantile {}: it has to be between 0 and 1", quantile)));
+        }
+        if self.window_snapshots == 0 {
+            return Err(Error::Msg("Invalid summary window: it has to have at least 1 snapshot".into()));
+        }
+        Ok(())
+    }
+}
+
+fn default_quantiles() -> Vec<f64> {
+    vec![0.5, 0.9, 0.99, 0.999]
+}
+
+fn default_window_snapshots() -> usize {
+    1
+}
+
+fn default_schema() -> i32 {
+    3
+}
+
+fn default_max_buckets() -> usize {
+    160
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Buckets {
-    pub default: BucketValues,
-    pub custom_buckets: HashMap<BucketName, BucketValues>,
+    pub default: BucketLayout,
+    pub custom_buckets: HashMap<BucketName, BucketLayout>,
 }
 
 impl Buckets {
-    pub fn from(&self, name: &String) -> &BucketValues {
+    pub fn from(&self, name: &String) -> &BucketLayout {
         self.custom_buckets.get(name).unwrap_or_else(|| { &self.default })
     }
 }
 
+/// The upper bounds of the buckets, either listed or generated from a spec like
+/// `{ type = "exponential", start = 0.0001, factor = 2, count = 20 }`.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum BucketLayout {
+    Values(BucketValues),
+    Spec(BucketSpec),
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BucketSpec {
+    /// `count` bounds from `start`, each one `factor` times the previous one.
+    Exponential { start: f64, factor: f64, count: usize },
+    /// `count` bounds from `start`, each one `width` greater than the previous one.
+    Linear { start: f64, width: f64, count: usize },
+    /// The bounds of the ranges the HDR histogram of the metric tells apart, given its precision, so the
+    /// buckets count its values exactly. Only every n-th bound is kept when there are more than `max_count`.
+    Hdr {
+        #[serde(default = "default_hdr_max_count")]
+        max_count: usize,
+    },
+}
+
+impl From<BucketValues> for BucketLayout {
+    fn from(values: BucketValues) -> Self {
+        BucketLayout::Values(values)
+    }
+}
+
+impl BucketLayout {
+    /// Resolves the bounds, in the Prometheus unit of the histogram recorded with `histogram_settings`,
+    /// validating that they are finite and strictly increasing.
+    pub fn resolve(&self, histogram_settings: &HistogramSettings) -> Result<BucketValues> {
+        let values = match self.configured_bounds()? {
+            Some(values) => values,
+            None => hdr_bounds(histogram_settings, self.hdr_max_count())?,
+        };
+        self.check(values)
+    }
+
+    /// Validates the bounds which don't depend on the histogram, all of them but the `hdr` ones, which
+    /// can only be resolved when the histogram is created.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(values) = self.configured_bounds()? {
+            self.check(values)?;
+        }
+        Ok(())
+    }
+
+    fn configured_bounds(&self) -> Result<Option<BucketValues>> {
+        let values = match self {
+            BucketLayout::Values(values) => values.clone(),
+            BucketLayout::Spec(BucketSpec::Exponential { start, factor, count }) => {
+                if *start <= 0.0 || *factor <= 1.0 {
+                    return self.invalid("the start has to be positive and the factor greater than 1");
+                }
+                (0..*count).map(|i| start * factor.powi(i as i32)).collect()
+            },
+            BucketLayout::Spec(BucketSpec::Linear { start, width, count }) => {
+                if *width <= 0.0 {
+                    return self.invalid("the width has to be positive");
+                }
+                (0..*count).map(|i| start + width * i as f64).collect()
+            },
+            BucketLayout::Spec(BucketSpec::Hdr { .. }) => return Ok(None),
+        };
+        Ok(Some(values))
+    }
+
+    fn hdr_max_count(&self) -> usize {
+        match self {
+            BucketLayout::Spec(BucketSpec::Hdr { max_count }) => *max_count,
+            _ => default_hdr_max_count(),
+        }
+    }
+
+    fn check(&self, values: BucketValues) -> Result<BucketValues> {
+        if values.is_empty() {
+            return self.invalid("there are no bounds");
+        }
+        if values.iter().any(|value| !value.is_finite()) {
+            return self.invalid("the bounds have to be finite");
+        }
+        if values.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return self.invalid("the bounds have to be strictly increasing");
+        }
+        Ok(values)
+    }
+
+    fn invalid<T>(&self, reason: &str) -> Result<T> {
+        Err(Error::Msg(format!("Invalid buckets {:?}: {}", self, reason)))
+    }
+}
+
+/// The highest value of every range of equivalent values of the HDR histogram, from `low` to `high`.
+fn hdr_bounds(histogram_settings: &HistogramSettings, max_count: usize) -> Result<BucketValues> {
+    let hdr_histogram = HdrHistogram::<u64>::new_with_bounds(histogram_settings.low.max(1), histogram_settings.high, histogram_settings.precision)
+        .map_err(|error| Error::Msg(format!("Invalid histogram settings {:?}. Reason: {:?}", histogram_settings, error)))?;
+    let mut bounds = Vec::new();
+    let mut value = hdr_histogram.lowest_equivalent(histogram_settings.low);
+    while value <= histogram_settings.high {
+        let bound = hdr_histogram.highest_equivalent(value);
+        bounds.push(bound);
+        value = bound + 1;
+    }
+    // the highest bound is always kept, so the buckets cover every value up to `high`
+    let step = bounds.len().div_ceil(max_count.max(1)).max(1);
+    let mut thinned: BucketValues = bounds.iter()
+        .rev()
+        .step_by(step)
+        .map(|bound| to_prometheus_unit(*bound as f64, histogram_settings.measurement_unit))
+        .collect();
+    thinned.reverse();
+    Ok(thinned)
+}
+
+/// The collectors of the hiccups, of the system and of the probes.
+fn default_groups() -> HashMap<String, Vec<String>> {
+    let group = |name: &str, prefixes: &[&str]| (name.to_string(), prefixes.iter().map(|prefix| prefix.to_string()).collect());
+    vec![
+        group("hiccups", &["hiccups_"]),
+        group("system", &["schedstat_", "interrupts_", "softirqs_", "netstat_", "sockstat_", "tcp_", "numa_",
+            "hugepages_", "hwmon_", "thermal_", "cpu_", "clocksource_", "timex_"]),
+        group("probes", &["probe_"]),
+    ].into_iter().collect()
+}
+
+fn default_hdr_max_count() -> usize {
+    40
+}
+
 impl Default for PrometheusSettings {
     fn default() -> Self {
         PrometheusSettings {
@@ -40,6 +313,30 @@ impl Default for PrometheusSettings {
             port: 9096,
             path: "/metrics".to_string(),
             metrics: PrometheusMetricsSettings::default(),
+            compression: PrometheusCompressionSettings::default(),
+            expiry: PrometheusExpirySettings::default(),
+            groups: default_groups(),
+            auth: PrometheusAuthSettings::default(),
+            tls: PrometheusTlsSettings::default(),
+        }
+    }
+}
+
+impl Default for PrometheusCompressionSettings {
+    fn default() -> Self {
+        PrometheusCompressionSettings {
+            enabled: true,
+            min_size_bytes: 1024,
+        }
+    }
+}
+
+impl Default for PrometheusExpirySettings {
+    fn default() -> Self {
+        PrometheusExpirySettings {
+            enabled: false,
+            ttl_millis: 300_000,
+            stale_marker: false,
         }
     }
 }
@@ -47,18 +344,123 @@ impl Default for PrometheusSettings {
 
 impl Default for Buckets {
     fn default() -> Self {
-        let mut custom_buckets = HashMap::<BucketName, BucketValues>::with_capacity(1);
-        custom_buckets.insert("hiccups_duration_seconds".to_string(), vec!(
+        let mut custom_buckets = HashMap::<BucketName, BucketLayout>::with_capacity(6);
+        custom_buckets.insert("hiccups_duration_seconds".to_string(), BucketLayout::Values(vec!(
             0.000_000_050, 0.000_000_100, 0.000_000_250, 0.000_000_500, 0.000_001_000, 0.000_002_500, 0.000_005_000, 0.000_010_000, 0.000_025_000, 0.000_050_000, 0.000_100_000,
-        ));
-        custom_buckets.insert("prometheus_http_request_duration_seconds".to_string(), vec!(
+        )));
+        custom_buckets.insert("hiccups_attributed_duration_seconds".to_string(), BucketLayout::Values(vec!(
+            0.000_100, 0.000_250, 0.000_500, 0.001, 0.002_5, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+        )));
+        custom_buckets.insert("prometheus_http_request_duration_seconds".to_string(), BucketLayout::Values(vec!(
             0.000_5, 0.001, 0.002_5, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.50, 1.0, 2.5, 5.0, 10.0,
-        ));
+        )));
+        for probe in ["probe_http_duration_seconds", "probe_tcp_duration_seconds", "probe_dns_duration_seconds"].iter() {
+            custom_buckets.insert(probe.to_string(), BucketLayout::Values(vec!(
+                0.000_1, 0.000_25, 0.000_5, 0.001, 0.002_5, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+            )));
+        }
         Buckets {
-            default: vec!(
+            default: BucketLayout::Values(vec!(
                 10f64, 30f64, 100f64, 300f64, 1000f64, 3000f64, 10000f64, 30000f64, 100000f64,
-            ),
+            )),
             custom_buckets,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
+    use crate::utils::tests::ApproxComparison;
+
+    use super::*;
+
+    fn histogram_settings() -> HistogramSettings {
+        HistogramSettings::from(1, 1_000, 1, &MEASUREMENT_UNITS.time.millis)
+    }
+
+    #[test]
+    fn test_validate_summaries() {
+        let mut settings = PrometheusSettings::default();
+        settings.metrics.histograms.summaries.insert("hiccups_duration_seconds".into(), SummarySettings { quantiles: vec![0.0, 0.5, 1.0], window_snapshots: 1 });
+        assert!(settings.validate().is_ok());
+        settings.metrics.histograms.summaries.insert("hiccups_duration_seconds".into(), SummarySettings { quantiles: vec![0.5, 99.0], window_snapshots: 1 });
+        assert!(settings.validate().is_err());
+        settings.metrics.histograms.summaries.insert("hiccups_duration_seconds".into(), SummarySettings { quantiles: vec![0.5], window_snapshots: 0 });
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_native_histograms() {
+        let native = |schema, zero_threshold, max_buckets| NativeHistogramSettings { schema, zero_threshold, max_buckets };
+        let mut settings = PrometheusSettings::default();
+        settings.metrics.histograms.native.insert("hiccups_duration_seconds".into(), native(MIN_SCHEMA, 0.0, 1));
+        assert!(settings.validate().is_ok());
+        let invalid_settings = [
+            native(MIN_SCHEMA - 1, 0.0, 160),
+            native(MAX_SCHEMA + 1, 0.0, 160),
+            native(3, -0.001, 160),
+            native(3, f64::NAN, 160),
+            native(3, 0.0, 0),
+        ];
+        for invalid in invalid_settings {
+            settings.metrics.histograms.native.insert("hiccups_duration_seconds".into(), invalid);
+            assert!(settings.validate().is_err());
+        }
+        settings.metrics.histograms.native.insert("hiccups_duration_seconds".into(), native(MAX_SCHEMA, 0.001, 160));
+        settings.metrics.histograms.summaries.insert("hiccups_duration_seconds".into(), SummarySettings { quantiles: vec![0.5], window_snapshots: 1 });
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_buckets() {
+        let mut settings = PrometheusSettings::default();
+        settings.metrics.histograms.buckets.custom_buckets.insert("hiccups_duration_seconds".into(), BucketLayout::Spec(BucketSpec::Hdr { max_count: 0 }));
+        assert!(settings.validate().is_ok());
+        let invalid_layouts = vec![
+            BucketLayout::Values(vec![0.1, 0.05]),
+            BucketLayout::Spec(BucketSpec::Exponential { start: 0.001, factor: 1.0, count: 5 }),
+            BucketLayout::Spec(BucketSpec::Linear { start: 0.0, width: 0.1, count: 0 }),
+        ];
+        for layout in invalid_layouts {
+            settings.metrics.histograms.buckets.custom_buckets.insert("probe_http_duration_seconds".into(), layout);
+            assert!(settings.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_resolve_exponential_and_linear_buckets() {
+        let exponential = BucketLayout::Spec(BucketSpec::Exponential { start: 0.000_1, factor: 2.0, count: 4 });
+        let buckets = exponential.resolve(&histogram_settings()).unwrap();
+        assert_eq!(buckets.len(), 4);
+        assert!(buckets[3].is_eq(0.000_8, 6));
+
+        let linear = BucketLayout::Spec(BucketSpec::Linear { start: 0.0, width: 0.25, count: 5 });
+        assert_eq!(linear.resolve(&histogram_settings()).unwrap(), vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+    }
+
+    #[test]
+    fn test_resolve_hdr_buckets_in_the_prometheus_unit() {
+        let buckets = BucketLayout::Spec(BucketSpec::Hdr { max_count: 1_000 }).resolve(&histogram_settings()).unwrap();
+        // a precision of 1 tells apart the values up to 31 and then halves the resolution on every power of 2
+        assert!(buckets[0].is_eq(0.001, 6));
+        assert!(buckets[30].is_eq(0.031, 6));
+        assert!(buckets[31].is_eq(0.033, 6));
+        assert!(*buckets.last().unwrap() >= 1.0);
+
+        let thinned = BucketLayout::Spec(BucketSpec::Hdr { max_count: 10 }).resolve(&histogram_settings()).unwrap();
+        assert!(thinned.len() <= 10);
+        assert_eq!(thinned.last(), buckets.last());
+    }
+
+    #[test]
+    fn test_reject_invalid_buckets() {
+        assert!(BucketLayout::from(vec![1.0, 3.0, 2.0]).resolve(&histogram_settings()).is_err());
+        assert!(BucketLayout::from(vec![1.0, 1.0]).resolve(&histogram_settings()).is_err());
+        assert!(BucketLayout::from(vec![1.0, f64::INFINITY]).resolve(&histogram_settings()).is_err());
+        assert!(BucketLayout::from(vec![]).resolve(&histogram_settings()).is_err());
+        assert!(BucketLayout::Spec(BucketSpec::Exponential { start: 0.0, factor: 2.0, count: 3 }).resolve(&histogram_settings()).is_err());
+        assert!(BucketLayout::Spec(BucketSpec::Linear { start: 1.0, width: 0.0, count: 3 }).resolve(&histogram_settings()).is_err());
+        assert!(BucketLayout::Spec(BucketSpec::Linear { start: 1.0, width: 1.0, count: 0 }).resolve(&histogram_settings()).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+//! Authentication of the scrapes with a bearer token, read from a file so it isn't kept on the config, or with
+//! the password of a user, checked against its bcrypt hash.
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+
+use ring::hmac;
+use ring::rand::SystemRandom;
+use tokio::sync::Semaphore;
+
+use crate::errors::{Error::Msg, Result};
+use crate::exporters::prometheus_exporter::prometheus_settings::PrometheusAuthSettings;
+
+/// The bcrypt verifications running at once, so a flood of bad logins can't take the blocking thread pool that
+/// the collectors use too.
+const MAX_CONCURRENT_VERIFICATIONS: usize = 2;
+
+pub struct ScrapeAuth {
+    token: Option<Vec<u8>>,
+    basic_users: HashMap<String, String>,
+    /// The digest of the last `user:password` verified of every user, since bcrypt is slow on purpose and the same
+    /// password is sent on every scrape. It's keyed with a random key of the process, so the passwords aren't kept.
+    verified: Mutex<HashMap<String, hmac::Tag>>,
+    verified_key: hmac::Key,
+    verifications: Semaphore,
+}
+
+impl ScrapeAuth {
+    /// The authentication of the scrapes, none when it isn't configured.
+    pub fn from(settings: &PrometheusAuthSettings) -> Result<Option<ScrapeAuth>> {
+        let token = match settings.bearer_token_file.as_ref() {
+            Some(token_file) => Some(read_token(token_file)?),
+            None => None,
+        };
+        for (user, hash) in settings.basic_users.iter() {
+            bcrypt::verify("", hash).map_err(|error| Msg(format!("Password hash of the user {} is invalid. Reason: {}", user, error)))?;
+        }
+        if token.is_none() && settings.basic_users.is_empty() {
+            return Ok(None);
+        }
+        let verified_key = hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+            .map_err(|_| Msg("Key of the verified passwords couldn't be generated".to_string()))?;
+        Ok(Some(ScrapeAuth {
+            token,
+            basic_users: settings.basic_users.clone(),
+            verified: Mutex::default(),
+            verified_key,
+            verifications: Semaphore::new(MAX_CONCURRENT_VERIFICATIONS),
+        }))
+    }
+
+    /// Whether the `Authorization` header of the scrape carries the token or the password of a user.
+    pub async fn authorizes(&self, authorization: Option<&str>) -> bool {
+        match authorization.and_then(|authorization| authorization.split_once(' ')) {
+            Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => {
+                self.token.as_ref().is_some_and(|expected| constant_time_eq(token.trim().as_bytes(), expected))
+            },
+            Some((scheme, credentials)) if scheme.eq_ignore_ascii_case("basic") => self.authorizes_user(credentials.trim()).await,
+            _ => false,
+        }
+    }
+
+    /// The challenges of the 401 responses, one for every scheme configured.
+    pub fn challenges(&self) -> Vec<&'static str> {
+        let mut challenges = vec![];
+        if self.token.is_some() {
+            challenges.push("Bearer");
+        }
+        if !self.basic_users.is_empty() {
+            challenges.push("Basic realm=\"rusty-advisor\"");
+        }
+        challenges
+    }
+
+    /// Verifies the `user:password` in base64 against the hash of the user on the blocking thread pool, unless
+    /// it's the password verified last. It's rejected when all the verifications are taken.
+    async fn authorizes_user(&self, credentials: &str) -> bool {
+        let credentials = match base64::decode(credentials).ok().and_then(|credentials| String::from_utf8(credentials).ok()) {
+            Some(credentials) => credentials,
+            None => return false,
+        };
+        let (user, password) = match credentials.split_once(':') {
+            Some(user_password) => user_password,
+            None => return false,
+        };
+        let hash = match self.basic_users.get(user) {
+            Some(hash) => hash.clone(),
+            None => return false,
+        };
+        if self.verified.lock().unwrap().get(user)
+            .is_some_and(|verified| hmac::verify(&self.verified_key, credentials.as_bytes(), verified.as_ref()).is_ok()) {
+            return true;
+        }
+        let _permit = match self.verifications.try_acquire() {
+            Ok(permit) => permit,
+            Err(_) => return false,
+        };
+        let to_verify = password.to_string();
+        let verified = tokio::task::spawn_blocking(move || bcrypt::verify(to_verify, &hash).unwrap_or(false)).await
+            .unwrap_or(false);
+        if verified {
+            self.verified.lock().unwrap().insert(user.to_string(), hmac::sign(&self.verified_key, credentials.as_bytes()));
+        }
+        verified
+    }
+}
+
+fn read_token(token_file: &str) -> Result<Vec<u8>> {
+    let token = fs::read_to_string(token_file)
+        .map_err(|error| Msg(format!("Bearer token file {} couldn't be read. Reason: {}", token_file, error)))?;
+    let token = token.trim();
+    if token.is_empty() {
+        return Err(Msg(format!("Bearer token file {} is empty", token_file)));
+    }
+    Ok(token.as_bytes().to_vec())
+}
+
+/// Compares in constant time, so the time to reject a scrape doesn't tell how much of the secret it guessed.
+fn constant_time_eq(value: &[u8], expected: &[u8]) -> bool {
+    value.len() == expected.len()
+        && value.iter().zip(expected.iter()).fold(0u8, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    /// A token file removed when the test ends.
+    struct TokenFile(PathBuf);
+
+    impl TokenFile {
+        fn new(token: &str) -> TokenFile {
+            let path = std::env::temp_dir().join(format!("rusty-advisor-scrape-token-{}-{}", std::process::id(), token.len()));
+            fs::write(&path, token).unwrap();
+            TokenFile(path)
+        }
+
+        fn settings(&self) -> PrometheusAuthSettings {
+            PrometheusAuthSettings { bearer_token_file: Some(self.0.to_string_lossy().into_owned()), ..PrometheusAuthSettings::default() }
+        }
+    }
+
+    impl Drop for TokenFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn basic(credentials: &str) -> String {
+        format!("Basic {}", base64::encode(credentials))
+    }
+
+    #[test]
+    fn test_authorizes_the_bearer_token() {
+        let token_file = TokenFile::new("s3cr3t-token\n");
+        let auth = ScrapeAuth::from(&token_file.settings()).unwrap().unwrap();
+        assert!(aw!(auth.authorizes(Some("Bearer s3cr3t-token"))));
+        assert!(aw!(auth.authorizes(Some("bearer s3cr3t-token"))));
+        assert!(!aw!(auth.authorizes(Some("Bearer s3cr3t-tokem"))));
+        assert!(!aw!(auth.authorizes(Some("Bearer s3cr3t"))));
+        assert!(!aw!(auth.authorizes(Some("Basic s3cr3t-token"))));
+        assert!(!aw!(auth.authorizes(None)));
+        assert_eq!(auth.challenges(), vec!["Bearer"]);
+    }
+
+    #[test]
+    fn test_authorizes_the_basic_users() {
+        let settings = PrometheusAuthSettings {
+            basic_users: hashmap! {"prometheus".to_string() => bcrypt::hash("s3cr3t", 4).unwrap()},
+            ..PrometheusAuthSettings::default()
+        };
+        let auth = ScrapeAuth::from(&settings).unwrap().unwrap();
+        assert!(aw!(auth.authorizes(Some(&basic("prometheus:s3cr3t")))));
+        // the second time it's verified from the cache
+        assert!(aw!(auth.authorizes(Some(&basic("prometheus:s3cr3t")))));
+        assert!(!aw!(auth.authorizes(Some(&basic("prometheus:s3cr3u")))));
+        assert!(!aw!(auth.authorizes(Some(&basic("grafana:s3cr3t")))));
+        assert!(!aw!(auth.authorizes(Some(&basic("prometheus")))));
+        assert!(!aw!(auth.authorizes(Some("Basic not-base64"))));
+        assert!(!aw!(auth.authorizes(Some("Bearer s3cr3t"))));
+        assert_eq!(auth.challenges(), vec!["Basic realm=\"rusty-advisor\""]);
+    }
+
+    #[test]
+    fn test_rejects_the_basic_users_when_the_verifications_are_taken() {
+        let settings = PrometheusAuthSettings {
+            basic_users: hashmap! {"prometheus".to_string() => bcrypt::hash("s3cr3t", 4).unwrap()},
+            ..PrometheusAuthSettings::default()
+        };
+        let auth = ScrapeAuth::from(&settings).unwrap().unwrap();
+        let permits: Vec<_> = (0..MAX_CONCURRENT_VERIFICATIONS).map(|_| auth.verifications.try_acquire().unwrap()).collect();
+        assert!(!aw!(auth.authorizes(Some(&basic("prometheus:s3cr3t")))));
+        drop(permits);
+        assert!(aw!(auth.authorizes(Some(&basic("prometheus:s3cr3t")))));
+        // the verified password is cached as a digest, and it doesn't take a verification
+        let _permits: Vec<_> = (0..MAX_CONCURRENT_VERIFICATIONS).map(|_| auth.verifications.try_acquire().unwrap()).collect();
+        assert!(aw!(auth.authorizes(Some(&basic("prometheus:s3cr3t")))));
+        assert!(!aw!(auth.authorizes(Some(&basic("prometheus:s3cr3u")))));
+    }
+
+    #[test]
+    fn test_auth_settings() {
+        assert!(ScrapeAuth::from(&PrometheusAuthSettings::default()).unwrap().is_none());
+        assert!(ScrapeAuth::from(&TokenFile::new(" \n").settings()).is_err());
+        let missing = PrometheusAuthSettings { bearer_token_file: Some("/nonexistent/token".into()), ..PrometheusAuthSettings::default() };
+        assert!(ScrapeAuth::from(&missing).is_err());
+        let invalid_hash = PrometheusAuthSettings { basic_users: hashmap! {"prometheus".to_string() => "s3cr3t".to_string()}, ..PrometheusAuthSettings::default() };
+        assert!(ScrapeAuth::from(&invalid_hash).is_err());
+    }
+}
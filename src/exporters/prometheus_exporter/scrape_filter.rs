@@ -0,0 +1,192 @@
+//! Selection of the series served by a scrape, from its path and its query.
+//!
+//! The metrics path serves every series, and `<path>/<group>` the series of a group, the ones whose name
+//! starts with any of the prefixes of the group. The query narrows them down, by name with every
+//! `name[]=<name>` and by tag with every `match=<tag>=<value>`, e.g.
+//! `/metrics?name[]=hiccups_duration_seconds&match=component=rusty_advisor`.
+
+use std::collections::HashMap;
+
+use hyper::Uri;
+use prometheus::proto::MetricFamily;
+use protobuf::RepeatedField;
+
+use crate::exporters::prometheus_exporter::metrics::Series;
+
+/// The paths served by the exporter.
+#[derive(Debug, Clone)]
+pub struct ScrapeRoutes {
+    path: String,
+    groups: HashMap<String, Vec<String>>,
+}
+
+impl ScrapeRoutes {
+    pub fn new(path: &str, groups: HashMap<String, Vec<String>>) -> ScrapeRoutes {
+        ScrapeRoutes {
+            path: path.trim_end_matches('/').to_string(),
+            groups,
+        }
+    }
+
+    /// The filter of the scrape, none when the path is neither the metrics path nor the one of a group.
+    pub fn filter(&self, uri: &Uri) -> Option<ScrapeFilter> {
+        let path = uri.path().trim_end_matches('/');
+        let prefixes = if path == self.path {
+            None
+        } else {
+            let group = path.strip_prefix(&self.path)?.strip_prefix('/')?;
+            Some(self.groups.get(group)?.clone())
+        };
+        let mut filter = ScrapeFilter { prefixes, names: vec![], matchers: vec![] };
+        for pair in uri.query().unwrap_or_default().split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match decode(key).as_str() {
+                "name[]" | "name" => filter.names.push(decode(value)),
+                "match" => {
+                    let value = decode(value);
+                    match value.split_once('=') {
+                        Some((tag, tag_value)) => filter.matchers.push((tag.to_string(), tag_value.to_string())),
+                        None => warn!("Ignoring the matcher {} of a scrape, which isn't like <tag>=<value>", value),
+                    }
+                },
+                _ => {},
+            }
+        }
+        Some(filter)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ScrapeFilter {
+    /// The name prefixes of the group scraped, none when every series is.
+    prefixes: Option<Vec<String>>,
+    names: Vec<String>,
+    matchers: Vec<(String, String)>,
+}
+
+impl ScrapeFilter {
+    pub fn matches<'a, I>(&self, name: &str, mut tags: I) -> bool
+        where I: FnMut(&str) -> Option<&'a str>
+    {
+        self.prefixes.as_ref().is_none_or(|prefixes| prefixes.iter().any(|prefix| name.starts_with(prefix.as_str())))
+            && (self.names.is_empty() || self.names.iter().any(|selected| selected == name))
+            && self.matchers.iter().all(|(tag, value)| tags(tag) == Some(value.as_str()))
+    }
+
+    /// The series of the exporter selected by the scrape.
+    pub(crate) fn select<'a, S: Series>(&'a self, series: &'a HashMap<u64, S>) -> impl Iterator<Item=&'a S> + 'a {
+        series.values().filter(move |series| {
+            let tags = series.metric_description().tags();
+            self.matches(series.name(), |tag| tags.get(tag).map(String::as_str))
+        })
+    }
+
+    /// The families gathered from the registry of the `prometheus` crate selected by the scrape, without
+    /// the ones left with no metrics.
+    pub fn select_families(&self, families: Vec<MetricFamily>) -> Vec<MetricFamily> {
+        families.into_iter()
+            .filter_map(|mut family| {
+                let name = family.get_name().to_string();
+                let metrics: Vec<_> = family.take_metric().into_iter()
+                    .filter(|metric| {
+                        let labels = metric.get_label();
+                        self.matches(&name, |tag| labels.iter().find(|label| label.get_name() == tag).map(|label| label.get_value()))
+                    })
+                    .collect();
+                if metrics.is_empty() {
+                    return None;
+                }
+                family.set_metric(RepeatedField::from_vec(metrics));
+                Some(family)
+            })
+            .collect()
+    }
+}
+
+/// Decodes a component of a query, with its `+` as spaces and its `%XX` as bytes.
+fn decode(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() && bytes[i + 1].is_ascii_hexdigit() && bytes[i + 2].is_ascii_hexdigit() => {
+                decoded.push(hex_value(bytes[i + 1]) * 16 + hex_value(bytes[i + 2]));
+                i += 2;
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hex_value(digit: u8) -> u8 {
+    (digit as char).to_digit(16).unwrap_or_default() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::proto::{LabelPair, Metric};
+
+    use super::*;
+
+    fn routes() -> ScrapeRoutes {
+        ScrapeRoutes::new("/metrics", hashmap! {"hiccups".into() => vec!["hiccups_".into()]})
+    }
+
+    fn filter(uri: &str) -> Option<ScrapeFilter> {
+        routes().filter(&uri.parse().unwrap())
+    }
+
+    #[test]
+    fn test_filter_of_the_paths() {
+        assert_eq!(filter("/metrics"), Some(ScrapeFilter::default()));
+        assert_eq!(filter("/metrics/"), Some(ScrapeFilter::default()));
+        assert_eq!(filter("/metrics/hiccups").unwrap().prefixes, Some(vec!["hiccups_".to_string()]));
+        assert_eq!(filter("/metrics/unknown"), None);
+        assert_eq!(filter("/metricsfoo"), None);
+        assert_eq!(filter("/"), None);
+    }
+
+    #[test]
+    fn test_filter_by_name_and_tags() {
+        let filter = filter("/metrics?name%5B%5D=hiccups_duration_seconds&name[]=probe_success&match=component%3Drusty_advisor").unwrap();
+        let tags = hashmap! {"component".to_string() => "rusty_advisor".to_string()};
+        let tags = |tag: &str| tags.get(tag).map(String::as_str);
+        assert!(filter.matches("hiccups_duration_seconds", tags));
+        assert!(filter.matches("probe_success", tags));
+        assert!(!filter.matches("hiccups_attributed_duration_seconds", tags));
+        assert!(!filter.matches("probe_success", |_| None));
+
+        let group = self::filter("/metrics/hiccups?match=cause=swap").unwrap();
+        assert!(group.matches("hiccups_attributed_duration_seconds", |tag| if tag == "cause" { Some("swap") } else { None }));
+        assert!(!group.matches("hiccups_attributed_duration_seconds", |tag| if tag == "cause" { Some("irq") } else { None }));
+        assert!(!group.matches("probe_success", |tag| if tag == "cause" { Some("swap") } else { None }));
+    }
+
+    #[test]
+    fn test_select_families() {
+        let metric = |handler: &str| {
+            let mut label = LabelPair::default();
+            label.set_name("handler".into());
+            label.set_value(handler.into());
+            let mut metric = Metric::default();
+            metric.set_label(RepeatedField::from_vec(vec![label]));
+            metric
+        };
+        let family = |name: &str| {
+            let mut family = MetricFamily::default();
+            family.set_name(name.into());
+            family.set_metric(RepeatedField::from_vec(vec![metric("all"), metric("metrics")]));
+            family
+        };
+        let families = vec![family("prometheus_http_requests_total"), family("process_cpu_seconds_total")];
+
+        let selected = filter("/metrics?name[]=prometheus_http_requests_total&match=handler=all").unwrap().select_families(families);
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].get_metric().len(), 1);
+        assert_eq!(selected[0].get_metric()[0].get_label()[0].get_value(), "all");
+    }
+}
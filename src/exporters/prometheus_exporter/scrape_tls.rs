@@ -0,0 +1,137 @@
+//! TLS of the scrapes, authenticating the scrapers by their client certificate when a client CA is configured.
+//!
+//! Every connection is handshaken on its own task, so a scraper which stalls in the middle of the handshake
+//! doesn't hold back the others.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::channel::mpsc;
+use futures::SinkExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::{AllowAnyAuthenticatedClient, NoClientAuth, PrivateKey, RootCertStore, ServerConfig};
+use tokio_rustls::rustls::internal::pemfile;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+use crate::errors::{Error::Msg, Result};
+use crate::exporters::prometheus_exporter::prometheus_settings::PrometheusTlsSettings;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The handshaken connections waiting for the server to take them.
+const PENDING_CONNECTIONS: usize = 64;
+
+pub struct ScrapeTls {
+    acceptor: TlsAcceptor,
+}
+
+impl ScrapeTls {
+    /// The TLS of the scrapes, none when it isn't enabled.
+    pub fn from(settings: &PrometheusTlsSettings) -> Result<Option<ScrapeTls>> {
+        if !settings.enabled {
+            return Ok(None);
+        }
+        let certs = pemfile::certs(&mut reader(&settings.cert_file)?)
+            .map_err(|_| Msg(format!("Certificate file {} isn't valid PEM", settings.cert_file)))?;
+        if certs.is_empty() {
+            return Err(Msg(format!("Certificate file {} has no certificates", settings.cert_file)));
+        }
+        let client_auth = match settings.client_ca_file.as_ref() {
+            Some(client_ca_file) => {
+                let mut client_cas = RootCertStore::empty();
+                let (added, _) = client_cas.add_pem_file(&mut reader(client_ca_file)?)
+                    .map_err(|_| Msg(format!("Client CA file {} isn't valid PEM", client_ca_file)))?;
+                if added == 0 {
+                    return Err(Msg(format!("Client CA file {} has no valid certificates", client_ca_file)));
+                }
+                AllowAnyAuthenticatedClient::new(client_cas)
+            },
+            None => NoClientAuth::new(),
+        };
+        let mut config = ServerConfig::new(client_auth);
+        config.set_single_cert(certs, private_key(&settings.key_file)?)
+            .map_err(|error| Msg(format!("Key file {} isn't supported. Reason: {}", settings.key_file, error)))?;
+        Ok(Some(ScrapeTls { acceptor: TlsAcceptor::from(Arc::new(config)) }))
+    }
+
+    /// The connections of the listener whose handshake succeeded, the other ones are dropped.
+    pub fn incoming(&self, mut listener: TcpListener) -> mpsc::Receiver<std::io::Result<TlsStream<TcpStream>>> {
+        let (sender, receiver) = mpsc::channel(PENDING_CONNECTIONS);
+        let acceptor = self.acceptor.clone();
+        tokio::spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(error) => {
+                        // e.g. too many open files, which may be closed soon
+                        warn!("Prometheus Exporter couldn't accept a connection. Reason: {}", error);
+                        tokio::time::delay_for(Duration::from_millis(100)).await;
+                        continue;
+                    },
+                };
+                let acceptor = acceptor.clone();
+                let mut sender = sender.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => { let _ = sender.send(Ok(stream)).await; },
+                        Ok(Err(error)) => debug!("TLS handshake of a scrape failed. Reason: {}", error),
+                        Err(_) => debug!("TLS handshake of a scrape timed out"),
+                    }
+                });
+            }
+        });
+        receiver
+    }
+}
+
+fn reader(path: &str) -> Result<BufReader<File>> {
+    let file = File::open(path).map_err(|error| Msg(format!("File {} couldn't be read. Reason: {}", path, error)))?;
+    Ok(BufReader::new(file))
+}
+
+/// The first PKCS#8 key of the file, or the first RSA one when there are none.
+fn private_key(key_file: &str) -> Result<PrivateKey> {
+    let invalid = |_| Msg(format!("Key file {} isn't valid PEM", key_file));
+    let mut keys = pemfile::pkcs8_private_keys(&mut reader(key_file)?).map_err(invalid)?;
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut reader(key_file)?).map_err(invalid)?;
+    }
+    keys.into_iter().next().ok_or_else(|| Msg(format!("Key file {} has no PKCS#8 or RSA private key", key_file)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn settings() -> PrometheusTlsSettings {
+        PrometheusTlsSettings {
+            enabled: true,
+            cert_file: fixture("localhost.pem"),
+            key_file: fixture("localhost-key.pem"),
+            client_ca_file: Some(fixture("ca.pem")),
+        }
+    }
+
+    #[test]
+    fn test_tls_settings() {
+        assert!(ScrapeTls::from(&PrometheusTlsSettings::default()).unwrap().is_none());
+        assert!(ScrapeTls::from(&settings()).unwrap().is_some());
+
+        let mut config = settings();
+        config.key_file = fixture("ca.pem");
+        assert!(ScrapeTls::from(&config).is_err());
+        let mut config = settings();
+        config.cert_file = fixture("missing.pem");
+        assert!(ScrapeTls::from(&config).is_err());
+        let mut config = settings();
+        config.client_ca_file = Some(fixture("localhost-key.pem"));
+        assert!(ScrapeTls::from(&config).is_err());
+    }
+}
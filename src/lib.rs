@@ -6,6 +6,7 @@ extern crate crossbeam_channel;
 extern crate float_cmp;
 #[macro_use]
 extern crate lazy_static;
+extern crate libc;
 #[macro_use]
 extern crate log;
 #[cfg(test)]
@@ -31,7 +32,20 @@ use futures::future::join3;
 use tokio::runtime;
 use tokio::sync::broadcast;
 
+use collectors::cpufreq_collector::cpufreq_monitor::CpufreqMonitor;
+use collectors::exec_collector::exec_monitor::ExecMonitor;
 use collectors::hiccups_collector::hiccup_monitor::HiccupMonitor;
+use collectors::hwmon_collector::hwmon_monitor::HwmonMonitor;
+use collectors::interrupts_collector::interrupts_monitor::InterruptsMonitor;
+use collectors::log_tail_collector::log_tail_monitor::LogTailMonitor;
+use collectors::numa_collector::numa_monitor::NumaMonitor;
+use collectors::probe_collector::dns_probe::DnsProbeMonitor;
+use collectors::probe_collector::http_probe::HttpProbeMonitor;
+use collectors::probe_collector::tcp_probe::TcpProbeMonitor;
+use collectors::schedstat_collector::schedstat_monitor::SchedstatMonitor;
+use collectors::sockets_collector::sockets_monitor::SocketsMonitor;
+use collectors::textfile_collector::textfile_monitor::TextfileMonitor;
+use collectors::timex_collector::timex_monitor::TimexMonitor;
 use settings::Settings;
 
 use crate::exporters::metrics_exporter::{MetricsExporter, MetricsSnapshot};
@@ -50,7 +64,8 @@ pub struct RustyAdvisor;
 impl RustyAdvisor {
     pub fn run() -> Result<(), Box<dyn std::error::Error>> {
         info!("RustyAdvisor is starting...");
-        let settings = Settings::load();
+        let settings = Settings::load()?;
+        let prometheus_exporter = PrometheusExporter::new(settings.prometheus_exporter)?;
 
         let mut threaded_rt = runtime::Builder::new()
             .threaded_scheduler()
@@ -65,7 +80,58 @@ impl RustyAdvisor {
         let mut monitor = HiccupMonitor::new(&settings.hiccups_monitor);
         monitor.run();
 
-        let prometheus_exporter = PrometheusExporter::new(settings.prometheus_exporter);
+        if settings.collectors.schedstat.enabled {
+            threaded_rt.spawn(SchedstatMonitor::new(&settings.collectors).start());
+        }
+        if settings.collectors.sockets.enabled {
+            threaded_rt.spawn(SocketsMonitor::new(&settings.collectors).start());
+        }
+        if settings.collectors.interrupts.enabled {
+            threaded_rt.spawn(InterruptsMonitor::new(&settings.collectors).start());
+        }
+        if settings.collectors.numa.enabled {
+            threaded_rt.spawn(NumaMonitor::new(&settings.collectors).start());
+        }
+        if settings.collectors.hwmon.enabled {
+            threaded_rt.spawn(HwmonMonitor::new(&settings.collectors).start());
+        }
+        if settings.collectors.cpufreq.enabled {
+            threaded_rt.spawn(CpufreqMonitor::new(&settings.collectors).start());
+        }
+        if settings.collectors.timex.enabled {
+            threaded_rt.spawn(TimexMonitor::new(&settings.collectors).start());
+        }
+        if settings.collectors.textfile.enabled {
+            threaded_rt.spawn(TextfileMonitor::new(&settings.collectors).start());
+        }
+        for exec in settings.collectors.exec.iter() {
+            threaded_rt.spawn(ExecMonitor::new(exec).start());
+        }
+        for log_tail in settings.collectors.log_tail.iter() {
+            match LogTailMonitor::new(log_tail) {
+                Ok(monitor) => { threaded_rt.spawn(monitor.start()); },
+                Err(error) => error!("Log tail collector of {} is not started. Reason: {}", log_tail.path, error),
+            }
+        }
+        for http_probe in settings.collectors.http_probe.iter() {
+            match HttpProbeMonitor::new(http_probe) {
+                Ok(monitor) => { threaded_rt.spawn(monitor.start()); },
+                Err(error) => error!("HTTP probe {} is not started. Reason: {}", http_probe.name, error),
+            }
+        }
+        for tcp_probe in settings.collectors.tcp_probe.iter() {
+            match TcpProbeMonitor::new(tcp_probe) {
+                Ok(monitor) => { threaded_rt.spawn(monitor.start()); },
+                Err(error) => error!("TCP probe {} is not started. Reason: {}", tcp_probe.name, error),
+            }
+        }
+        for dns_probe in settings.collectors.dns_probe.iter() {
+            match DnsProbeMonitor::new(dns_probe) {
+                Ok(monitor) => { threaded_rt.spawn(monitor.start()); },
+                Err(error) => error!("DNS probe {} is not started. Reason: {}", dns_probe.name, error),
+            }
+        }
+
         let prometheus_runtime = prometheus_exporter.start_server();
         let prometheus_listener = prometheus_exporter.listen_metrics(receiver);
 
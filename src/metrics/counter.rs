@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::errors::Result;
+use crate::exporters::metrics_exporter::CounterSample;
+use crate::metrics::measurement_unit::{MEASUREMENT_UNITS, MeasurementUnit};
+use crate::metrics::metric::{MetricActivity, MetricDescription};
+use crate::metrics::registry;
+
+#[derive(Clone, Debug)]
+pub struct CounterBuilder {
+    pub name: String,
+    pub description: String,
+    pub tags: HashMap<String, String>,
+    pub measurement_unit: &'static MeasurementUnit,
+}
+
+impl CounterBuilder {
+    pub fn new(name: String, description: String) -> CounterBuilder {
+        CounterBuilder {
+            name,
+            description,
+            tags: HashMap::new(),
+            measurement_unit: &MEASUREMENT_UNITS.none,
+        }
+    }
+
+    pub fn with_tags(mut self, name: String, value: String) -> CounterBuilder {
+        self.tags.insert(name, value);
+        self
+    }
+
+    pub fn with_measurement_unit(mut self, measurement_unit: &'static MeasurementUnit) -> CounterBuilder {
+        self.measurement_unit = measurement_unit;
+        self
+    }
+
+    pub fn metric_description(&self) -> Result<MetricDescription> {
+        MetricDescription::from(self.name.clone(), self.description.clone(), self.tags.clone())
+    }
+
+    pub async fn build(self) -> Result<CounterRecorder> {
+        registry::global_registry().get_or_register_counter(self).await
+    }
+
+    /// build_sync has to be used when the caller is running out of the Tokio async runtime
+    #[tokio::main]
+    pub async fn build_sync(self) -> Result<CounterRecorder> {
+        registry::global_registry().get_or_register_counter(self).await
+    }
+}
+
+/// Increments a counter. It's cheap to clone and every clone increments the same counter.
+#[derive(Clone, Debug)]
+pub struct CounterRecorder {
+    value: Arc<AtomicU64>,
+    activity: Arc<MetricActivity>,
+    pub measurement_unit: &'static MeasurementUnit,
+}
+
+impl CounterRecorder {
+    pub fn increment(&self) {
+        self.add(1)
+    }
+
+    /// Adding 0 keeps the counter from expiring, like any other increment.
+    pub fn add(&self, times: u64) {
+        self.value.fetch_add(times, Ordering::Relaxed);
+        self.activity.touch();
+    }
+
+    /// Whether the counter was removed from the registry for not being updated, so it has to be registered again.
+    pub fn is_expired(&self) -> bool {
+        self.activity.is_expired()
+    }
+}
+
+#[derive(Debug)]
+pub struct Counter {
+    metric_description: MetricDescription,
+    measurement_unit: &'static MeasurementUnit,
+    value: Arc<AtomicU64>,
+    activity: Arc<MetricActivity>,
+}
+
+impl Counter {
+    pub fn new(metric_description: MetricDescription, measurement_unit: &'static MeasurementUnit) -> Counter {
+        Counter {
+            metric_description,
+            measurement_unit,
+            value: Arc::new(AtomicU64::new(0)),
+            activity: Arc::new(MetricActivity::new()),
+        }
+    }
+
+    /// Returns the increments recorded since the last time the counter was sampled.
+    pub fn sample(&mut self) -> CounterSample {
+        CounterSample::new(self.value.swap(0, Ordering::Relaxed), self.measurement_unit)
+            .with_updated(self.activity.take_updated())
+    }
+
+    pub fn new_recorder(&self) -> CounterRecorder {
+        self.activity.touch();
+        CounterRecorder {
+            value: Arc::clone(&self.value),
+            activity: Arc::clone(&self.activity),
+            measurement_unit: self.measurement_unit,
+        }
+    }
+
+    pub fn metric_description(&self) -> &MetricDescription {
+        &self.metric_description
+    }
+
+    pub(crate) fn activity(&self) -> &MetricActivity {
+        &self.activity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_returns_the_increments_since_the_last_sample() {
+        let metric_description = MetricDescription::from("counter_name".into(), "some description".into(), hashmap! {}).unwrap();
+        let mut counter = Counter::new(metric_description, &MEASUREMENT_UNITS.none);
+        let recorder = counter.new_recorder();
+        let other_recorder = recorder.clone();
+
+        recorder.increment();
+        other_recorder.add(10);
+        assert_eq!(counter.sample().value(), 11);
+
+        recorder.add(5);
+        assert_eq!(counter.sample().value(), 5);
+        assert_eq!(counter.sample().value(), 0);
+    }
+
+    #[test]
+    fn test_sample_tells_whether_the_counter_was_updated() {
+        let metric_description = MetricDescription::from("counter_name".into(), "some description".into(), hashmap! {}).unwrap();
+        let mut counter = Counter::new(metric_description, &MEASUREMENT_UNITS.none);
+        let recorder = counter.new_recorder();
+        assert!(counter.sample().updated());
+        assert!(!counter.sample().updated());
+
+        // a running total which didn't change is still updated
+        recorder.add(0);
+        let sample = counter.sample();
+        assert_eq!(sample.value(), 0);
+        assert!(sample.updated());
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::errors::Result;
+use crate::exporters::metrics_exporter::GaugeSample;
+use crate::metrics::measurement_unit::{MEASUREMENT_UNITS, MeasurementUnit};
+use crate::metrics::metric::{MetricActivity, MetricDescription};
+use crate::metrics::registry;
+
+#[derive(Clone, Debug)]
+pub struct GaugeBuilder {
+    pub name: String,
+    pub description: String,
+    pub tags: HashMap<String, String>,
+    pub measurement_unit: &'static MeasurementUnit,
+}
+
+impl GaugeBuilder {
+    pub fn new(name: String, description: String) -> GaugeBuilder {
+        GaugeBuilder {
+            name,
+            description,
+            tags: HashMap::new(),
+            measurement_unit: &MEASUREMENT_UNITS.none,
+        }
+    }
+
+    pub fn with_tags(mut self, name: String, value: String) -> GaugeBuilder {
+        self.tags.insert(name, value);
+        self
+    }
+
+    pub fn with_measurement_unit(mut self, measurement_unit: &'static MeasurementUnit) -> GaugeBuilder {
+        self.measurement_unit = measurement_unit;
+        self
+    }
+
+    pub fn metric_description(&self) -> Result<MetricDescription> {
+        MetricDescription::from(self.name.clone(), self.description.clone(), self.tags.clone())
+    }
+
+    pub async fn build(self) -> Result<GaugeRecorder> {
+        registry::global_registry().get_or_register_gauge(self).await
+    }
+
+    /// build_sync has to be used when the caller is running out of the Tokio async runtime
+    #[tokio::main]
+    pub async fn build_sync(self) -> Result<GaugeRecorder> {
+        registry::global_registry().get_or_register_gauge(self).await
+    }
+}
+
+/// Sets the value of a gauge. It's cheap to clone and every clone updates the same gauge.
+///
+/// The value is kept as the bits of an `f64` so it can be shared without locks.
+#[derive(Clone, Debug)]
+pub struct GaugeRecorder {
+    value: Arc<AtomicU64>,
+    activity: Arc<MetricActivity>,
+    pub measurement_unit: &'static MeasurementUnit,
+}
+
+impl GaugeRecorder {
+    /// Setting the same value again keeps the gauge from expiring.
+    pub fn set(&self, value: f64) {
+        self.value.store(value.to_bits(), Ordering::Relaxed);
+        self.activity.touch();
+    }
+
+    /// Whether the gauge was removed from the registry for not being updated, so it has to be registered again.
+    pub fn is_expired(&self) -> bool {
+        self.activity.is_expired()
+    }
+}
+
+#[derive(Debug)]
+pub struct Gauge {
+    metric_description: MetricDescription,
+    measurement_unit: &'static MeasurementUnit,
+    value: Arc<AtomicU64>,
+    activity: Arc<MetricActivity>,
+}
+
+impl Gauge {
+    pub fn new(metric_description: MetricDescription, measurement_unit: &'static MeasurementUnit) -> Gauge {
+        Gauge {
+            metric_description,
+            measurement_unit,
+            value: Arc::new(AtomicU64::new(0f64.to_bits())),
+            activity: Arc::new(MetricActivity::new()),
+        }
+    }
+
+    /// Returns the last value set on the gauge.
+    pub fn sample(&mut self) -> GaugeSample {
+        GaugeSample::new(f64::from_bits(self.value.load(Ordering::Relaxed)), self.measurement_unit)
+            .with_updated(self.activity.take_updated())
+    }
+
+    pub fn new_recorder(&self) -> GaugeRecorder {
+        self.activity.touch();
+        GaugeRecorder {
+            value: Arc::clone(&self.value),
+            activity: Arc::clone(&self.activity),
+            measurement_unit: self.measurement_unit,
+        }
+    }
+
+    pub fn metric_description(&self) -> &MetricDescription {
+        &self.metric_description
+    }
+
+    pub(crate) fn activity(&self) -> &MetricActivity {
+        &self.activity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::tests::ApproxComparison;
+
+    use super::*;
+
+    #[test]
+    fn test_sample_returns_the_last_value_set() {
+        let metric_description = MetricDescription::from("gauge_name".into(), "some description".into(), hashmap! {}).unwrap();
+        let mut gauge = Gauge::new(metric_description, &MEASUREMENT_UNITS.none);
+        let recorder = gauge.new_recorder();
+
+        assert!(gauge.sample().value().is_eq(0f64, 0));
+        recorder.set(-12.5);
+        recorder.clone().set(42.25);
+        assert!(gauge.sample().value().is_eq(42.25, 0));
+        assert!(gauge.sample().value().is_eq(42.25, 0));
+    }
+}
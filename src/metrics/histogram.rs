@@ -1,6 +1,7 @@
 use std::{fmt, time};
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::sync::Arc;
 
 use hdrhistogram::{Histogram as HdrHistogram, SyncHistogram};
 use hdrhistogram::sync::Recorder;
@@ -11,7 +12,7 @@ use crate::errors::{Error, Result};
 use crate::exporters::metrics_exporter::HistogramSample;
 use crate::metrics::{measurement_unit, registry};
 use crate::metrics::measurement_unit::{MEASUREMENT_UNITS, MeasurementUnit};
-use crate::metrics::metric::MetricDescription;
+use crate::metrics::metric::{MetricActivity, MetricDescription};
 
 #[derive(Clone, Debug)]
 pub struct HistogramBuilder {
@@ -59,24 +60,54 @@ impl HistogramBuilder {
 #[derive(Debug)]
 pub struct HistogramRecorder {
     recorder: Recorder<u64>,
+    activity: Arc<MetricActivity>,
     pub measurement_unit: &'static MeasurementUnit,
 }
 
 impl HistogramRecorder {
-    pub fn new(recorder: Recorder<u64>, measurement_unit: &'static MeasurementUnit) -> HistogramRecorder {
+    pub fn new(recorder: Recorder<u64>, activity: Arc<MetricActivity>, measurement_unit: &'static MeasurementUnit) -> HistogramRecorder {
         HistogramRecorder {
             recorder,
+            activity,
             measurement_unit,
         }
     }
 
+    /// Whether the histogram was removed from the registry for not being updated, so it has to be registered again.
+    pub fn is_expired(&self) -> bool {
+        self.activity.is_expired()
+    }
+
+    /// Keeps the histogram from expiring while there are no values to record, for the series that are
+    /// still meaningful without them.
+    pub fn touch(&self) {
+        self.activity.touch();
+    }
+
     pub fn record(&mut self, value: u64) -> Result<()> {
+        self.activity.touch();
         self.recorder.record(value)
             .map_err(|error| { Error::Msg(format!("Error occurs trying to record value {} on a histogram. Reason: {:#?}", value, error)) })
     }
 
+    /// Records `value` and back-fills the samples that were expected every `expected_interval`
+    /// while `value` was happening, in order to correct the coordinated omission.
+    pub fn record_correct(&mut self, value: u64, expected_interval: u64) -> Result<()> {
+        self.activity.touch();
+        self.recorder.record_correct(value, expected_interval)
+            .map_err(|error| { Error::Msg(format!("Error occurs trying to record value {} on a histogram. Reason: {:#?}", value, error)) })
+    }
+
+    /// Records `count` times the same `value`.
+    pub fn record_n(&mut self, value: u64, count: u64) -> Result<()> {
+        self.activity.touch();
+        self.recorder.record_n(value, count)
+            .map_err(|error| { Error::Msg(format!("Error occurs trying to record value {} on a histogram. Reason: {:#?}", value, error)) })
+    }
+
     pub fn record_duration(&mut self, duration: Duration) -> Result<()> {
         let value = measurement_unit::convert(duration.as_secs_f64(), &MEASUREMENT_UNITS.time.seconds, self.measurement_unit) as u64;
+        self.activity.touch();
         self.recorder.record(value)
             .map_err(|error| { Error::Msg(format!("Error occurs trying to record value {} on a histogram. Reason: {:#?}", value, error)) })
     }
@@ -113,6 +144,9 @@ pub struct HistogramSettings {
     pub high: u64,
     pub precision: u8,
     pub measurement_unit: &'static MeasurementUnit,
+    /// Expected interval between recorded values. When it's present, the coordinated omission
+    /// is corrected at sampling time by back-filling the values missed at this interval.
+    pub expected_interval: Option<u64>,
 }
 
 impl HistogramSettings {
@@ -122,8 +156,14 @@ impl HistogramSettings {
             high,
             precision,
             measurement_unit,
+            expected_interval: None,
         }
     }
+
+    pub fn with_expected_interval(mut self, expected_interval: u64) -> HistogramSettings {
+        self.expected_interval = Some(expected_interval);
+        self
+    }
 }
 
 impl Default for HistogramSettings {
@@ -133,6 +173,7 @@ impl Default for HistogramSettings {
             high: 1_000_000,
             precision: 2,
             measurement_unit: &MEASUREMENT_UNITS.time.seconds,
+            expected_interval: None,
         }
     }
 }
@@ -148,6 +189,7 @@ pub struct Histogram {
     metric_description: MetricDescription,
     histogram_settings: HistogramSettings,
     hdr_histogram: SyncHistogram<u64>,
+    activity: Arc<MetricActivity>,
 }
 
 impl Histogram {
@@ -159,24 +201,34 @@ impl Histogram {
                     metric_description,
                     histogram_settings,
                     hdr_histogram: hdr_histogram.into_sync(),
+                    activity: Arc::new(MetricActivity::new()),
                 })
     }
 
     /// This method is not thread safe
     pub fn sample(&mut self, reset: bool) -> HistogramSample {
         self.hdr_histogram.refresh_timeout(time::Duration::from_millis(1));
-        let histogram_sample = self.hdr_histogram.clone_correct(self.hdr_histogram.max());
+        let histogram_sample = match self.histogram_settings.expected_interval {
+            Some(expected_interval) => self.hdr_histogram.clone_correct(expected_interval),
+            None => HdrHistogram::clone(&self.hdr_histogram),
+        };
         if reset {
             self.hdr_histogram.reset();
         }
         HistogramSample::new(histogram_sample, self.histogram_settings.clone())
+            .with_updated(self.activity.take_updated())
     }
 
     pub fn new_recorder(&self) -> HistogramRecorder {
-        HistogramRecorder::new(self.hdr_histogram.recorder(), self.histogram_settings.measurement_unit)
+        self.activity.touch();
+        HistogramRecorder::new(self.hdr_histogram.recorder(), Arc::clone(&self.activity), self.histogram_settings.measurement_unit)
     }
 
     pub fn metric_description(&self) -> &MetricDescription {
         &self.metric_description
     }
+
+    pub(crate) fn activity(&self) -> &MetricActivity {
+        &self.activity
+    }
 }
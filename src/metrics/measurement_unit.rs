@@ -89,6 +89,10 @@ impl MeasurementUnit {
             magnitude,
         }
     }
+
+    pub fn dimension(&self) -> &Dimension {
+        &self.dimension
+    }
 }
 
 impl Display for MeasurementUnit {
@@ -7,6 +7,7 @@
 use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use crate::errors::{Error, Result};
 
@@ -37,6 +38,48 @@ pub enum MetricKind {
     Histogram,
 }
 
+/// Shared by a metric and its recorders, to tell the series that are still recorded, even when their
+/// values don't change, from the ones that are not anymore.
+#[derive(Debug)]
+pub struct MetricActivity {
+    updated: AtomicBool,
+    expired: AtomicBool,
+}
+
+impl MetricActivity {
+    /// A new metric counts as updated until it's sampled for the first time.
+    pub fn new() -> MetricActivity {
+        MetricActivity {
+            updated: AtomicBool::new(true),
+            expired: AtomicBool::new(false),
+        }
+    }
+
+    pub fn touch(&self) {
+        self.updated.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the metric was updated since the previous time this was called.
+    pub fn take_updated(&self) -> bool {
+        self.updated.swap(false, Ordering::Relaxed)
+    }
+
+    /// Marks the metric as removed from its registry, so its recorders have to be registered again.
+    pub fn expire(&self) {
+        self.expired.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expired.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for MetricActivity {
+    fn default() -> Self {
+        MetricActivity::new()
+    }
+}
+
 impl MetricDescription {
     pub fn from(name: String, description: String, tags: HashMap<String, String>) -> Result<MetricDescription> {
         Self::validate_name(&name)
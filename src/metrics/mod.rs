@@ -1,4 +1,6 @@
 pub mod histogram;
+pub mod counter;
+pub mod gauge;
 pub mod registry;
 pub mod metric;
 pub mod measurement_unit;
@@ -7,8 +7,10 @@ use tokio::sync::RwLock;
 
 use crate::errors::Error::MetricAlreadyRegDifferently;
 use crate::errors::Result;
+use crate::metrics::counter::{Counter, CounterBuilder, CounterRecorder};
+use crate::metrics::gauge::{Gauge, GaugeBuilder, GaugeRecorder};
 use crate::metrics::histogram::{Histogram, HistogramBuilder, HistogramRecorder};
-use crate::metrics::metric::{MetricDescription, MetricId, MetricName};
+use crate::metrics::metric::{MetricActivity, MetricDescription, MetricId, MetricKind, MetricName};
 
 lazy_static! {
     pub static ref GLOBAL_REGISTRY: Registry = Registry::new("GlobalMetricRegistry".to_string());
@@ -23,6 +25,8 @@ pub(crate) type MetricsStorage<T> = DashMap<MetricName, MetricHolder<T>>;
 pub struct Registry {
     name: String,
     histograms_storage: MetricsStorage<Histogram>,
+    counters_storage: MetricsStorage<Counter>,
+    gauges_storage: MetricsStorage<Gauge>,
 }
 
 impl Registry {
@@ -30,6 +34,8 @@ impl Registry {
         Registry {
             name,
             histograms_storage: DashMap::default(),
+            counters_storage: DashMap::default(),
+            gauges_storage: DashMap::default(),
         }
     }
 
@@ -47,6 +53,36 @@ impl Registry {
                                 }).await
     }
 
+    pub async fn get_or_register_counter(&self, counter_builder: CounterBuilder) -> Result<CounterRecorder> {
+        let metric_desc = counter_builder.metric_description()?;
+        debug!("Adding counter {} on Registry {}. [Description: {}. Unit: {}. Tags: {:#?}]", counter_builder.name,
+               self.name, counter_builder.description, counter_builder.measurement_unit, counter_builder.tags);
+        let measurement_unit = counter_builder.measurement_unit;
+        Self::get_or_add_metric(&self.counters_storage, metric_desc,
+                                |metric_desc| {
+                                    debug!("Creating counter {}", counter_builder.name);
+                                    Counter::new(metric_desc, measurement_unit)
+                                },
+                                |metric| {
+                                    metric.new_recorder()
+                                }).await
+    }
+
+    pub async fn get_or_register_gauge(&self, gauge_builder: GaugeBuilder) -> Result<GaugeRecorder> {
+        let metric_desc = gauge_builder.metric_description()?;
+        debug!("Adding gauge {} on Registry {}. [Description: {}. Unit: {}. Tags: {:#?}]", gauge_builder.name,
+               self.name, gauge_builder.description, gauge_builder.measurement_unit, gauge_builder.tags);
+        let measurement_unit = gauge_builder.measurement_unit;
+        Self::get_or_add_metric(&self.gauges_storage, metric_desc,
+                                |metric_desc| {
+                                    debug!("Creating gauge {}", gauge_builder.name);
+                                    Gauge::new(metric_desc, measurement_unit)
+                                },
+                                |metric| {
+                                    metric.new_recorder()
+                                }).await
+    }
+
     pub(crate) async fn get_or_add_metric<F, T, R, FR>(metrics_storage: &MetricsStorage<T>, metric_description: MetricDescription,
                                                        builder: F, new_recorder: FR) -> Result<R>
         where
@@ -80,17 +116,50 @@ impl Registry {
         }
     }
 
+    /// Removes the metric, so it isn't sampled anymore, and expires its recorders. The recorders have to be
+    /// registered again to record on it.
+    pub async fn remove(&self, kind: &MetricKind, metric_description: &MetricDescription) {
+        debug!("Removing {:?} {} from Registry {}", kind, metric_description.name(), self.name);
+        match kind {
+            MetricKind::Counter => Self::remove_metric(&self.counters_storage, metric_description, Counter::activity).await,
+            MetricKind::Gauge => Self::remove_metric(&self.gauges_storage, metric_description, Gauge::activity).await,
+            MetricKind::Histogram => Self::remove_metric(&self.histograms_storage, metric_description, Histogram::activity).await,
+        }
+    }
+
+    async fn remove_metric<T, F>(metrics_storage: &MetricsStorage<T>, metric_description: &MetricDescription, activity: F)
+        where F: Fn(&T) -> &MetricActivity
+    {
+        let removed = metrics_storage.get(&metric_description.name)
+            .and_then(|metric_holder| metric_holder.metrics.remove(&metric_description.id));
+        if let Some((_, metric)) = removed {
+            activity(&*metric.read().await).expire();
+        }
+        metrics_storage.remove_if(&metric_description.name, |_, metric_holder| metric_holder.metrics.is_empty());
+    }
+
     pub fn histograms(&self) -> Vec<Arc<RwLock<Histogram>>> {
-        self.histograms_storage.iter()
+        Self::metrics(&self.histograms_storage)
+    }
+
+    pub fn counters(&self) -> Vec<Arc<RwLock<Counter>>> {
+        Self::metrics(&self.counters_storage)
+    }
+
+    pub fn gauges(&self) -> Vec<Arc<RwLock<Gauge>>> {
+        Self::metrics(&self.gauges_storage)
+    }
+
+    fn metrics<T>(metrics_storage: &MetricsStorage<T>) -> Vec<Arc<RwLock<T>>> {
+        metrics_storage.iter()
             .flat_map(|ref_multi| {
                 ref_multi.borrow()
                     .metrics
-                    .clone()
                     .iter()
                     .map(|item| { Arc::clone(item.value()) })
-                    .collect::<Vec<Arc<RwLock<Histogram>>>>()
+                    .collect::<Vec<Arc<RwLock<T>>>>()
             })
-            .collect::<Vec<Arc<RwLock<Histogram>>>>()
+            .collect::<Vec<Arc<RwLock<T>>>>()
     }
 }
 
@@ -233,4 +302,22 @@ mod tests {
             other => panic!("Result from get_or_registry should be Error(MetricAlreadyRegDifferently).\n\nMetric sent: {:#?}\n\n Recorder received: {:#?}", copy_of_sent_metric, other)
         };
     }
+
+    #[test]
+    fn test_remove_metric_expires_its_recorders() {
+        let registry = Registry::new("GlobalMetricRegistry".into());
+        let builder = GaugeBuilder::new("gauge_name".into(), "some description".into()).with_tags("tag_1".into(), "tag_value_1".into());
+        let other_builder = GaugeBuilder::new("gauge_name".into(), "some description".into()).with_tags("tag_1".into(), "tag_value_2".into());
+        let recorder = aw!(registry.get_or_register_gauge(builder.clone())).unwrap();
+        aw!(registry.get_or_register_gauge(other_builder)).unwrap();
+        assert_eq!(registry.gauges().len(), 2);
+
+        aw!(registry.remove(&MetricKind::Gauge, &builder.metric_description().unwrap()));
+        assert_eq!(registry.gauges().len(), 1);
+        assert!(recorder.is_expired());
+
+        let recorder = aw!(registry.get_or_register_gauge(builder)).unwrap();
+        assert!(!recorder.is_expired());
+        assert_eq!(registry.gauges().len(), 2);
+    }
 }
@@ -1,10 +1,12 @@
+use std::collections::HashMap;
 use std::env;
 
-use config::{Config, Environment, File};
+use config::{Config, Environment, File, Value};
 use config::Source;
 
+use crate::collectors::collectors_settings::CollectorsSettings;
 use crate::collectors::hiccups_collector::hiccup_settings::HiccupsMonitorSettings;
-use crate::exporters::prometheus_exporter::prometheus_settings::PrometheusSettings;
+use crate::exporters::prometheus_exporter::prometheus_settings::{BucketLayout, BucketSpec, PrometheusSettings};
 use crate::strum::AsStaticRef;
 
 pub fn load_config() -> Config {
@@ -38,16 +40,91 @@ fn add_default_config(config: &mut Config) {
     config.set_default("debug", false).unwrap();
     let prometheus_settings_default = PrometheusSettings::default();
     let hiccups_monitor_default = HiccupsMonitorSettings::default();
+    let collectors_default = CollectorsSettings::default();
     config.set_default("prometheus_exporter.host", prometheus_settings_default.host).unwrap();
     config.set_default("prometheus_exporter.port", prometheus_settings_default.port as i64).unwrap();
     config.set_default("prometheus_exporter.path", prometheus_settings_default.path).unwrap();
-    config.set_default("prometheus_exporter.metrics.histograms.buckets.default", prometheus_settings_default.metrics.histograms.buckets.default).unwrap();
-    config.set_default("prometheus_exporter.metrics.histograms.buckets.custom_buckets", prometheus_settings_default.metrics.histograms.buckets.custom_buckets).unwrap();
+    config.set_default("prometheus_exporter.metrics.histograms.buckets.default", bucket_layout_value(&prometheus_settings_default.metrics.histograms.buckets.default)).unwrap();
+    let custom_buckets_default: HashMap<String, Value> = prometheus_settings_default.metrics.histograms.buckets.custom_buckets.iter()
+        .map(|(name, layout)| (name.clone(), bucket_layout_value(layout)))
+        .collect();
+    config.set_default("prometheus_exporter.metrics.histograms.buckets.custom_buckets", custom_buckets_default).unwrap();
+    config.set_default("prometheus_exporter.metrics.histograms.summaries", HashMap::<String, Value>::new()).unwrap();
+    config.set_default("prometheus_exporter.metrics.histograms.native", HashMap::<String, Value>::new()).unwrap();
+    config.set_default("prometheus_exporter.compression.enabled", prometheus_settings_default.compression.enabled).unwrap();
+    config.set_default("prometheus_exporter.compression.min_size_bytes", prometheus_settings_default.compression.min_size_bytes as i64).unwrap();
+    config.set_default("prometheus_exporter.expiry.enabled", prometheus_settings_default.expiry.enabled).unwrap();
+    config.set_default("prometheus_exporter.expiry.ttl_millis", prometheus_settings_default.expiry.ttl_millis as i64).unwrap();
+    config.set_default("prometheus_exporter.expiry.stale_marker", prometheus_settings_default.expiry.stale_marker).unwrap();
+    let groups_default: HashMap<String, Value> = prometheus_settings_default.groups.into_iter()
+        .map(|(group, prefixes)| (group, prefixes.into()))
+        .collect();
+    config.set_default("prometheus_exporter.groups", groups_default).unwrap();
+    config.set_default("prometheus_exporter.auth.basic_users", HashMap::<String, Value>::new()).unwrap();
+    config.set_default("prometheus_exporter.tls.enabled", prometheus_settings_default.tls.enabled).unwrap();
+    config.set_default("prometheus_exporter.tls.cert_file", prometheus_settings_default.tls.cert_file).unwrap();
+    config.set_default("prometheus_exporter.tls.key_file", prometheus_settings_default.tls.key_file).unwrap();
     config.set_default("hiccups_monitor.name", hiccups_monitor_default.name).unwrap();
     config.set_default("hiccups_monitor.description", hiccups_monitor_default.description).unwrap();
     config.set_default("hiccups_monitor.resolution_nanos", hiccups_monitor_default.resolution_nanos as i64).unwrap();
+    config.set_default("hiccups_monitor.mode", hiccups_monitor_default.mode.as_static()).unwrap();
+    config.set_default("hiccups_monitor.correction", hiccups_monitor_default.correction.as_static()).unwrap();
     config.set_default("hiccups_monitor.histogram_settings.min", hiccups_monitor_default.histogram_settings.min as i64).unwrap();
     config.set_default("hiccups_monitor.histogram_settings.max", hiccups_monitor_default.histogram_settings.max as i64).unwrap();
     config.set_default("hiccups_monitor.histogram_settings.precision", hiccups_monitor_default.histogram_settings.precision as i64).unwrap();
     config.set_default("hiccups_monitor.histogram_settings.unit", hiccups_monitor_default.histogram_settings.unit.as_static()).unwrap();
+    config.set_default("hiccups_monitor.attribution.enabled", hiccups_monitor_default.attribution.enabled).unwrap();
+    config.set_default("hiccups_monitor.attribution.name", hiccups_monitor_default.attribution.name).unwrap();
+    config.set_default("hiccups_monitor.attribution.description", hiccups_monitor_default.attribution.description).unwrap();
+    config.set_default("hiccups_monitor.attribution.threshold_nanos", hiccups_monitor_default.attribution.threshold_nanos as i64).unwrap();
+    config.set_default("collectors.procfs_path", collectors_default.procfs_path).unwrap();
+    config.set_default("collectors.sysfs_path", collectors_default.sysfs_path).unwrap();
+    config.set_default("collectors.schedstat.enabled", collectors_default.schedstat.enabled).unwrap();
+    config.set_default("collectors.schedstat.interval_millis", collectors_default.schedstat.interval_millis as i64).unwrap();
+    config.set_default("collectors.sockets.enabled", collectors_default.sockets.enabled).unwrap();
+    config.set_default("collectors.sockets.interval_millis", collectors_default.sockets.interval_millis as i64).unwrap();
+    config.set_default("collectors.sockets.ports", collectors_default.sockets.ports.iter().map(|port| *port as i64).collect::<Vec<i64>>()).unwrap();
+    config.set_default("collectors.interrupts.enabled", collectors_default.interrupts.enabled).unwrap();
+    config.set_default("collectors.interrupts.interval_millis", collectors_default.interrupts.interval_millis as i64).unwrap();
+    config.set_default("collectors.interrupts.aggregate_cpus", collectors_default.interrupts.aggregate_cpus).unwrap();
+    config.set_default("collectors.numa.enabled", collectors_default.numa.enabled).unwrap();
+    config.set_default("collectors.numa.interval_millis", collectors_default.numa.interval_millis as i64).unwrap();
+    config.set_default("collectors.hwmon.enabled", collectors_default.hwmon.enabled).unwrap();
+    config.set_default("collectors.hwmon.interval_millis", collectors_default.hwmon.interval_millis as i64).unwrap();
+    config.set_default("collectors.cpufreq.enabled", collectors_default.cpufreq.enabled).unwrap();
+    config.set_default("collectors.cpufreq.interval_millis", collectors_default.cpufreq.interval_millis as i64).unwrap();
+    config.set_default("collectors.timex.enabled", collectors_default.timex.enabled).unwrap();
+    config.set_default("collectors.timex.interval_millis", collectors_default.timex.interval_millis as i64).unwrap();
+    config.set_default("collectors.textfile.enabled", collectors_default.textfile.enabled).unwrap();
+    config.set_default("collectors.textfile.interval_millis", collectors_default.textfile.interval_millis as i64).unwrap();
+    config.set_default("collectors.textfile.directory", collectors_default.textfile.directory).unwrap();
+    config.set_default("collectors.exec", Vec::<Value>::new()).unwrap();
+    config.set_default("collectors.log_tail", Vec::<Value>::new()).unwrap();
+    config.set_default("collectors.http_probe", Vec::<Value>::new()).unwrap();
+    config.set_default("collectors.tcp_probe", Vec::<Value>::new()).unwrap();
+    config.set_default("collectors.dns_probe", Vec::<Value>::new()).unwrap();
+}
+
+fn bucket_layout_value(layout: &BucketLayout) -> Value {
+    let mut spec = HashMap::<String, Value>::new();
+    match layout {
+        BucketLayout::Values(values) => return values.clone().into(),
+        BucketLayout::Spec(BucketSpec::Exponential { start, factor, count }) => {
+            spec.insert("type".into(), "exponential".into());
+            spec.insert("start".into(), (*start).into());
+            spec.insert("factor".into(), (*factor).into());
+            spec.insert("count".into(), (*count as i64).into());
+        },
+        BucketLayout::Spec(BucketSpec::Linear { start, width, count }) => {
+            spec.insert("type".into(), "linear".into());
+            spec.insert("start".into(), (*start).into());
+            spec.insert("width".into(), (*width).into());
+            spec.insert("count".into(), (*count as i64).into());
+        },
+        BucketLayout::Spec(BucketSpec::Hdr { max_count }) => {
+            spec.insert("type".into(), "hdr".into());
+            spec.insert("max_count".into(), (*max_count as i64).into());
+        },
+    }
+    spec.into()
 }
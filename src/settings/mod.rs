@@ -1,4 +1,6 @@
+use crate::collectors::collectors_settings::CollectorsSettings;
 use crate::collectors::hiccups_collector::hiccup_settings::HiccupsMonitorSettings;
+use crate::errors::Result;
 use crate::exporters::prometheus_exporter::prometheus_settings::PrometheusSettings;
 use crate::metrics::measurement_unit::MEASUREMENT_UNITS;
 use crate::metrics::measurement_unit::MeasurementUnit;
@@ -41,13 +43,17 @@ pub struct Settings {
     pub debug: bool,
     pub prometheus_exporter: PrometheusSettings,
     pub hiccups_monitor: HiccupsMonitorSettings,
+    pub collectors: CollectorsSettings,
 }
 
 impl Settings {
-    pub fn load() -> Self {
+    /// Loads the settings, failing when they are invalid rather than starting without what they configure.
+    pub fn load() -> Result<Self> {
         let s = config_loader::load_config();
-        let settings = s.try_into().unwrap();
+        let settings: Settings = s.try_into().unwrap();
         info!("Settings: {:?}", settings);
-        settings
+        settings.prometheus_exporter.validate()?;
+        settings.collectors.validate()?;
+        Ok(settings)
     }
 }
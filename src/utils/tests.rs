@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use float_cmp::{ApproxEq, F64Margin};
 
 pub trait ApproxComparison {
@@ -13,3 +15,8 @@ impl ApproxComparison for f64 {
         is_eq
     }
 }
+
+/// The path of a file or directory of the sysfs fixtures, laid out like they are under `/sys`.
+pub fn sysfs_fixture(path: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/sys").join(path)
+}
@@ -13,3 +13,23 @@ pub fn current_millis() -> u64 {
     since_the_epoch.as_secs() * 1000 +
         since_the_epoch.subsec_nanos() as u64 / 1_000_000
 }
+
+/// Reads the monotonic clock (`CLOCK_MONOTONIC`) in nanoseconds.
+///
+/// It's the same clock used by `std::time::Instant` on Linux, but expressed as
+/// an absolute value so it can be used for absolute deadlines.
+pub fn monotonic_nanos() -> u64 {
+    clock_nanos(libc::CLOCK_MONOTONIC)
+}
+
+/// Reads the CPU time consumed by the calling thread (`CLOCK_THREAD_CPUTIME_ID`) in nanoseconds.
+pub fn thread_cpu_nanos() -> u64 {
+    clock_nanos(libc::CLOCK_THREAD_CPUTIME_ID)
+}
+
+fn clock_nanos(clock_id: libc::clockid_t) -> u64 {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // clock_gettime can only fail with an invalid clock id or an invalid pointer
+    unsafe { libc::clock_gettime(clock_id, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}